@@ -0,0 +1,3436 @@
+//! Ames networking driver.
+//!
+//! This module implements the Ames IO driver, which sends and receives the UDP packets of an
+//! [Arvo] kernel's [Ames] networking vane. Each request to the driver arrives as a length-encoded
+//! jammed (i.e. serialized) noun from some input source--`stdin`, a socket, etc. The driver
+//! understands six types of requests:
+//! - bind the driver's socket to a port (`%bind`),
+//! - send a packet (`%send`),
+//! - discover the socket's public address with [STUN] (`%stun`),
+//! - advertise the ship and discover other ships on the local network (`%discover`),
+//! - replace the list of blocked networks (`%block`), and
+//! - report statistics about the packets sent and received (`%stats`).
+//!
+//! Packets are sent and received over IPv4. A packet's source or destination is a lane of the
+//! form:
+//! ```text
+//! [<ip> <port>]
+//! ```
+//! where `<ip>` is the IPv4 address as a 32-bit atom (i.e. an `@if`), and `<port>` is the UDP port.
+//!
+//! ### `%bind`
+//!
+//! A jammed noun representing a `%bind` request has the following structure:
+//! ```text
+//! [%bind <port>]
+//! ```
+//! where `<port>` is the port to bind the driver's socket to, or 0 for a port chosen by the
+//! operating system. If the socket is already bound to `<port>`, it's left as is. Otherwise, the
+//! driver binds a new socket to `<port>` and closes the previous socket, so that packets received
+//! on the old port are no longer delivered. `%bind` requests generate responses of the form:
+//! ```text
+//! [%bound <port>]
+//! ```
+//! where `<port>` is the port the socket is bound to, or, if binding the socket failed, of the
+//! form:
+//! ```text
+//! [%bind-error <port> <message>]
+//! ```
+//! where `<port>` is the port of the request, and `<message>` describes the failure, in which
+//! case the previous socket, if any, remains bound.
+//!
+//! The driver binds its socket to the port of `URBIT_IO_DRIVERS_AMES_PORT` when it starts, and
+//! generates a `%bound` or `%bind-error` response for that port as if it had received a `%bind`
+//! request.
+//!
+//! ### `%send`
+//!
+//! A jammed noun representing a `%send` request has the following structure:
+//! ```text
+//! [%send <lane> <len> <packet>]
+//! ```
+//! where `<lane>` is the destination of the packet, and `<len>` is the length of `<packet>` in
+//! bytes, which ensures trailing null bytes are retained. `%send` requests don't generate
+//! responses. A packet that can't be sent, e.g. because no socket is bound, is dropped, as UDP
+//! packets may be.
+//!
+//! If `URBIT_IO_DRIVERS_AMES_PACING` sets a rate, packets are queued rather than sent right away,
+//! and sent no faster than that rate, so that a large transfer doesn't saturate the uplink of the
+//! ship's network. Each destination has its own queue, and the queues take turns sending a packet,
+//! so a large transfer to one peer doesn't hold up packets to others. A packet to a destination
+//! whose queue is full is dropped.
+//!
+//! ### `%hear`
+//!
+//! Each packet received by the driver's socket generates a response of the form:
+//! ```text
+//! [%hear <lane> <len> <packet>]
+//! ```
+//! where `<lane>` is the source of the packet, and `<len>` is the length of `<packet>` in bytes.
+//! STUN binding requests received by the socket are answered by the driver with the address they
+//! were sent from, and STUN responses are consumed by the driver, so neither generates a `%hear`
+//! response.
+//!
+//! Before anything else is done with a packet, the driver drops it if:
+//! - it's malformed, i.e. shorter than an Ames packet header, or from a source that can't be
+//!   replied to, such as port 0 or a broadcast or multicast address.
+//! - its source is in a blocked network (see `%block`).
+//! - its source address has exceeded its rate limit (see [Configuration]). The limit applies to
+//!   each address rather than each lane, so that a flood can't evade it by changing ports.
+//!
+//! A dropped packet isn't reported, but is counted in the statistics reported by `%stats`.
+//!
+//! ### `%stun`
+//!
+//! A jammed noun representing a `%stun` request has one of the following structures:
+//! ```text
+//! [%stun %start <lane>]
+//! [%stun %stop ~]
+//! ```
+//! `%start` makes the driver send STUN binding requests over its socket to the STUN server at
+//! `<lane>` (e.g. the ship's sponsor), replacing the STUN server of any previous `%start`, and
+//! `%stop` stops sending them. A binding request is sent right away and then every
+//! `URBIT_IO_DRIVERS_AMES_STUN_INTERVAL` seconds, which keeps the NAT mapping of the socket's port
+//! alive, and is retransmitted a few times if the server doesn't respond. Rebinding the socket
+//! starts over with a binding request over the new socket. `%stun` requests don't generate
+//! responses. Instead, whenever a binding request succeeds with a public address different from
+//! that of the previous binding request, including the first successful binding request, which
+//! is how a change of the NAT's mapping of the socket's port is detected, the driver generates a
+//! response of the form:
+//! ```text
+//! [%stun %once <lane>]
+//! ```
+//! where `<lane>` is the socket's public address. When a binding request fails after succeeding
+//! (or on the first attempt), the driver generates a response of the form:
+//! ```text
+//! [%stun %fail ~]
+//! ```
+//! after which the next successful binding request generates a `%once` response.
+//!
+//! ### `%discover`
+//!
+//! A jammed noun representing a `%discover` request has one of the following structures:
+//! ```text
+//! [%discover %start <ship>]
+//! [%discover %stop ~]
+//! ```
+//! If `URBIT_IO_DRIVERS_AMES_DISCOVERY` enables discovery (see [Configuration]), `%start` makes
+//! the driver advertise `<ship>` (i.e. an `@p`) at the socket's port on the local network and
+//! look for other ships advertised there, with [mDNS] and [DNS-SD], replacing the ship of any
+//! previous `%start`, and `%stop` stops both. Otherwise, `%discover` requests are ignored. A ship
+//! is advertised as an instance of the `_urbit-ames._udp` service named after the ship's number
+//! in hexadecimal, e.g. `0._urbit-ames._udp.local` for `~zod`, whose `SRV` record has the
+//! socket's port. The driver asks for instances of the service when it starts advertising and
+//! every `URBIT_IO_DRIVERS_AMES_DISCOVERY_INTERVAL` seconds after that, and answers other hosts'
+//! requests for them. Rebinding the socket starts over with the new port. `%discover` requests
+//! don't generate responses. Instead, whenever another ship is found on the local network, or
+//! found at a different lane than before, the driver generates a response of the form:
+//! ```text
+//! [%discover %found <ship> <lane>]
+//! ```
+//! where `<lane>` is the address of the host that advertised `<ship>` and the port of its
+//! advertisement, which is a candidate lane for reaching `<ship>` without leaving the local
+//! network. If the driver can't listen for mDNS messages, it generates a response of the form:
+//! ```text
+//! [%discover %fail <message>]
+//! ```
+//! where `<message>` describes the failure.
+//!
+//! ### `%block`
+//!
+//! A jammed noun representing a `%block` request has the following structure:
+//! ```text
+//! [%block <networks>]
+//! ```
+//! where `<networks>` is a null-terminated list of networks of the form `[<ip> <prefix_len>]`, such
+//! as `[.10.0.0.0 8]`, from which packets are dropped. The list replaces the driver's list of
+//! blocked networks, including those of `URBIT_IO_DRIVERS_AMES_BLOCK`, so an empty list unblocks
+//! every network. `%block` requests don't generate responses.
+//!
+//! ### `%stats`
+//!
+//! A jammed noun representing a `%stats` request has the following structure:
+//! ```text
+//! [%stats ~]
+//! ```
+//! `%stats` requests generate responses of the form:
+//! ```text
+//! [%stats <received> <malformed> <blocked> <rate_limited> <sent> <unsent> <queued>]
+//! ```
+//! where each but the last is the number of packets since the driver started that were received,
+//! dropped because they were malformed, dropped because their source was blocked, dropped because
+//! their source exceeded its rate limit, sent, and dropped because they couldn't be sent, and
+//! `<queued>` is the number of packets currently waiting to be sent at the pacing rate.
+//!
+//! ### `%port-mapping`
+//!
+//! If `URBIT_IO_DRIVERS_AMES_PORT_MAPPING` enables port mapping, the driver asks the gateway of the
+//! local network to forward the socket's port to the socket, with [NAT-PMP] or [UPnP], whenever it
+//! binds the socket, so that the ship can be reached from outside of the local network. A mapping
+//! is requested with a lifetime of an hour and renewed every half hour, or requested again every
+//! minute while the gateway refuses it. When a mapping is requested for a new socket, or the
+//! public address of the mapping changes, the driver generates a response of the form:
+//! ```text
+//! [%port-mapping %done <method> <lane>]
+//! ```
+//! where `<method>` is `%nat-pmp` or `%upnp`, and `<lane>` is the public address that's forwarded
+//! to the socket. When requesting a mapping fails after succeeding (or on the first attempt), the
+//! driver generates a response of the form:
+//! ```text
+//! [%port-mapping %fail <message>]
+//! ```
+//! where `<message>` describes the failure. A mapping isn't removed when the socket is rebound or
+//! the driver exits, and instead expires at the end of its lifetime.
+//!
+//! ### Configuration
+//!
+//! The driver is configured by environment variables, which are read when the driver is
+//! initialized:
+//! - `URBIT_IO_DRIVERS_AMES_PORT`: the port to bind the driver's socket to when it starts. Defaults
+//!   to 0, in which case the operating system chooses a port.
+//! - `URBIT_IO_DRIVERS_AMES_LOCAL`: whether to bind the driver's socket to the loopback interface
+//!   only, as `true` or `false` (the default), which keeps a ship from being reachable from other
+//!   machines.
+//! - `URBIT_IO_DRIVERS_AMES_STUN_INTERVAL`: how many seconds to wait between STUN binding
+//!   requests. Defaults to 25 seconds, which is shorter than the time most NATs keep an idle UDP
+//!   mapping.
+//! - `URBIT_IO_DRIVERS_AMES_PORT_MAPPING`: how to ask the gateway of the local network to forward
+//!   the socket's port, one of `none` (the default), `nat-pmp`, `upnp`, or `auto` to try NAT-PMP
+//!   and fall back to UPnP. Ignored if `URBIT_IO_DRIVERS_AMES_LOCAL` is `true`.
+//! - `URBIT_IO_DRIVERS_AMES_GATEWAY`: the IPv4 address of the gateway to send NAT-PMP requests to.
+//!   Defaults to the gateway of the default route on Linux, and must be set to use NAT-PMP
+//!   elsewhere. UPnP gateways are discovered on the local network instead.
+//! - `URBIT_IO_DRIVERS_AMES_DISCOVERY`: whether `%discover` requests may advertise the ship and
+//!   discover other ships on the local network, as `true` or `false` (the default). Ignored if
+//!   `URBIT_IO_DRIVERS_AMES_LOCAL` is `true`.
+//! - `URBIT_IO_DRIVERS_AMES_DISCOVERY_INTERVAL`: how many seconds to wait between requests for
+//!   ships on the local network. Defaults to 60 seconds.
+//! - `URBIT_IO_DRIVERS_AMES_RATE_LIMIT`: how many packets to accept from each source address, as a
+//!   comma-separated list of options:
+//!   - `per-source=<n>` or `per-source=none`: accept at most `<n>` packets per second from each
+//!     source address, or any number of packets (the default).
+//!   - `burst=<n>`: accept up to `<n>` packets at once from a source address that has been quiet.
+//!     Defaults to the `per-source` rate, i.e. a second's worth of packets.
+//! - `URBIT_IO_DRIVERS_AMES_BLOCK`: the networks to drop packets from, as a comma-separated list of
+//!   IPv4 addresses and networks of the form `<ip>/<prefix-len>`, e.g. `10.0.0.0/8`. Unset by
+//!   default.
+//! - `URBIT_IO_DRIVERS_AMES_PACING`: how fast to send packets, as a comma-separated list of
+//!   options:
+//!   - `rate=<n>` or `rate=none`: send at most `<n>` packets per second, or send each packet as
+//!     soon as it's requested (the default).
+//!   - `burst=<n>`: send up to `<n>` packets at once after sending nothing for a while. Defaults to
+//!     the rate, i.e. a second's worth of packets.
+//!   - `queue=<n>`: queue at most `<n>` packets for each destination. Defaults to 1000.
+//!
+//! [Configuration]: #configuration
+//! [Ames]: https://developers.urbit.org/reference/arvo/ames/ames
+//! [Arvo]: https://developers.urbit.org/reference/glossary/arvo
+//! [DNS-SD]: https://www.rfc-editor.org/rfc/rfc6763
+//! [mDNS]: https://www.rfc-editor.org/rfc/rfc6762
+//! [NAT-PMP]: https://www.rfc-editor.org/rfc/rfc6886
+//! [STUN]: https://www.rfc-editor.org/rfc/rfc5389
+//! [UPnP]: https://en.wikipedia.org/wiki/Internet_Gateway_Device_Protocol
+
+use crate::{atom_as_str, env_var, Driver, Status};
+use log::{debug, info, warn};
+use noun::{atom::Atom, cell::Cell, convert, Noun};
+use std::{
+    collections::{hash_map::RandomState, HashMap, VecDeque},
+    hash::BuildHasher,
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, Stdin, Stdout},
+    net::{TcpStream, UdpSocket},
+    sync::{
+        mpsc::{Receiver, Sender},
+        oneshot, Notify,
+    },
+    task::JoinHandle,
+    time,
+};
+
+//==================================================================================================
+// Request Types
+//==================================================================================================
+
+/// Requests that can be handled by the Ames driver.
+enum Request {
+    /// A request to bind the driver's socket to a port.
+    Bind(Bind),
+
+    /// A request to send a packet.
+    SendPacket(SendPacket),
+
+    /// A request to start or stop discovering the socket's public address.
+    Stun(Stun),
+
+    /// A request to start or stop discovering ships on the local network.
+    Discover(Discover),
+
+    /// A request to replace the list of blocked networks.
+    Block(Block),
+
+    /// A request to report statistics about the packets sent and received.
+    GetStats(GetStats),
+}
+
+impl_try_from_noun_for_request!(
+    Request,
+    "bind" => Bind,
+    "block" => Block,
+    "discover" => Discover,
+    "send" => SendPacket,
+    "stats" => GetStats,
+    "stun" => Stun,
+);
+
+/// A request to bind the driver's socket to a port.
+struct Bind {
+    /// The port to bind to, or 0 for a port chosen by the operating system.
+    port: u16,
+}
+
+impl TryFrom<&Noun> for Bind {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// <port>
+    /// ```
+    ///
+    /// where `<port>` is the port to bind to.
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        if let Noun::Atom(port) = data {
+            Ok(Self {
+                port: port_from_atom(port)?,
+            })
+        } else {
+            Err(convert::Error::UnexpectedCell)
+        }
+    }
+}
+
+/// A request to send a packet.
+struct SendPacket {
+    /// The destination of the packet.
+    lane: Lane,
+
+    /// The packet.
+    packet: Vec<u8>,
+}
+
+impl TryFrom<&Noun> for SendPacket {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// [<lane> <len> <packet>]
+    /// ```
+    ///
+    /// where `<lane>` is the destination of the packet (see [`Lane`]), and `<len>` is the length
+    /// of `<packet>` in bytes.
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        if let Noun::Cell(data) = data {
+            let [lane, len, packet] = data.to_array::<3>().ok_or(convert::Error::MissingValue)?;
+            Ok(Self {
+                lane: Lane::try_from(&*lane)?,
+                packet: packet_from_noun(&len, &packet)?,
+            })
+        } else {
+            Err(convert::Error::UnexpectedAtom)
+        }
+    }
+}
+
+/// A request to start or stop discovering the socket's public address.
+enum Stun {
+    /// Send binding requests to the STUN server at a lane.
+    Start(Lane),
+
+    /// Stop sending binding requests.
+    Stop,
+}
+
+impl TryFrom<&Noun> for Stun {
+    type Error = convert::Error;
+
+    /// A properly structured noun is one of:
+    ///
+    /// ```text
+    /// [%start <lane>]
+    /// [%stop ~]
+    /// ```
+    ///
+    /// where `<lane>` is the lane of the STUN server (see [`Lane`]).
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        if let Noun::Cell(data) = data {
+            if let Noun::Atom(tag) = data.head_ref() {
+                match atom_as_str(tag)? {
+                    "start" => Ok(Self::Start(Lane::try_from(data.tail_ref())?)),
+                    "stop" => match data.tail_ref() {
+                        Noun::Atom(null) if null.is_null() => Ok(Self::Stop),
+                        _ => Err(convert::Error::ExpectedNull),
+                    },
+                    _ => Err(convert::Error::ImplType),
+                }
+            } else {
+                Err(convert::Error::UnexpectedCell)
+            }
+        } else {
+            Err(convert::Error::UnexpectedAtom)
+        }
+    }
+}
+
+/// A request to start or stop discovering ships on the local network.
+#[derive(Debug, Eq, PartialEq)]
+enum Discover {
+    /// Advertise a ship and discover other ships.
+    Start(u128),
+
+    /// Stop advertising the ship and discovering other ships.
+    Stop,
+}
+
+impl TryFrom<&Noun> for Discover {
+    type Error = convert::Error;
+
+    /// A properly structured noun is one of:
+    ///
+    /// ```text
+    /// [%start <ship>]
+    /// [%stop ~]
+    /// ```
+    ///
+    /// where `<ship>` is the ship to advertise.
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        if let Noun::Cell(data) = data {
+            if let Noun::Atom(tag) = data.head_ref() {
+                match (atom_as_str(tag)?, data.tail_ref()) {
+                    ("start", Noun::Atom(ship)) => Ok(Self::Start(ship_from_atom(ship)?)),
+                    ("start", Noun::Cell(_)) => Err(convert::Error::UnexpectedCell),
+                    ("stop", Noun::Atom(null)) if null.is_null() => Ok(Self::Stop),
+                    ("stop", _) => Err(convert::Error::ExpectedNull),
+                    _ => Err(convert::Error::ImplType),
+                }
+            } else {
+                Err(convert::Error::UnexpectedCell)
+            }
+        } else {
+            Err(convert::Error::UnexpectedAtom)
+        }
+    }
+}
+
+/// A request to replace the list of blocked networks.
+struct Block {
+    /// The networks to drop packets from.
+    networks: Vec<Network>,
+}
+
+impl TryFrom<&Noun> for Block {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// <networks>
+    /// ```
+    ///
+    /// where `<networks>` is a null-terminated list of [`Network`]s.
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        let mut networks = Vec::new();
+        let mut list = data;
+        loop {
+            match list {
+                Noun::Atom(null) if null.is_null() => return Ok(Self { networks }),
+                Noun::Atom(_) => return Err(convert::Error::ExpectedNull),
+                Noun::Cell(cell) => {
+                    networks.push(Network::try_from(cell.head_ref())?);
+                    list = cell.tail_ref();
+                }
+            }
+        }
+    }
+}
+
+/// A request to report statistics about the packets sent and received.
+struct GetStats;
+
+impl TryFrom<&Noun> for GetStats {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// ~
+    /// ```
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        match data {
+            Noun::Atom(data) if data.is_null() => Ok(Self),
+            Noun::Atom(_) => Err(convert::Error::ExpectedNull),
+            Noun::Cell(_) => Err(convert::Error::UnexpectedCell),
+        }
+    }
+}
+
+//==================================================================================================
+// Driver
+//==================================================================================================
+
+/// Configuration of the Ames driver.
+///
+/// Each option is read from an environment variable when the driver is initialized.
+#[derive(Clone)]
+struct Config {
+    /// The port to bind the driver's socket to when the driver starts, or 0 for a port chosen by
+    /// the operating system.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_AMES_PORT`.
+    port: u16,
+
+    /// Whether the driver's socket is bound to the loopback interface only.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_AMES_LOCAL` as `true` or `false`.
+    local: bool,
+
+    /// How long to wait between STUN binding requests.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_AMES_STUN_INTERVAL` as a number of seconds.
+    stun_interval: Duration,
+
+    /// How the gateway of the local network is asked to forward the socket's port.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_AMES_PORT_MAPPING`.
+    port_mapping: PortMapping,
+
+    /// Whether ships may be advertised and discovered on the local network.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_AMES_DISCOVERY` as `true` or `false`.
+    discovery: bool,
+
+    /// How long to wait between requests for ships on the local network.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_AMES_DISCOVERY_INTERVAL` as a number of seconds.
+    discovery_interval: Duration,
+
+    /// The gateway to send NAT-PMP requests to, if not the gateway of the default route.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_AMES_GATEWAY`.
+    gateway: Option<Ipv4Addr>,
+
+    /// How many packets are accepted from each source address.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_AMES_RATE_LIMIT`. Defaults to [`RateLimit::default()`].
+    rate_limit: RateLimit,
+
+    /// The networks packets are dropped from until a [`Block`] request replaces them.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_AMES_BLOCK`.
+    blocked: Networks,
+
+    /// How fast packets are sent.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_AMES_PACING`. Defaults to [`Pacing::default()`].
+    pacing: Pacing,
+}
+
+impl Config {
+    /// Reads the configuration from the environment.
+    fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            port: env_var("URBIT_IO_DRIVERS_AMES_PORT").unwrap_or(default.port),
+            local: env_var("URBIT_IO_DRIVERS_AMES_LOCAL").unwrap_or(default.local),
+            stun_interval: env_var("URBIT_IO_DRIVERS_AMES_STUN_INTERVAL")
+                .map(Duration::from_secs)
+                .unwrap_or(default.stun_interval),
+            port_mapping: env_var("URBIT_IO_DRIVERS_AMES_PORT_MAPPING")
+                .unwrap_or(default.port_mapping),
+            discovery: env_var("URBIT_IO_DRIVERS_AMES_DISCOVERY").unwrap_or(default.discovery),
+            discovery_interval: env_var("URBIT_IO_DRIVERS_AMES_DISCOVERY_INTERVAL")
+                .map(Duration::from_secs)
+                .unwrap_or(default.discovery_interval),
+            gateway: env_var("URBIT_IO_DRIVERS_AMES_GATEWAY"),
+            rate_limit: env_var("URBIT_IO_DRIVERS_AMES_RATE_LIMIT").unwrap_or_default(),
+            blocked: env_var("URBIT_IO_DRIVERS_AMES_BLOCK").unwrap_or_default(),
+            pacing: env_var("URBIT_IO_DRIVERS_AMES_PACING").unwrap_or_default(),
+        }
+    }
+
+    /// Returns the address to bind the driver's socket to for `port`.
+    fn bind_addr(&self, port: u16) -> SocketAddrV4 {
+        let ip = if self.local {
+            Ipv4Addr::LOCALHOST
+        } else {
+            Ipv4Addr::UNSPECIFIED
+        };
+        SocketAddrV4::new(ip, port)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            port: 0,
+            local: false,
+            stun_interval: Duration::from_secs(25),
+            port_mapping: PortMapping::None,
+            discovery: false,
+            discovery_interval: Duration::from_secs(60),
+            gateway: None,
+            rate_limit: RateLimit::default(),
+            blocked: Networks::default(),
+            pacing: Pacing::default(),
+        }
+    }
+}
+
+/// How many packets are accepted from each source address.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+struct RateLimit {
+    /// The number of packets per second, or `None` for any number of packets.
+    per_source: Option<u32>,
+
+    /// The number of packets that may arrive at once, or `None` for the rate.
+    burst: Option<u32>,
+}
+
+impl FromStr for RateLimit {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut limit = Self::default();
+        for opt in s.split(',').map(str::trim).filter(|opt| !opt.is_empty()) {
+            let (key, val) = opt.split_once('=').ok_or(())?;
+            match key {
+                "per-source" => {
+                    limit.per_source = match val {
+                        "none" => None,
+                        val => match val.parse().map_err(|_| ())? {
+                            // No packets could ever be received.
+                            0 => return Err(()),
+                            per_source => Some(per_source),
+                        },
+                    }
+                }
+                "burst" => {
+                    limit.burst = match val.parse().map_err(|_| ())? {
+                        // No packets could ever be received.
+                        0 => return Err(()),
+                        burst => Some(burst),
+                    }
+                }
+                _ => return Err(()),
+            }
+        }
+        Ok(limit)
+    }
+}
+
+/// How fast packets are sent.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Pacing {
+    /// The number of packets per second, or `None` to send packets as soon as they're requested.
+    rate: Option<u32>,
+
+    /// The number of packets that may be sent at once, or `None` for the rate.
+    burst: Option<u32>,
+
+    /// The number of packets that may be queued for each destination.
+    max_queue_len: usize,
+}
+
+impl Default for Pacing {
+    fn default() -> Self {
+        Self {
+            rate: None,
+            burst: None,
+            max_queue_len: 1000,
+        }
+    }
+}
+
+impl FromStr for Pacing {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pacing = Self::default();
+        for opt in s.split(',').map(str::trim).filter(|opt| !opt.is_empty()) {
+            let (key, val) = opt.split_once('=').ok_or(())?;
+            match key {
+                "rate" => {
+                    pacing.rate = match val {
+                        "none" => None,
+                        val => match val.parse().map_err(|_| ())? {
+                            // No packets could ever be sent.
+                            0 => return Err(()),
+                            rate => Some(rate),
+                        },
+                    }
+                }
+                "burst" => {
+                    pacing.burst = match val.parse().map_err(|_| ())? {
+                        // No packets could ever be sent.
+                        0 => return Err(()),
+                        burst => Some(burst),
+                    }
+                }
+                "queue" => {
+                    pacing.max_queue_len = match val.parse().map_err(|_| ())? {
+                        // No packets could ever be sent.
+                        0 => return Err(()),
+                        len => len,
+                    }
+                }
+                _ => return Err(()),
+            }
+        }
+        Ok(pacing)
+    }
+}
+
+/// How the gateway of the local network is asked to forward the socket's port.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum PortMapping {
+    /// The port isn't forwarded.
+    None,
+
+    /// NAT-PMP, falling back to UPnP if the gateway doesn't respond to NAT-PMP.
+    Auto,
+
+    /// NAT-PMP.
+    NatPmp,
+
+    /// UPnP.
+    Upnp,
+}
+
+impl PortMapping {
+    /// Returns the name of the method of a successful mapping.
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Auto => "auto",
+            Self::NatPmp => "nat-pmp",
+            Self::Upnp => "upnp",
+        }
+    }
+}
+
+impl FromStr for PortMapping {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "auto" => Ok(Self::Auto),
+            "nat-pmp" => Ok(Self::NatPmp),
+            "upnp" => Ok(Self::Upnp),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A bound UDP socket and the task that receives packets from it.
+struct Socket {
+    /// The socket.
+    udp: Arc<UdpSocket>,
+
+    /// The port the socket is bound to.
+    port: u16,
+
+    /// The task that forwards the packets received by the socket to the output task.
+    recv_task: JoinHandle<()>,
+
+    /// The task that discovers the socket's public address, if there's a STUN server.
+    stun_task: Option<JoinHandle<()>>,
+
+    /// The task that keeps the socket's port forwarded, if port mapping is enabled.
+    mapping_task: Option<JoinHandle<()>>,
+
+    /// The task that advertises the ship and discovers other ships on the local network, if
+    /// there's a ship to advertise.
+    discovery_task: Option<JoinHandle<()>>,
+
+    /// The task that sends queued packets over the socket, if packets are paced.
+    pacing_task: Option<JoinHandle<()>>,
+}
+
+impl Drop for Socket {
+    /// Stops receiving packets, discovering the socket's public address, renewing the port
+    /// mapping, discovering ships on the local network, and sending queued packets, which closes
+    /// the socket once no packets are being sent over it.
+    fn drop(&mut self) {
+        self.recv_task.abort();
+        let tasks = [
+            &self.stun_task,
+            &self.mapping_task,
+            &self.discovery_task,
+            &self.pacing_task,
+        ];
+        for task in tasks.into_iter().flatten() {
+            task.abort();
+        }
+    }
+}
+
+/// The Ames driver.
+pub struct Ames {
+    /// The socket packets are sent and received over, if one is bound.
+    socket: Option<Socket>,
+
+    /// The STUN server the socket's public address is discovered with, if any.
+    stun_server: Option<Lane>,
+
+    /// The STUN binding requests awaiting responses.
+    stun_transactions: Arc<StunTransactions>,
+
+    /// The ship advertised on the local network, if any.
+    discovery_ship: Option<u128>,
+
+    /// The filter that received packets pass through.
+    filter: Arc<PacketFilter>,
+
+    /// The queues that packets wait in to be sent, if packets are paced.
+    pacer: Option<Arc<Pacer>>,
+
+    /// The driver configuration.
+    config: Config,
+}
+
+impl Ames {
+    /// The size of the largest UDP packet that can be sent over IPv4.
+    const MAX_PACKET_LEN: usize = 65507;
+
+    /// How long to wait for a response to the first transmission of a STUN binding request, which
+    /// doubles with each retransmission.
+    const STUN_RTO: Duration = Duration::from_millis(500);
+
+    /// The number of times a STUN binding request is transmitted before it fails.
+    const STUN_TRANSMISSIONS: usize = 3;
+
+    /// The lifetime of a port mapping, which is renewed halfway through.
+    const MAPPING_LIFETIME: Duration = Duration::from_secs(3600);
+
+    /// How long to wait before requesting a port mapping again after failing to.
+    const MAPPING_RETRY: Duration = Duration::from_secs(60);
+
+    /// Initializes a driver with `config`.
+    fn with_config(config: Config) -> Self {
+        Self {
+            socket: None,
+            stun_server: None,
+            stun_transactions: Arc::new(StunTransactions::default()),
+            discovery_ship: None,
+            filter: Arc::new(PacketFilter::new(&config)),
+            pacer: config
+                .pacing
+                .rate
+                .map(|rate| Arc::new(Pacer::new(rate, &config.pacing))),
+            config,
+        }
+    }
+
+    /// Handles a [`Bind`] request.
+    async fn bind(&mut self, req: Bind, output_tx: &Sender<Noun>) -> Noun {
+        if let Some(socket) = &self.socket {
+            if req.port != 0 && req.port == socket.port {
+                debug!(
+                    target: Self::name(),
+                    "socket is already bound to port {}", req.port
+                );
+                return bound(socket.port);
+            }
+        }
+        match self.bind_socket(req.port, output_tx).await {
+            Ok(socket) => {
+                let port = socket.port;
+                if let Some(prev) = self.socket.replace(socket) {
+                    info!(
+                        target: Self::name(),
+                        "rebound socket from port {} to port {}", prev.port, port
+                    );
+                } else {
+                    info!(target: Self::name(), "bound socket to port {}", port);
+                }
+                self.start_stun(output_tx);
+                self.start_discovery(output_tx);
+                bound(port)
+            }
+            Err(err) => {
+                warn!(
+                    target: Self::name(),
+                    "failed to bind socket to port {}: {}", req.port, err
+                );
+                Noun::from(Cell::from([
+                    Atom::from("bind-error"),
+                    Atom::from(u64::from(req.port)),
+                    Atom::from(err.to_string().as_str()),
+                ]))
+            }
+        }
+    }
+
+    /// Binds a socket to `port` and spawns a task that forwards the packets it receives to the
+    /// output task.
+    async fn bind_socket(&self, port: u16, output_tx: &Sender<Noun>) -> io::Result<Socket> {
+        let udp = Arc::new(UdpSocket::bind(self.config.bind_addr(port)).await?);
+        let port = udp.local_addr()?.port();
+        let recv_task = tokio::spawn(Self::recv_packets(
+            udp.clone(),
+            self.filter.clone(),
+            self.stun_transactions.clone(),
+            output_tx.clone(),
+        ));
+        let mapping_task = match self.config.port_mapping {
+            PortMapping::None => None,
+            _ if self.config.local => None,
+            method => Some(tokio::spawn(Self::map_port(
+                port,
+                method,
+                self.config.gateway,
+                output_tx.clone(),
+            ))),
+        };
+        let pacing_task = self.pacer.as_ref().map(|pacer| {
+            tokio::spawn(Self::pace_packets(
+                udp.clone(),
+                pacer.clone(),
+                self.filter.stats.clone(),
+            ))
+        });
+        Ok(Socket {
+            udp,
+            port,
+            recv_task,
+            stun_task: None,
+            mapping_task,
+            discovery_task: None,
+            pacing_task,
+        })
+    }
+
+    /// Forwards the packets received by `udp` that pass through `filter` to the output task until
+    /// the output task stops accepting responses.
+    ///
+    /// STUN binding requests are answered, and STUN responses are handed to the binding requests
+    /// in `transactions` that they respond to, rather than forwarded.
+    async fn recv_packets(
+        udp: Arc<UdpSocket>,
+        filter: Arc<PacketFilter>,
+        transactions: Arc<StunTransactions>,
+        output_tx: Sender<Noun>,
+    ) {
+        let mut buf = vec![0; Self::MAX_PACKET_LEN];
+        loop {
+            let (len, addr) = match udp.recv_from(&mut buf).await {
+                Ok((len, SocketAddr::V4(addr))) => (len, addr),
+                Ok((_len, SocketAddr::V6(addr))) => {
+                    debug!(
+                        target: Self::name(),
+                        "ignoring packet from IPv6 address {}", addr
+                    );
+                    continue;
+                }
+                Err(err) => {
+                    // Some platforms report ICMP errors caused by earlier sends as receive errors,
+                    // none of which concern the packets still to be received.
+                    debug!(target: Self::name(), "failed to receive packet: {}", err);
+                    continue;
+                }
+            };
+            if let Err(reason) = filter.admit(&buf[..len], addr) {
+                debug!(
+                    target: Self::name(),
+                    "dropped {}-byte packet from {}, which {}",
+                    len,
+                    addr,
+                    reason
+                );
+                continue;
+            }
+            match StunMessage::parse(&buf[..len]) {
+                Some(StunMessage::Request(id)) => {
+                    let resp = StunMessage::Response(id, Some(addr)).encode();
+                    if let Err(err) = udp.send_to(&resp, addr).await {
+                        debug!(
+                            target: Self::name(),
+                            "failed to answer STUN request from {}: {}", addr, err
+                        );
+                    }
+                    continue;
+                }
+                Some(StunMessage::Response(id, mapped)) => {
+                    if !transactions.complete(id, mapped) {
+                        debug!(
+                            target: Self::name(),
+                            "ignoring unexpected STUN response from {}", addr
+                        );
+                    }
+                    continue;
+                }
+                None => {}
+            }
+            debug!(
+                target: Self::name(),
+                "received {}-byte packet from {}", len, addr
+            );
+            let resp = Noun::from(Cell::from([
+                Noun::from(Atom::from("hear")),
+                Noun::from(Lane(addr)),
+                Noun::from(Atom::from(len as u64)),
+                Noun::from(Atom::from(buf[..len].to_vec())),
+            ]));
+            if let Err(_resp) = output_tx.send(resp).await {
+                warn!(
+                    target: Self::name(),
+                    "failed to send packet from {} to output task", addr
+                );
+                return;
+            }
+        }
+    }
+
+    /// Handles a [`SendPacket`] request.
+    async fn send_packet(&self, req: SendPacket) {
+        let addr = req.lane.0;
+        let stats = &self.filter.stats;
+        match (&self.socket, &self.pacer) {
+            (Some(_socket), Some(pacer)) => {
+                if !pacer.push(addr, req.packet) {
+                    stats.unsent.fetch_add(1, Ordering::Relaxed);
+                    warn!(
+                        target: Self::name(),
+                        "dropped packet to {} because its queue is full", addr
+                    );
+                }
+            }
+            (Some(socket), None) => Self::send_to(&socket.udp, &req.packet, addr, stats).await,
+            (None, _) => {
+                stats.unsent.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    target: Self::name(),
+                    "dropped packet to {} because no socket is bound", addr
+                );
+            }
+        }
+    }
+
+    /// Sends the packets queued in `pacer` over `udp`, no faster than the pacing rate.
+    async fn pace_packets(udp: Arc<UdpSocket>, pacer: Arc<Pacer>, stats: Arc<PacketStats>) {
+        loop {
+            let (addr, packet) = pacer.pop().await;
+            Self::send_to(&udp, &packet, addr, &stats).await;
+            let wait = pacer.charge();
+            if !wait.is_zero() {
+                time::sleep(wait).await;
+            }
+        }
+    }
+
+    /// Sends `packet` to `addr` over `udp`, counting it in `stats`.
+    async fn send_to(udp: &UdpSocket, packet: &[u8], addr: SocketAddrV4, stats: &PacketStats) {
+        match udp.send_to(packet, addr).await {
+            Ok(_) => {
+                stats.sent.fetch_add(1, Ordering::Relaxed);
+                debug!(
+                    target: Self::name(),
+                    "sent {}-byte packet to {}",
+                    packet.len(),
+                    addr
+                );
+            }
+            Err(err) => {
+                stats.unsent.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    target: Self::name(),
+                    "failed to send {}-byte packet to {}: {}",
+                    packet.len(),
+                    addr,
+                    err
+                );
+            }
+        }
+    }
+
+    /// Handles a [`Block`] request.
+    fn block(&self, req: Block) {
+        info!(
+            target: Self::name(),
+            "blocking {} networks",
+            req.networks.len()
+        );
+        *self
+            .filter
+            .blocked
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = req.networks;
+    }
+
+    /// Reports statistics about the packets sent and received.
+    fn get_stats(&self) -> Noun {
+        let stats = &self.filter.stats;
+        Noun::from(Cell::from([
+            Atom::from("stats"),
+            Atom::from(stats.received.load(Ordering::Relaxed)),
+            Atom::from(stats.malformed.load(Ordering::Relaxed)),
+            Atom::from(stats.blocked.load(Ordering::Relaxed)),
+            Atom::from(stats.rate_limited.load(Ordering::Relaxed)),
+            Atom::from(stats.sent.load(Ordering::Relaxed)),
+            Atom::from(stats.unsent.load(Ordering::Relaxed)),
+            Atom::from(self.pacer.as_ref().map_or(0, |pacer| pacer.len()) as u64),
+        ]))
+    }
+
+    /// Handles a [`Stun`] request.
+    fn stun(&mut self, req: Stun, output_tx: &Sender<Noun>) {
+        match req {
+            Stun::Start(server) => {
+                info!(
+                    target: Self::name(),
+                    "discovering public address with STUN server {}", server.0
+                );
+                self.stun_server = Some(server);
+            }
+            Stun::Stop => {
+                info!(target: Self::name(), "stopped discovering public address");
+                self.stun_server = None;
+            }
+        }
+        self.start_stun(output_tx);
+    }
+
+    /// Restarts the task that discovers the socket's public address with the STUN server, or
+    /// stops it if there's no longer a STUN server.
+    fn start_stun(&mut self, output_tx: &Sender<Noun>) {
+        if let Some(socket) = &mut self.socket {
+            if let Some(stun_task) = socket.stun_task.take() {
+                stun_task.abort();
+            }
+            socket.stun_task = self.stun_server.map(|server| {
+                tokio::spawn(Self::discover_public_addr(
+                    socket.udp.clone(),
+                    server,
+                    self.stun_transactions.clone(),
+                    self.config.stun_interval,
+                    output_tx.clone(),
+                ))
+            });
+        }
+    }
+
+    /// Sends a STUN binding request over `udp` to `server` every `interval`, reporting changes to
+    /// the socket's public address to the output task until the output task stops accepting
+    /// responses.
+    async fn discover_public_addr(
+        udp: Arc<UdpSocket>,
+        server: Lane,
+        transactions: Arc<StunTransactions>,
+        interval: Duration,
+        output_tx: Sender<Noun>,
+    ) {
+        // The public address reported by the last binding request, or `Err(())` if it failed.
+        let mut public_addr = Err(());
+        // Whether a failure has been reported since the last successful binding request.
+        let mut failed = false;
+        loop {
+            let resp = match Self::stun_binding(&udp, server.0, &transactions).await {
+                Some(addr) if public_addr != Ok(addr) => {
+                    if let Ok(prev) = public_addr {
+                        info!(
+                            target: Self::name(),
+                            "public address changed from {} to {}", prev, addr
+                        );
+                    } else {
+                        info!(target: Self::name(), "public address is {}", addr);
+                    }
+                    public_addr = Ok(addr);
+                    failed = false;
+                    Some(Noun::from(Cell::from([
+                        Noun::from(Atom::from("stun")),
+                        Noun::from(Atom::from("once")),
+                        Noun::from(Lane(addr)),
+                    ])))
+                }
+                Some(_addr) => None,
+                None if !failed => {
+                    warn!(
+                        target: Self::name(),
+                        "STUN server {} didn't respond", server.0
+                    );
+                    public_addr = Err(());
+                    failed = true;
+                    Some(Noun::from(Cell::from([
+                        Noun::from(Atom::from("stun")),
+                        Noun::from(Atom::from("fail")),
+                        Noun::null(),
+                    ])))
+                }
+                None => None,
+            };
+            if let Some(resp) = resp {
+                if let Err(_resp) = output_tx.send(resp).await {
+                    warn!(
+                        target: Self::name(),
+                        "failed to send STUN result to output task"
+                    );
+                    return;
+                }
+            }
+            time::sleep(interval).await;
+        }
+    }
+
+    /// Sends a STUN binding request over `udp` to `server`, retransmitting it until the server
+    /// responds, and returns the public address of the socket in the server's response, if any.
+    async fn stun_binding(
+        udp: &UdpSocket,
+        server: SocketAddrV4,
+        transactions: &StunTransactions,
+    ) -> Option<SocketAddrV4> {
+        let id = transactions.id();
+        let mut resp_rx = transactions.start(id);
+        let req = StunMessage::Request(id).encode();
+        let mut rto = Self::STUN_RTO;
+        let mut public_addr = None;
+        for _ in 0..Self::STUN_TRANSMISSIONS {
+            if let Err(err) = udp.send_to(&req, server).await {
+                debug!(
+                    target: Self::name(),
+                    "failed to send STUN request to {}: {}", server, err
+                );
+            }
+            if let Ok(resp) = time::timeout(rto, &mut resp_rx).await {
+                public_addr = resp.ok().flatten();
+                break;
+            }
+            rto *= 2;
+        }
+        transactions.cancel(id);
+        public_addr
+    }
+
+    /// Keeps `port` forwarded by the gateway of the local network with `method`, reporting changes
+    /// to the mapping to the output task until the output task stops accepting responses.
+    async fn map_port(
+        port: u16,
+        method: PortMapping,
+        gateway: Option<Ipv4Addr>,
+        output_tx: Sender<Noun>,
+    ) {
+        // The method and public address of the last mapping, or `Err(())` if it failed.
+        let mut mapping = Err(());
+        // Whether a failure has been reported since the last successful mapping.
+        let mut failed = false;
+        loop {
+            let (resp, wait) = match Self::request_mapping(port, method, gateway).await {
+                Ok(new_mapping) => {
+                    let resp = if mapping != Ok(new_mapping) {
+                        let (method, public_addr) = new_mapping;
+                        info!(
+                            target: Self::name(),
+                            "port {} is forwarded from {} with {}",
+                            port,
+                            public_addr,
+                            method.as_str()
+                        );
+                        Some(Noun::from(Cell::from([
+                            Noun::from(Atom::from("port-mapping")),
+                            Noun::from(Atom::from("done")),
+                            Noun::from(Atom::from(method.as_str())),
+                            Noun::from(Lane(public_addr)),
+                        ])))
+                    } else {
+                        None
+                    };
+                    mapping = Ok(new_mapping);
+                    failed = false;
+                    (resp, Self::MAPPING_LIFETIME / 2)
+                }
+                Err(err) => {
+                    let resp = if !failed {
+                        warn!(
+                            target: Self::name(),
+                            "failed to forward port {}: {}", port, err
+                        );
+                        Some(Noun::from(Cell::from([
+                            Atom::from("port-mapping"),
+                            Atom::from("fail"),
+                            Atom::from(err.to_string().as_str()),
+                        ])))
+                    } else {
+                        None
+                    };
+                    mapping = Err(());
+                    failed = true;
+                    (resp, Self::MAPPING_RETRY)
+                }
+            };
+            if let Some(resp) = resp {
+                if let Err(_resp) = output_tx.send(resp).await {
+                    warn!(
+                        target: Self::name(),
+                        "failed to send port mapping result to output task"
+                    );
+                    return;
+                }
+            }
+            time::sleep(wait).await;
+        }
+    }
+
+    /// Asks the gateway of the local network to forward `port` with `method`, returning the
+    /// method that succeeded and the public address that's forwarded.
+    async fn request_mapping(
+        port: u16,
+        method: PortMapping,
+        gateway: Option<Ipv4Addr>,
+    ) -> io::Result<(PortMapping, SocketAddrV4)> {
+        let nat_pmp = move || async move {
+            let gateway = gateway.or_else(default_gateway).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no gateway to send NAT-PMP requests to",
+                )
+            })?;
+            let server = SocketAddrV4::new(gateway, nat_pmp::PORT);
+            nat_pmp::map(server, port, Self::MAPPING_LIFETIME)
+                .await
+                .map(|public_addr| (PortMapping::NatPmp, public_addr))
+        };
+        let upnp = move || async move {
+            upnp::map(port, Self::MAPPING_LIFETIME)
+                .await
+                .map(|public_addr| (PortMapping::Upnp, public_addr))
+        };
+        match method {
+            PortMapping::None => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "port mapping is disabled",
+            )),
+            PortMapping::Auto => match nat_pmp().await {
+                Ok(mapping) => Ok(mapping),
+                Err(err) => {
+                    debug!(
+                        target: Self::name(),
+                        "falling back to UPnP after NAT-PMP failed: {}", err
+                    );
+                    upnp().await
+                }
+            },
+            PortMapping::NatPmp => nat_pmp().await,
+            PortMapping::Upnp => upnp().await,
+        }
+    }
+
+    /// Handles a [`Discover`] request.
+    fn discover(&mut self, req: Discover, output_tx: &Sender<Noun>) {
+        if !self.config.discovery || self.config.local {
+            debug!(
+                target: Self::name(),
+                "ignoring discovery request because discovery is disabled"
+            );
+            return;
+        }
+        match req {
+            Discover::Start(ship) => {
+                info!(
+                    target: Self::name(),
+                    "advertising ship {:x} on the local network", ship
+                );
+                self.discovery_ship = Some(ship);
+            }
+            Discover::Stop => {
+                info!(target: Self::name(), "stopped discovering ships");
+                self.discovery_ship = None;
+            }
+        }
+        self.start_discovery(output_tx);
+    }
+
+    /// Restarts the task that advertises the ship and discovers other ships on the local network,
+    /// or stops it if there's no longer a ship to advertise.
+    fn start_discovery(&mut self, output_tx: &Sender<Noun>) {
+        if let Some(socket) = &mut self.socket {
+            if let Some(discovery_task) = socket.discovery_task.take() {
+                discovery_task.abort();
+            }
+            socket.discovery_task = self.discovery_ship.map(|ship| {
+                tokio::spawn(Self::discover_ships(
+                    ship,
+                    socket.port,
+                    self.config.discovery_interval,
+                    output_tx.clone(),
+                ))
+            });
+        }
+    }
+
+    /// Advertises `ship` at `port` on the local network and asks for other ships every
+    /// `interval`, reporting ships found at new lanes to the output task until the output task
+    /// stops accepting responses.
+    async fn discover_ships(ship: u128, port: u16, interval: Duration, output_tx: Sender<Noun>) {
+        let udp = match mdns::bind() {
+            Ok(udp) => udp,
+            Err(err) => {
+                warn!(
+                    target: Self::name(),
+                    "failed to listen for mDNS messages: {}", err
+                );
+                let resp = Noun::from(Cell::from([
+                    Atom::from("discover"),
+                    Atom::from("fail"),
+                    Atom::from(err.to_string().as_str()),
+                ]));
+                if let Err(_resp) = output_tx.send(resp).await {
+                    warn!(
+                        target: Self::name(),
+                        "failed to send discovery failure to output task"
+                    );
+                }
+                return;
+            }
+        };
+        let announcement = mdns::announcement(ship, port);
+        // The lane each ship was last found at.
+        let mut found: HashMap<u128, SocketAddrV4> = HashMap::new();
+        let mut next_query = Instant::now();
+        let mut buf = [0; mdns::MAX_MESSAGE_LEN];
+        loop {
+            if Instant::now() >= next_query {
+                for msg in [&announcement, &mdns::query()] {
+                    if let Err(err) = udp.send_to(msg, mdns::ADDR).await {
+                        debug!(
+                            target: Self::name(),
+                            "failed to send mDNS message: {}", err
+                        );
+                    }
+                }
+                next_query = Instant::now() + interval;
+            }
+            let wait = next_query.saturating_duration_since(Instant::now());
+            let (len, src) = match time::timeout(wait, udp.recv_from(&mut buf)).await {
+                Ok(Ok((len, SocketAddr::V4(src)))) => (len, src),
+                Ok(Ok((_len, SocketAddr::V6(_src)))) => continue,
+                Ok(Err(err)) => {
+                    debug!(
+                        target: Self::name(),
+                        "failed to receive mDNS message: {}", err
+                    );
+                    continue;
+                }
+                Err(_elapsed) => continue,
+            };
+            let peers = match mdns::parse(&buf[..len]) {
+                Some(mdns::Message::Query(true)) => {
+                    if let Err(err) = udp.send_to(&announcement, mdns::ADDR).await {
+                        debug!(
+                            target: Self::name(),
+                            "failed to answer mDNS query from {}: {}", src, err
+                        );
+                    }
+                    continue;
+                }
+                Some(mdns::Message::Response(peers)) => peers,
+                Some(mdns::Message::Query(false)) | None => continue,
+            };
+            for (peer, peer_port) in peers {
+                let lane = SocketAddrV4::new(*src.ip(), peer_port);
+                if peer == ship || found.insert(peer, lane) == Some(lane) {
+                    continue;
+                }
+                info!(
+                    target: Self::name(),
+                    "found ship {:x} on the local network at {}", peer, lane
+                );
+                let resp = Noun::from(Cell::from([
+                    Noun::from(Atom::from("discover")),
+                    Noun::from(Atom::from("found")),
+                    Noun::from(ship_to_atom(peer)),
+                    Noun::from(Lane(lane)),
+                ]));
+                if let Err(_resp) = output_tx.send(resp).await {
+                    warn!(
+                        target: Self::name(),
+                        "failed to send discovered ship to output task"
+                    );
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Implements the [`Driver`] trait for the [`Ames`] driver.
+macro_rules! impl_driver {
+    ($input_src:ty, $output_sink:ty) => {
+        impl Driver<$input_src, $output_sink> for Ames {
+            fn new() -> Result<Self, Status> {
+                let driver = Self::with_config(Config::from_env());
+                debug!(target: Self::name(), "initialized driver");
+                Ok(driver)
+            }
+
+            fn name() -> &'static str {
+                "ames"
+            }
+
+            fn handle_requests(
+                mut self,
+                mut input_rx: Receiver<Noun>,
+                output_tx: Sender<Noun>,
+            ) -> JoinHandle<Status> {
+                let task = tokio::spawn(async move {
+                    let port = self.config.port;
+                    let resp = self.bind(Bind { port }, &output_tx).await;
+                    if let Err(_resp) = output_tx.send(resp).await {
+                        warn!(target: Self::name(), "failed to send bind result to output task");
+                    }
+                    while let Some(req) = input_rx.recv().await {
+                        match Request::try_from(req) {
+                            Ok(Request::Bind(req)) => {
+                                let resp = self.bind(req, &output_tx).await;
+                                if let Err(_resp) = output_tx.send(resp).await {
+                                    warn!(
+                                        target: Self::name(),
+                                        "failed to send bind result to output task"
+                                    );
+                                }
+                            }
+                            Ok(Request::SendPacket(req)) => self.send_packet(req).await,
+                            Ok(Request::Stun(req)) => self.stun(req, &output_tx),
+                            Ok(Request::Discover(req)) => self.discover(req, &output_tx),
+                            Ok(Request::Block(req)) => self.block(req),
+                            Ok(Request::GetStats(_req)) => {
+                                let resp = self.get_stats();
+                                if let Err(_resp) = output_tx.send(resp).await {
+                                    warn!(
+                                        target: Self::name(),
+                                        "failed to send statistics to output task"
+                                    );
+                                }
+                            }
+                            _ => {
+                                warn!(target: Self::name(), "skipping unidentifiable request");
+                            }
+                        }
+                    }
+                    // Close the socket.
+                    drop(self);
+                    Status::Success
+                });
+                debug!(target: Self::name(), "spawned handling task");
+                task
+            }
+        }
+    };
+}
+
+impl_driver!(Stdin, Stdout);
+
+/// Provides an FFI-friendly interface for running the Ames driver with `stdin` as the input source
+/// and `stdout` as the output sink.
+#[no_mangle]
+pub extern "C" fn ames_run() -> Status {
+    match Ames::new() {
+        Ok(driver) => driver.run(tokio::io::stdin(), tokio::io::stdout()),
+        Err(status) => status,
+    }
+}
+
+//==================================================================================================
+// Packet Filtering
+//==================================================================================================
+
+/// The filter that received packets pass through before anything else is done with them.
+struct PacketFilter {
+    /// The networks packets are dropped from.
+    blocked: Mutex<Vec<Network>>,
+
+    /// How many packets are accepted from each source address.
+    rate_limit: RateLimit,
+
+    /// When all of the packets accepted from each source address would have been accepted at the
+    /// rate limit.
+    due: Mutex<HashMap<Ipv4Addr, Instant>>,
+
+    /// Statistics about the packets sent and received.
+    stats: Arc<PacketStats>,
+}
+
+impl PacketFilter {
+    /// The length of an Ames packet header, which every packet starts with.
+    const HEADER_LEN: usize = 4;
+
+    /// The number of source addresses whose rate is tracked before those that have fallen back
+    /// under their rate are forgotten.
+    const MAX_SOURCES: usize = 4096;
+
+    fn new(config: &Config) -> Self {
+        Self {
+            blocked: Mutex::new(config.blocked.0.clone()),
+            rate_limit: config.rate_limit,
+            due: Mutex::new(HashMap::new()),
+            stats: Arc::new(PacketStats::default()),
+        }
+    }
+
+    /// Counts a packet received from `src`, returning the reason it should be dropped, if any.
+    fn admit(&self, packet: &[u8], src: SocketAddrV4) -> Result<(), &'static str> {
+        let stats = &self.stats;
+        stats.received.fetch_add(1, Ordering::Relaxed);
+        let ip = *src.ip();
+        if packet.len() < Self::HEADER_LEN
+            || src.port() == 0
+            || ip.is_unspecified()
+            || ip.is_broadcast()
+            || ip.is_multicast()
+        {
+            stats.malformed.fetch_add(1, Ordering::Relaxed);
+            return Err("is malformed");
+        }
+        let blocked = self.blocked.lock().unwrap_or_else(PoisonError::into_inner);
+        if blocked.iter().any(|network| network.contains(ip)) {
+            stats.blocked.fetch_add(1, Ordering::Relaxed);
+            return Err("is blocked");
+        }
+        drop(blocked);
+        if let Some(rate) = self.rate_limit.per_source {
+            let burst = self.rate_limit.burst.unwrap_or(rate);
+            let interval = Duration::from_secs(1) / rate;
+            let now = Instant::now();
+            let mut due = self.due.lock().unwrap_or_else(PoisonError::into_inner);
+            if due.len() >= Self::MAX_SOURCES && !due.contains_key(&ip) {
+                due.retain(|_ip, due| *due > now);
+            }
+            let due = due.entry(ip).or_insert(now);
+            let next_due = (*due).max(now) + interval;
+            if next_due.saturating_duration_since(now) > interval * burst {
+                stats.rate_limited.fetch_add(1, Ordering::Relaxed);
+                return Err("exceeded its rate limit");
+            }
+            *due = next_due;
+        }
+        Ok(())
+    }
+}
+
+/// Statistics about the packets sent and received.
+#[derive(Default)]
+struct PacketStats {
+    /// The number of packets received.
+    received: AtomicU64,
+
+    /// The number of received packets dropped because they were malformed.
+    malformed: AtomicU64,
+
+    /// The number of received packets dropped because their source was blocked.
+    blocked: AtomicU64,
+
+    /// The number of received packets dropped because their source exceeded its rate limit.
+    rate_limited: AtomicU64,
+
+    /// The number of packets sent.
+    sent: AtomicU64,
+
+    /// The number of packets that couldn't be sent.
+    unsent: AtomicU64,
+}
+
+/// A network of IPv4 addresses.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Network {
+    /// An address in the network.
+    ip: Ipv4Addr,
+
+    /// The length of the network's prefix, which is at most 32.
+    prefix_len: u8,
+}
+
+impl Network {
+    /// Determines whether `ip` is in the network.
+    fn contains(&self, ip: Ipv4Addr) -> bool {
+        let mask = u32::MAX
+            .checked_shl(32 - u32::from(self.prefix_len))
+            .unwrap_or(0);
+        (u32::from(ip) ^ u32::from(self.ip)) & mask == 0
+    }
+}
+
+impl FromStr for Network {
+    type Err = ();
+
+    /// A properly structured string is an IPv4 address or `<ip>/<prefix-len>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ip, prefix_len) = match s.split_once('/') {
+            Some((ip, prefix_len)) => (ip, prefix_len.parse().map_err(|_| ())?),
+            None => (s, 32),
+        };
+        if prefix_len > 32 {
+            return Err(());
+        }
+        Ok(Self {
+            ip: ip.parse().map_err(|_| ())?,
+            prefix_len,
+        })
+    }
+}
+
+impl TryFrom<&Noun> for Network {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// [<ip> <prefix_len>]
+    /// ```
+    ///
+    /// where `<ip>` is an IPv4 address as a 32-bit atom, and `<prefix_len>` is at most 32.
+    fn try_from(network: &Noun) -> Result<Self, Self::Error> {
+        if let Noun::Cell(network) = network {
+            match (network.head_ref(), network.tail_ref()) {
+                (Noun::Atom(ip), Noun::Atom(prefix_len)) => Ok(Self {
+                    ip: ipv4_from_atom(ip)?,
+                    prefix_len: prefix_len
+                        .as_u64()
+                        .filter(|prefix_len| *prefix_len <= 32)
+                        .ok_or(convert::Error::AtomToUint)? as u8,
+                }),
+                _ => Err(convert::Error::UnexpectedCell),
+            }
+        } else {
+            Err(convert::Error::UnexpectedAtom)
+        }
+    }
+}
+
+/// A list of networks.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+struct Networks(Vec<Network>);
+
+impl FromStr for Networks {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|network| !network.is_empty())
+            .map(Network::from_str)
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+//==================================================================================================
+// Packet Pacing
+//==================================================================================================
+
+/// The queues that packets wait in to be sent at the pacing rate.
+struct Pacer {
+    /// The time between packets at the pacing rate.
+    interval: Duration,
+
+    /// The number of packets that may be sent at once.
+    burst: u32,
+
+    /// The number of packets that may be queued for each destination.
+    max_queue_len: usize,
+
+    /// The queued packets.
+    queues: Mutex<PacketQueues>,
+
+    /// Notified when a packet is queued.
+    queued: Notify,
+
+    /// When all of the packets sent so far would have been sent at the pacing rate.
+    due: Mutex<Instant>,
+}
+
+/// The packets waiting to be sent to each destination.
+#[derive(Default)]
+struct PacketQueues {
+    /// The queue of packets for each destination with queued packets.
+    packets: HashMap<SocketAddrV4, VecDeque<Vec<u8>>>,
+
+    /// The destinations with queued packets, in the order they take turns sending a packet.
+    turns: VecDeque<SocketAddrV4>,
+
+    /// The total number of queued packets.
+    len: usize,
+}
+
+impl Pacer {
+    fn new(rate: u32, pacing: &Pacing) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / rate,
+            burst: pacing.burst.unwrap_or(rate),
+            max_queue_len: pacing.max_queue_len,
+            queues: Mutex::new(PacketQueues::default()),
+            queued: Notify::new(),
+            due: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Queues a packet for `addr`, returning `false` if the queue for `addr` is full.
+    fn push(&self, addr: SocketAddrV4, packet: Vec<u8>) -> bool {
+        let mut queues = self.queues.lock().unwrap_or_else(PoisonError::into_inner);
+        let queues = &mut *queues;
+        let queue = queues.packets.entry(addr).or_default();
+        if queue.len() >= self.max_queue_len {
+            return false;
+        }
+        if queue.is_empty() {
+            queues.turns.push_back(addr);
+        }
+        queue.push_back(packet);
+        queues.len += 1;
+        self.queued.notify_one();
+        true
+    }
+
+    /// Waits for a packet to be queued, and removes the next packet to send along with its
+    /// destination.
+    async fn pop(&self) -> (SocketAddrV4, Vec<u8>) {
+        loop {
+            if let Some(next) = self.try_pop() {
+                return next;
+            }
+            self.queued.notified().await;
+        }
+    }
+
+    /// Removes the next packet to send along with its destination, if any packets are queued.
+    ///
+    /// The destination whose turn it is sends one packet and goes to the back of the line if it has
+    /// more.
+    fn try_pop(&self) -> Option<(SocketAddrV4, Vec<u8>)> {
+        let mut queues = self.queues.lock().unwrap_or_else(PoisonError::into_inner);
+        let queues = &mut *queues;
+        let addr = queues.turns.pop_front()?;
+        let queue = queues.packets.get_mut(&addr)?;
+        let packet = queue.pop_front()?;
+        if queue.is_empty() {
+            queues.packets.remove(&addr);
+        } else {
+            queues.turns.push_back(addr);
+        }
+        queues.len -= 1;
+        Some((addr, packet))
+    }
+
+    /// Returns the number of queued packets.
+    fn len(&self) -> usize {
+        self.queues
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len
+    }
+
+    /// Counts a sent packet against the pacing rate, returning how long to wait before sending the
+    /// next packet.
+    fn charge(&self) -> Duration {
+        let now = Instant::now();
+        let mut due = self.due.lock().unwrap_or_else(PoisonError::into_inner);
+        *due = (*due).max(now) + self.interval;
+        due.saturating_duration_since(now)
+            .saturating_sub(self.interval * self.burst)
+    }
+}
+
+//==================================================================================================
+// STUN
+//==================================================================================================
+
+/// A STUN binding message, as defined by [RFC 5389].
+///
+/// [RFC 5389]: https://www.rfc-editor.org/rfc/rfc5389
+#[derive(Debug, PartialEq)]
+enum StunMessage {
+    /// A binding request with a transaction ID.
+    Request([u8; 12]),
+
+    /// A response to the binding request with a transaction ID, with the public address of the
+    /// requester if the request succeeded.
+    Response([u8; 12], Option<SocketAddrV4>),
+}
+
+impl StunMessage {
+    /// The fixed value of the second word of every STUN message.
+    const MAGIC_COOKIE: u32 = 0x2112_a442;
+
+    /// The message type of a binding request.
+    const BINDING_REQUEST: u16 = 0x0001;
+
+    /// The message type of a successful response to a binding request.
+    const BINDING_SUCCESS: u16 = 0x0101;
+
+    /// The message type of an error response to a binding request.
+    const BINDING_ERROR: u16 = 0x0111;
+
+    /// The attribute type of a `MAPPED-ADDRESS` attribute.
+    const MAPPED_ADDRESS: u16 = 0x0001;
+
+    /// The attribute type of an `XOR-MAPPED-ADDRESS` attribute.
+    const XOR_MAPPED_ADDRESS: u16 = 0x0020;
+
+    /// The attribute type of a `FINGERPRINT` attribute.
+    const FINGERPRINT: u16 = 0x8028;
+
+    /// The value that the CRC-32 of a message is XORed with to yield its `FINGERPRINT`.
+    const FINGERPRINT_XOR: u32 = 0x5354_554e;
+
+    /// Parses a packet as a STUN binding message, returning `None` if it isn't one.
+    fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.len() < 20 || packet[0] & 0xc0 != 0 {
+            return None;
+        }
+        let msg_type = u16::from_be_bytes([packet[0], packet[1]]);
+        let len = usize::from(u16::from_be_bytes([packet[2], packet[3]]));
+        let cookie = u32::from_be_bytes([packet[4], packet[5], packet[6], packet[7]]);
+        if cookie != Self::MAGIC_COOKIE || len % 4 != 0 || packet.len() != 20 + len {
+            return None;
+        }
+        let mut id = [0; 12];
+        id.copy_from_slice(&packet[8..20]);
+        match msg_type {
+            Self::BINDING_REQUEST => Some(Self::Request(id)),
+            Self::BINDING_SUCCESS => Some(Self::Response(id, Self::mapped_addr(&packet[20..]))),
+            Self::BINDING_ERROR => Some(Self::Response(id, None)),
+            _ => None,
+        }
+    }
+
+    /// Returns the IPv4 address of the `XOR-MAPPED-ADDRESS` attribute of a message's attributes,
+    /// falling back to that of the `MAPPED-ADDRESS` attribute for older servers.
+    fn mapped_addr(mut attrs: &[u8]) -> Option<SocketAddrV4> {
+        let mut mapped_addr = None;
+        while attrs.len() >= 4 {
+            let attr_type = u16::from_be_bytes([attrs[0], attrs[1]]);
+            let len = usize::from(u16::from_be_bytes([attrs[2], attrs[3]]));
+            let value = attrs.get(4..4 + len)?;
+            // An IPv4 address is a reserved byte, a family of 1, a port, and an address.
+            if let [_, 1, port_0, port_1, ip_0, ip_1, ip_2, ip_3] = *value {
+                let port = u16::from_be_bytes([port_0, port_1]);
+                let ip = u32::from_be_bytes([ip_0, ip_1, ip_2, ip_3]);
+                match attr_type {
+                    Self::XOR_MAPPED_ADDRESS => {
+                        return Some(SocketAddrV4::new(
+                            Ipv4Addr::from(ip ^ Self::MAGIC_COOKIE),
+                            port ^ (Self::MAGIC_COOKIE >> 16) as u16,
+                        ))
+                    }
+                    Self::MAPPED_ADDRESS => {
+                        mapped_addr = Some(SocketAddrV4::new(Ipv4Addr::from(ip), port))
+                    }
+                    _ => {}
+                }
+            }
+            // Attribute values are padded to a multiple of 4 bytes.
+            attrs = attrs.get(4 + len.next_multiple_of(4)..).unwrap_or_default();
+        }
+        mapped_addr
+    }
+
+    /// Encodes the message, ending it with a `FINGERPRINT` attribute.
+    fn encode(&self) -> Vec<u8> {
+        let (msg_type, id, mapped_addr) = match self {
+            Self::Request(id) => (Self::BINDING_REQUEST, id, None),
+            Self::Response(id, mapped_addr) => (Self::BINDING_SUCCESS, id, *mapped_addr),
+        };
+        let mut msg = Vec::with_capacity(40);
+        msg.extend_from_slice(&msg_type.to_be_bytes());
+        // The length is filled in once the attributes are known.
+        msg.extend_from_slice(&[0, 0]);
+        msg.extend_from_slice(&Self::MAGIC_COOKIE.to_be_bytes());
+        msg.extend_from_slice(id);
+        if let Some(addr) = mapped_addr {
+            msg.extend_from_slice(&Self::XOR_MAPPED_ADDRESS.to_be_bytes());
+            msg.extend_from_slice(&8u16.to_be_bytes());
+            msg.extend_from_slice(&[0, 1]);
+            let port = addr.port() ^ (Self::MAGIC_COOKIE >> 16) as u16;
+            msg.extend_from_slice(&port.to_be_bytes());
+            let ip = u32::from(*addr.ip()) ^ Self::MAGIC_COOKIE;
+            msg.extend_from_slice(&ip.to_be_bytes());
+        }
+        // The fingerprint covers the length, which includes the fingerprint itself.
+        let len = (msg.len() - 20 + 8) as u16;
+        msg[2..4].copy_from_slice(&len.to_be_bytes());
+        let fingerprint = crc32(&msg) ^ Self::FINGERPRINT_XOR;
+        msg.extend_from_slice(&Self::FINGERPRINT.to_be_bytes());
+        msg.extend_from_slice(&4u16.to_be_bytes());
+        msg.extend_from_slice(&fingerprint.to_be_bytes());
+        msg
+    }
+}
+
+/// The STUN binding requests awaiting responses, keyed by transaction ID.
+#[derive(Default)]
+struct StunTransactions {
+    /// The channel over which the public address in the response to each request is sent.
+    pending: Mutex<HashMap<[u8; 12], oneshot::Sender<Option<SocketAddrV4>>>>,
+
+    /// The source of transaction IDs.
+    random: RandomState,
+
+    /// The number of transaction IDs generated so far.
+    count: Mutex<u64>,
+}
+
+impl StunTransactions {
+    /// Generates a transaction ID, which is unpredictable so that off-path attackers can't forge
+    /// responses.
+    fn id(&self) -> [u8; 12] {
+        let mut count = self.count.lock().unwrap_or_else(PoisonError::into_inner);
+        *count += 1;
+        let mut id = [0; 12];
+        for (i, chunk) in id.chunks_mut(8).enumerate() {
+            let hash = self.random.hash_one((*count, i));
+            chunk.copy_from_slice(&hash.to_le_bytes()[..chunk.len()]);
+        }
+        id
+    }
+
+    /// Starts waiting for a response to the request with transaction ID `id`.
+    fn start(&self, id: [u8; 12]) -> oneshot::Receiver<Option<SocketAddrV4>> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(id, resp_tx);
+        resp_rx
+    }
+
+    /// Hands the public address in a response to the request with transaction ID `id`, returning
+    /// `false` if no such request is waiting for a response.
+    fn complete(&self, id: [u8; 12], public_addr: Option<SocketAddrV4>) -> bool {
+        let resp_tx = self
+            .pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&id);
+        match resp_tx {
+            Some(resp_tx) => resp_tx.send(public_addr).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Stops waiting for a response to the request with transaction ID `id`.
+    fn cancel(&self, id: [u8; 12]) {
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&id);
+    }
+}
+
+/// Computes the CRC-32 checksum of `data`, as used by Ethernet and STUN's `FINGERPRINT`
+/// attribute.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+//==================================================================================================
+// Port Mapping
+//==================================================================================================
+
+/// Port mapping with NAT-PMP, as defined by [RFC 6886].
+///
+/// [RFC 6886]: https://www.rfc-editor.org/rfc/rfc6886
+mod nat_pmp {
+    use super::*;
+
+    /// The port NAT-PMP servers listen on.
+    pub(super) const PORT: u16 = 5351;
+
+    /// How long to wait for a response to the first transmission of a request, which doubles with
+    /// each retransmission.
+    const RTO: Duration = Duration::from_millis(250);
+
+    /// The number of times a request is transmitted before it fails.
+    const TRANSMISSIONS: usize = 4;
+
+    /// The opcode of a request for the public address of the gateway.
+    const PUBLIC_ADDR: u8 = 0;
+
+    /// The opcode of a request to forward a UDP port.
+    const MAP_UDP: u8 = 1;
+
+    /// Asks the NAT-PMP server at `server` to forward `port` for `lifetime`, returning the public
+    /// address that's forwarded.
+    pub(super) async fn map(
+        server: SocketAddrV4,
+        port: u16,
+        lifetime: Duration,
+    ) -> io::Result<SocketAddrV4> {
+        let udp = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        udp.connect(server).await?;
+
+        let resp = request(&udp, &[0, PUBLIC_ADDR]).await?;
+        let ip = Ipv4Addr::new(resp[8], resp[9], resp[10], resp[11]);
+
+        let mut req = vec![0, MAP_UDP, 0, 0];
+        req.extend_from_slice(&port.to_be_bytes());
+        // Suggest the same public port.
+        req.extend_from_slice(&port.to_be_bytes());
+        let lifetime = u32::try_from(lifetime.as_secs()).unwrap_or(u32::MAX);
+        req.extend_from_slice(&lifetime.to_be_bytes());
+        let resp = request(&udp, &req).await?;
+        let public_port = u16::from_be_bytes([resp[10], resp[11]]);
+        Ok(SocketAddrV4::new(ip, public_port))
+    }
+
+    /// Sends a request over `udp`, retransmitting it until the server responds, and returns the
+    /// server's successful response.
+    async fn request(udp: &UdpSocket, req: &[u8]) -> io::Result<Vec<u8>> {
+        let opcode = req[1];
+        let resp_len = if opcode == PUBLIC_ADDR { 12 } else { 16 };
+        let mut buf = [0; 16];
+        let mut rto = RTO;
+        for _ in 0..TRANSMISSIONS {
+            udp.send(req).await?;
+            let len = match time::timeout(rto, udp.recv(&mut buf)).await {
+                Ok(len) => len?,
+                Err(_) => {
+                    rto *= 2;
+                    continue;
+                }
+            };
+            // A response has version 0 and the opcode of the request plus 128.
+            if len == resp_len && buf[0] == 0 && buf[1] == opcode + 128 {
+                return match u16::from_be_bytes([buf[2], buf[3]]) {
+                    0 => Ok(buf[..len].to_vec()),
+                    result => Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        format!("NAT-PMP server refused request with result code {}", result),
+                    )),
+                };
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "NAT-PMP server didn't respond",
+        ))
+    }
+}
+
+/// Port mapping with a UPnP Internet Gateway Device.
+mod upnp {
+    use super::*;
+
+    /// The multicast address that UPnP devices are discovered at.
+    const SSDP_ADDR: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(239, 255, 255, 250), 1900);
+
+    /// How long to wait for a gateway to respond to discovery, or to an HTTP request.
+    const TIMEOUT: Duration = Duration::from_secs(3);
+
+    /// Discovers the gateway of the local network and asks it to forward `port` for `lifetime`,
+    /// returning the public address that's forwarded.
+    pub(super) async fn map(port: u16, lifetime: Duration) -> io::Result<SocketAddrV4> {
+        let location = discover().await?;
+        let (addr, path) = parse_http_url(&location)?;
+        let (desc, local_ip) = http_request(addr, format!("GET {} HTTP/1.0\r\n\r\n", path)).await?;
+        let (service_type, control_url) = wan_connection_service(&desc).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "gateway doesn't have a WAN connection service",
+            )
+        })?;
+        let control = if control_url.starts_with("http://") {
+            parse_http_url(control_url)?
+        } else if control_url.starts_with('/') {
+            (addr, control_url.to_string())
+        } else {
+            (addr, format!("/{}", control_url))
+        };
+
+        let port_str = port.to_string();
+        let lifetime = lifetime.as_secs().to_string();
+        let local_ip = local_ip.to_string();
+        soap_request(
+            control.clone(),
+            service_type,
+            "AddPortMapping",
+            &[
+                ("NewRemoteHost", ""),
+                ("NewExternalPort", &port_str),
+                ("NewProtocol", "UDP"),
+                ("NewInternalPort", &port_str),
+                ("NewInternalClient", &local_ip),
+                ("NewEnabled", "1"),
+                ("NewPortMappingDescription", "Urbit Ames"),
+                ("NewLeaseDuration", &lifetime),
+            ],
+        )
+        .await?;
+        let resp = soap_request(control, service_type, "GetExternalIPAddress", &[]).await?;
+        let ip = xml_text(&resp, "NewExternalIPAddress")
+            .and_then(|ip| ip.parse().ok())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "gateway didn't report its public address",
+                )
+            })?;
+        Ok(SocketAddrV4::new(ip, port))
+    }
+
+    /// Discovers the gateway of the local network, returning the URL of its description.
+    async fn discover() -> io::Result<String> {
+        let udp = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+        let req = "M-SEARCH * HTTP/1.1\r\n\
+                   HOST: 239.255.255.250:1900\r\n\
+                   MAN: \"ssdp:discover\"\r\n\
+                   MX: 2\r\n\
+                   ST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\n\
+                   \r\n";
+        udp.send_to(req.as_bytes(), SSDP_ADDR).await?;
+        let mut buf = [0; 2048];
+        let discover = async {
+            loop {
+                let (len, _addr) = udp.recv_from(&mut buf).await?;
+                let resp = String::from_utf8_lossy(&buf[..len]);
+                let location = resp.lines().find_map(|line| {
+                    let (name, val) = line.split_once(':')?;
+                    if name.trim().eq_ignore_ascii_case("location") {
+                        Some(val.trim().to_string())
+                    } else {
+                        None
+                    }
+                });
+                if let Some(location) = location {
+                    return Ok::<_, io::Error>(location);
+                }
+            }
+        };
+        time::timeout(TIMEOUT, discover).await.unwrap_or_else(|_| {
+            Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "no UPnP gateway responded",
+            ))
+        })
+    }
+
+    /// Sends a SOAP request for `action` to the `service_type` service at `control`, an address
+    /// and path, returning the body of the response.
+    async fn soap_request(
+        control: (SocketAddrV4, String),
+        service_type: &str,
+        action: &str,
+        args: &[(&str, &str)],
+    ) -> io::Result<String> {
+        let (addr, path) = control;
+        let args: String = args
+            .iter()
+            .map(|(name, val)| format!("<{0}>{1}</{0}>", name, val))
+            .collect();
+        let body = format!(
+            "<?xml version=\"1.0\"?>\r\n\
+             <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+             s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\">\
+             <s:Body><u:{0} xmlns:u=\"{1}\">{2}</u:{0}></s:Body></s:Envelope>",
+            action, service_type, args
+        );
+        let req = format!(
+            "POST {} HTTP/1.0\r\n\
+             Content-Type: text/xml; charset=\"utf-8\"\r\n\
+             SOAPAction: \"{}#{}\"\r\n\
+             Content-Length: {}\r\n\
+             \r\n\
+             {}",
+            path,
+            service_type,
+            action,
+            body.len(),
+            body
+        );
+        http_request(addr, req).await.map(|(body, _local_ip)| body)
+    }
+
+    /// Sends an HTTP/1.0 request, whose headers are completed with a `Host` header, to `addr`,
+    /// returning the body of the response and the local address of the connection.
+    async fn http_request(addr: SocketAddrV4, req: String) -> io::Result<(String, IpAddr)> {
+        let exchange = async {
+            let mut stream = TcpStream::connect(addr).await?;
+            let local_ip = stream.local_addr()?.ip();
+            let (request_line, rest) = req.split_once("\r\n").unwrap_or((req.as_str(), ""));
+            let req = format!("{}\r\nHost: {}\r\n{}", request_line, addr, rest);
+            stream.write_all(req.as_bytes()).await?;
+            let mut resp = Vec::new();
+            stream.read_to_end(&mut resp).await?;
+            Ok::<_, io::Error>((String::from_utf8_lossy(&resp).into_owned(), local_ip))
+        };
+        let (resp, local_ip) = time::timeout(TIMEOUT, exchange).await.unwrap_or_else(|_| {
+            Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "UPnP gateway didn't respond",
+            ))
+        })?;
+        let (head, body) = resp.split_once("\r\n\r\n").unwrap_or((resp.as_str(), ""));
+        let status = head.split(' ').nth(1).unwrap_or_default();
+        if status == "200" {
+            Ok((body.to_string(), local_ip))
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("UPnP gateway responded with status {}", status),
+            ))
+        }
+    }
+
+    /// Splits an `http://` URL whose host is an IPv4 address into the address and the path.
+    pub(super) fn parse_http_url(url: &str) -> io::Result<(SocketAddrV4, String)> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported UPnP URL {}", url),
+            )
+        };
+        let url = url.strip_prefix("http://").ok_or_else(invalid)?;
+        let (authority, path) = url.split_at(url.find('/').unwrap_or(url.len()));
+        let addr = match authority.parse() {
+            Ok(addr) => addr,
+            Err(_) => SocketAddrV4::new(authority.parse().map_err(|_| invalid())?, 80),
+        };
+        let path = if path.is_empty() { "/" } else { path };
+        Ok((addr, path.to_string()))
+    }
+
+    /// Returns the service type and control URL of the WAN connection service in a device
+    /// description.
+    pub(super) fn wan_connection_service(desc: &str) -> Option<(&str, &str)> {
+        desc.split("<service>").skip(1).find_map(|service| {
+            let service_type = xml_text(service, "serviceType")?;
+            if service_type.starts_with("urn:schemas-upnp-org:service:WANIPConnection:")
+                || service_type.starts_with("urn:schemas-upnp-org:service:WANPPPConnection:")
+            {
+                Some((service_type, xml_text(service, "controlURL")?))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Returns the text of the first `tag` element in `xml`.
+    fn xml_text<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+        let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+        let len = xml[start..].find(&format!("</{}>", tag))?;
+        Some(xml[start..start + len].trim())
+    }
+}
+
+/// Returns the gateway of the default route.
+#[cfg(target_os = "linux")]
+fn default_gateway() -> Option<Ipv4Addr> {
+    parse_default_gateway(&std::fs::read_to_string("/proc/net/route").ok()?)
+}
+
+/// Returns the gateway of the default route.
+#[cfg(not(target_os = "linux"))]
+fn default_gateway() -> Option<Ipv4Addr> {
+    None
+}
+
+/// Parses the gateway of the default route from the contents of Linux's `/proc/net/route`, in
+/// which addresses are hexadecimal in the platform's byte order.
+fn parse_default_gateway(routes: &str) -> Option<Ipv4Addr> {
+    /// The flag of a route that goes through a gateway.
+    const RTF_GATEWAY: u16 = 0x2;
+    routes.lines().skip(1).find_map(|route| {
+        let fields: Vec<_> = route.split_whitespace().collect();
+        let (destination, gateway, flags) = (fields.get(1)?, fields.get(2)?, fields.get(3)?);
+        let flags = u16::from_str_radix(flags, 16).ok()?;
+        if *destination == "00000000" && flags & RTF_GATEWAY != 0 {
+            let gateway = u32::from_str_radix(gateway, 16).ok()?;
+            Some(Ipv4Addr::from(gateway.to_ne_bytes()))
+        } else {
+            None
+        }
+    })
+}
+
+//==================================================================================================
+// Local Discovery
+//==================================================================================================
+
+/// Advertising and discovering ships with mDNS, as defined by [RFC 6762], and DNS-SD, as defined
+/// by [RFC 6763].
+///
+/// [RFC 6762]: https://www.rfc-editor.org/rfc/rfc6762
+/// [RFC 6763]: https://www.rfc-editor.org/rfc/rfc6763
+mod mdns {
+    use super::*;
+    use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+    use std::net;
+
+    /// The multicast address mDNS messages are sent to.
+    pub(super) const ADDR: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(224, 0, 0, 251), 5353);
+
+    /// The longest mDNS message that's received.
+    pub(super) const MAX_MESSAGE_LEN: usize = 9000;
+
+    /// The name of the service ships are advertised as instances of.
+    const SERVICE: &str = "_urbit-ames._udp.local";
+
+    /// How long other hosts may cache an advertisement in seconds.
+    const TTL: u32 = 120;
+
+    /// The type of a `PTR` record.
+    const PTR: u16 = 12;
+
+    /// The type of an `SRV` record.
+    const SRV: u16 = 33;
+
+    /// The type of a question that asks for records of any type.
+    const ANY: u16 = 255;
+
+    /// The class of records on the internet.
+    const IN: u16 = 1;
+
+    /// The flag of a record's class that replaces the records of the same name in caches.
+    const CACHE_FLUSH: u16 = 0x8000;
+
+    /// A received mDNS message.
+    #[derive(Debug, Eq, PartialEq)]
+    pub(super) enum Message {
+        /// A query, and whether it asks for instances of the service.
+        Query(bool),
+
+        /// A response, and the ships it advertises with their ports.
+        Response(Vec<(u128, u16)>),
+    }
+
+    /// Binds a socket to the mDNS port that shares the port with other mDNS responders on the
+    /// host, and joins the mDNS multicast group.
+    pub(super) fn bind() -> io::Result<UdpSocket> {
+        // The standard library can't bind a socket to a port that's already bound, which the
+        // mDNS port usually is.
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        let addr = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, ADDR.port());
+        socket.bind(&SockAddr::from(addr))?;
+        let udp = net::UdpSocket::from(socket);
+        udp.join_multicast_v4(ADDR.ip(), &Ipv4Addr::UNSPECIFIED)?;
+        udp.set_multicast_loop_v4(true)?;
+        udp.set_nonblocking(true)?;
+        UdpSocket::from_std(udp)
+    }
+
+    /// Returns a query for instances of the service.
+    pub(super) fn query() -> Vec<u8> {
+        let mut msg = header(0, 1, 0);
+        encode_name(&mut msg, SERVICE);
+        msg.extend_from_slice(&PTR.to_be_bytes());
+        msg.extend_from_slice(&IN.to_be_bytes());
+        msg
+    }
+
+    /// Returns a response that advertises `ship` at `port`.
+    pub(super) fn announcement(ship: u128, port: u16) -> Vec<u8> {
+        let instance = format!("{:x}.{}", ship, SERVICE);
+        let mut msg = header(0x8400, 0, 2);
+
+        let mut rdata = Vec::new();
+        encode_name(&mut rdata, &instance);
+        encode_record(&mut msg, SERVICE, PTR, IN, &rdata);
+
+        // Priority and weight, which don't matter for a single instance.
+        let mut rdata = vec![0; 4];
+        rdata.extend_from_slice(&port.to_be_bytes());
+        encode_name(&mut rdata, &format!("{:x}.local", ship));
+        encode_record(&mut msg, &instance, SRV, IN | CACHE_FLUSH, &rdata);
+        msg
+    }
+
+    /// Parses a received mDNS message, returning `None` if it's malformed.
+    pub(super) fn parse(msg: &[u8]) -> Option<Message> {
+        let flags = u16_at(msg, 2)?;
+        let questions = u16_at(msg, 4)?;
+        let records = [6, 8, 10]
+            .into_iter()
+            .map(|pos| u16_at(msg, pos).map(usize::from))
+            .sum::<Option<usize>>()?;
+        let mut pos = 12;
+        let mut asks_for_service = false;
+        for _ in 0..questions {
+            let (name, next) = read_name(msg, pos)?;
+            let qtype = u16_at(msg, next)?;
+            pos = next + 4;
+            if (qtype == PTR || qtype == ANY) && name == SERVICE {
+                asks_for_service = true;
+            }
+        }
+        if flags & 0x8000 == 0 {
+            return Some(Message::Query(asks_for_service));
+        }
+        let mut ships = Vec::new();
+        for _ in 0..records {
+            let (name, next) = read_name(msg, pos)?;
+            let rtype = u16_at(msg, next)?;
+            let withdrawn = msg.get(next + 4..next + 8)? == [0; 4];
+            let rdata_len = usize::from(u16_at(msg, next + 8)?);
+            let rdata = next + 10;
+            pos = rdata + rdata_len;
+            if pos > msg.len() {
+                return None;
+            }
+            // A record with a TTL of 0 withdraws an advertisement.
+            if rtype != SRV || withdrawn || rdata_len < 6 {
+                continue;
+            }
+            let instance = name
+                .strip_suffix(SERVICE)
+                .and_then(|name| name.strip_suffix('.'))
+                .filter(|label| {
+                    !label.is_empty()
+                        && label.len() <= 32
+                        && label.bytes().all(|b| b.is_ascii_hexdigit())
+                });
+            if let Some(ship) = instance.and_then(|label| u128::from_str_radix(label, 16).ok()) {
+                ships.push((ship, u16_at(msg, rdata + 4)?));
+            }
+        }
+        Some(Message::Response(ships))
+    }
+
+    /// Returns the header of a message with `flags`, `questions` questions, and `answers`
+    /// answers.
+    fn header(flags: u16, questions: u16, answers: u16) -> Vec<u8> {
+        let mut msg = vec![0; 2];
+        for field in [flags, questions, answers, 0, 0] {
+            msg.extend_from_slice(&field.to_be_bytes());
+        }
+        msg
+    }
+
+    /// Appends a record to `msg`.
+    fn encode_record(msg: &mut Vec<u8>, name: &str, rtype: u16, class: u16, rdata: &[u8]) {
+        encode_name(msg, name);
+        msg.extend_from_slice(&rtype.to_be_bytes());
+        msg.extend_from_slice(&class.to_be_bytes());
+        msg.extend_from_slice(&TTL.to_be_bytes());
+        msg.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        msg.extend_from_slice(rdata);
+    }
+
+    /// Appends `name`, a dot-separated list of labels, to `msg`.
+    fn encode_name(msg: &mut Vec<u8>, name: &str) {
+        for label in name.split('.') {
+            msg.push(label.len() as u8);
+            msg.extend_from_slice(label.as_bytes());
+        }
+        msg.push(0);
+    }
+
+    /// Reads the name at `pos` in `msg`, following compression pointers, returning the name in
+    /// lowercase and the position after it.
+    fn read_name(msg: &[u8], mut pos: usize) -> Option<(String, usize)> {
+        let mut labels = Vec::new();
+        let mut end = None;
+        // Pointers must point backwards, which keeps them from looping.
+        let mut limit = pos;
+        loop {
+            let len = *msg.get(pos)?;
+            match len {
+                0 => {
+                    let name = labels.join(".").to_ascii_lowercase();
+                    return Some((name, end.unwrap_or(pos + 1)));
+                }
+                len if len & 0xc0 == 0xc0 => {
+                    let target = usize::from(u16_at(msg, pos)? & 0x3fff);
+                    if target >= limit {
+                        return None;
+                    }
+                    end.get_or_insert(pos + 2);
+                    pos = target;
+                    limit = target;
+                }
+                len if len & 0xc0 == 0 => {
+                    let label = msg.get(pos + 1..pos + 1 + usize::from(len))?;
+                    labels.push(String::from_utf8_lossy(label).into_owned());
+                    pos += 1 + usize::from(len);
+                }
+                _ => return None,
+            }
+        }
+    }
+
+    /// Reads the big-endian 16-bit integer at `pos` in `msg`.
+    fn u16_at(msg: &[u8], pos: usize) -> Option<u16> {
+        let bytes = msg.get(pos..pos + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+//==================================================================================================
+// Miscellaneous
+//==================================================================================================
+
+/// The source or destination of a packet.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Lane(SocketAddrV4);
+
+impl TryFrom<&Noun> for Lane {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// [<ip> <port>]
+    /// ```
+    ///
+    /// where `<ip>` is an IPv4 address as a 32-bit atom, and `<port>` is a UDP port.
+    fn try_from(lane: &Noun) -> Result<Self, Self::Error> {
+        if let Noun::Cell(lane) = lane {
+            match (lane.head_ref(), lane.tail_ref()) {
+                (Noun::Atom(ip), Noun::Atom(port)) => Ok(Self(SocketAddrV4::new(
+                    ipv4_from_atom(ip)?,
+                    port_from_atom(port)?,
+                ))),
+                _ => Err(convert::Error::UnexpectedCell),
+            }
+        } else {
+            Err(convert::Error::UnexpectedAtom)
+        }
+    }
+}
+
+impl From<Lane> for Noun {
+    fn from(lane: Lane) -> Self {
+        Noun::from(Cell::from([
+            Atom::from(u64::from(u32::from(*lane.0.ip()))),
+            Atom::from(u64::from(lane.0.port())),
+        ]))
+    }
+}
+
+/// Converts a 32-bit atom into an IPv4 address.
+fn ipv4_from_atom(ip: &Atom) -> Result<Ipv4Addr, convert::Error> {
+    ip.as_u64()
+        .and_then(|ip| u32::try_from(ip).ok())
+        .map(Ipv4Addr::from)
+        .ok_or(convert::Error::AtomToUint)
+}
+
+/// Converts an atom into a ship, i.e. an `@p`.
+fn ship_from_atom(ship: &Atom) -> Result<u128, convert::Error> {
+    let mut bytes = ship.to_vec();
+    while bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+    if bytes.len() > 16 {
+        return Err(convert::Error::AtomToUint);
+    }
+    let mut ship = [0; 16];
+    ship[..bytes.len()].copy_from_slice(&bytes);
+    Ok(u128::from_le_bytes(ship))
+}
+
+/// Converts a ship, i.e. an `@p`, into an atom.
+fn ship_to_atom(ship: u128) -> Atom {
+    let mut bytes = ship.to_le_bytes().to_vec();
+    while bytes.last() == Some(&0) {
+        bytes.pop();
+    }
+    Atom::from(bytes)
+}
+
+/// Converts an atom into a UDP port.
+fn port_from_atom(port: &Atom) -> Result<u16, convert::Error> {
+    port.as_u64()
+        .and_then(|port| u16::try_from(port).ok())
+        .ok_or(convert::Error::AtomToUint)
+}
+
+/// Converts a `<len>` and `<packet>` pair into the bytes of the packet, retaining trailing null
+/// bytes.
+fn packet_from_noun(len: &Noun, packet: &Noun) -> Result<Vec<u8>, convert::Error> {
+    if let (Noun::Atom(len), Noun::Atom(packet)) = (len, packet) {
+        let len = len
+            .as_u64()
+            .and_then(|len| usize::try_from(len).ok())
+            .filter(|len| *len <= Ames::MAX_PACKET_LEN)
+            .ok_or(convert::Error::AtomToUint)?;
+        let mut packet = packet.to_vec();
+        if packet.len() > len {
+            return Err(convert::Error::ImplType);
+        }
+        packet.resize(len, 0);
+        Ok(packet)
+    } else {
+        Err(convert::Error::UnexpectedCell)
+    }
+}
+
+/// Builds the response to a successful [`Bind`] request.
+fn bound(port: u16) -> Noun {
+    Noun::from(Cell::from([
+        Atom::from("bound"),
+        Atom::from(u64::from(port)),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::{sync::mpsc, time};
+
+    /// Returns the lane of a socket bound to the loopback interface.
+    fn loopback_lane(udp: &UdpSocket) -> Lane {
+        match udp.local_addr().expect("local address") {
+            SocketAddr::V4(addr) => Lane(SocketAddrV4::new(Ipv4Addr::LOCALHOST, addr.port())),
+            SocketAddr::V6(_) => panic!("socket is bound to an IPv6 address"),
+        }
+    }
+
+    /// Receives the next response from the driver, failing if none arrives in time.
+    async fn recv_response(output_rx: &mut Receiver<Noun>) -> Noun {
+        time::timeout(Duration::from_secs(5), output_rx.recv())
+            .await
+            .expect("response in time")
+            .expect("response")
+    }
+
+    /// Returns the port of a `%bound` response.
+    fn bound_port(resp: &Noun) -> u16 {
+        let resp = match resp {
+            Noun::Cell(resp) => resp,
+            Noun::Atom(_) => panic!("response is an atom"),
+        };
+        let [tag, port] = resp.to_array::<2>().expect("response to array");
+        match (&*tag, &*port) {
+            (Noun::Atom(tag), Noun::Atom(port)) => {
+                assert_eq!(atom_as_str(tag).expect("tag"), "bound");
+                port_from_atom(port).expect("port")
+            }
+            _ => panic!("malformed %bound response"),
+        }
+    }
+
+    /// Tests the `TryFrom<&Noun>` and `From<Lane>` implementations for [`Lane`].
+    #[test]
+    fn lane_from_noun() {
+        // 1.2.3.4:31337.
+        {
+            let noun = Noun::from(Cell::from([
+                Atom::from(0x01020304u64),
+                Atom::from(31337u64),
+            ]));
+            let lane = Lane::try_from(&noun).expect("&Noun to Lane");
+            assert_eq!(lane.0, "1.2.3.4:31337".parse().unwrap());
+            assert_eq!(Noun::from(lane), noun);
+        }
+
+        // Malformed lane: address doesn't fit in 32 bits.
+        {
+            let noun = Noun::from(Cell::from([Atom::from(1u64 << 32), Atom::from(80u64)]));
+            assert!(Lane::try_from(&noun).is_err());
+        }
+
+        // Malformed lane: port doesn't fit in 16 bits.
+        {
+            let noun = Noun::from(Cell::from([
+                Atom::from(0x7f000001u64),
+                Atom::from(65536u64),
+            ]));
+            assert!(Lane::try_from(&noun).is_err());
+        }
+    }
+
+    /// Tests the `TryFrom<&Noun>` implementation for [`SendPacket`].
+    #[test]
+    fn send_packet_from_noun() {
+        // Trailing null bytes are retained.
+        {
+            let noun = Noun::from(Cell::from([
+                Noun::from(Cell::from([Atom::from(0x7f000001u64), Atom::from(4000u64)])),
+                Noun::from(Atom::from(4u64)),
+                Noun::from(Atom::from(vec![0xde_u8, 0xad])),
+            ]));
+            let req = SendPacket::try_from(&noun).expect("&Noun to SendPacket");
+            assert_eq!(req.lane.0, "127.0.0.1:4000".parse().unwrap());
+            assert_eq!(req.packet, vec![0xde, 0xad, 0, 0]);
+        }
+
+        // Malformed request: packet is longer than its length.
+        {
+            let noun = Noun::from(Cell::from([
+                Noun::from(Cell::from([Atom::from(0x7f000001u64), Atom::from(4000u64)])),
+                Noun::from(Atom::from(1u64)),
+                Noun::from(Atom::from(vec![0xde_u8, 0xad])),
+            ]));
+            assert!(SendPacket::try_from(&noun).is_err());
+        }
+
+        // Malformed request: packet is too large for a UDP packet.
+        {
+            let noun = Noun::from(Cell::from([
+                Noun::from(Cell::from([Atom::from(0x7f000001u64), Atom::from(4000u64)])),
+                Noun::from(Atom::from(65508u64)),
+                Noun::from(Atom::from(vec![1_u8])),
+            ]));
+            assert!(SendPacket::try_from(&noun).is_err());
+        }
+    }
+
+    /// Tests the `FromStr` implementations for [`RateLimit`] and [`Networks`].
+    #[test]
+    fn filter_config_from_str() {
+        assert_eq!(
+            RateLimit::from_str("per-source=100, burst=500"),
+            Ok(RateLimit {
+                per_source: Some(100),
+                burst: Some(500),
+            })
+        );
+        assert_eq!(
+            RateLimit::from_str("per-source=none"),
+            Ok(RateLimit::default())
+        );
+        assert_eq!(RateLimit::from_str("per-source=0"), Err(()));
+        assert_eq!(RateLimit::from_str("per-source=00"), Err(()));
+        assert_eq!(RateLimit::from_str("burst=0"), Err(()));
+        assert_eq!(RateLimit::from_str("global=100"), Err(()));
+
+        assert_eq!(
+            Networks::from_str("10.0.0.0/8, 192.0.2.7"),
+            Ok(Networks(vec![
+                Network {
+                    ip: Ipv4Addr::new(10, 0, 0, 0),
+                    prefix_len: 8,
+                },
+                Network {
+                    ip: Ipv4Addr::new(192, 0, 2, 7),
+                    prefix_len: 32,
+                },
+            ]))
+        );
+        assert_eq!(Networks::from_str("10.0.0.0/33"), Err(()));
+        assert_eq!(Networks::from_str("2001:db8::/32"), Err(()));
+    }
+
+    /// Tests that [`PacketFilter`] drops malformed packets, packets from blocked networks, and
+    /// packets beyond a source's rate limit.
+    #[test]
+    fn filter_packets() {
+        let filter = PacketFilter::new(&Config {
+            rate_limit: RateLimit {
+                per_source: Some(1),
+                burst: Some(3),
+            },
+            blocked: Networks::from_str("10.0.0.0/8").unwrap(),
+            ..Config::default()
+        });
+        let packet = [0; 8];
+        let src: SocketAddrV4 = "192.0.2.1:4000".parse().unwrap();
+
+        assert!(filter.admit(&packet[..3], src).is_err());
+        assert!(filter
+            .admit(&packet, "192.0.2.1:0".parse().unwrap())
+            .is_err());
+        assert!(filter
+            .admit(&packet, "255.255.255.255:4000".parse().unwrap())
+            .is_err());
+        assert!(filter
+            .admit(&packet, "10.1.2.3:4000".parse().unwrap())
+            .is_err());
+
+        // A source may send a burst of packets, whichever ports they're from, but no more.
+        assert!(filter.admit(&packet, src).is_ok());
+        assert!(filter.admit(&packet, src).is_ok());
+        assert!(filter
+            .admit(&packet, "192.0.2.1:4001".parse().unwrap())
+            .is_ok());
+        assert!(filter.admit(&packet, src).is_err());
+        // Other sources have their own limits.
+        assert!(filter
+            .admit(&packet, "192.0.2.2:4000".parse().unwrap())
+            .is_ok());
+
+        // Unblocking a network admits its packets.
+        filter.blocked.lock().unwrap().clear();
+        assert!(filter
+            .admit(&packet, "10.1.2.3:4000".parse().unwrap())
+            .is_ok());
+
+        let stats = &filter.stats;
+        assert_eq!(stats.received.load(Ordering::Relaxed), 10);
+        assert_eq!(stats.malformed.load(Ordering::Relaxed), 3);
+        assert_eq!(stats.blocked.load(Ordering::Relaxed), 1);
+        assert_eq!(stats.rate_limited.load(Ordering::Relaxed), 1);
+    }
+
+    /// Tests the `FromStr` implementation for [`Pacing`].
+    #[test]
+    fn pacing_from_str() {
+        assert_eq!(
+            Pacing::from_str("rate=2000, burst=100, queue=50"),
+            Ok(Pacing {
+                rate: Some(2000),
+                burst: Some(100),
+                max_queue_len: 50,
+            })
+        );
+        assert_eq!(Pacing::from_str("rate=none"), Ok(Pacing::default()));
+        assert_eq!(Pacing::from_str(""), Ok(Pacing::default()));
+        assert_eq!(Pacing::from_str("rate=0"), Err(()));
+        assert_eq!(Pacing::from_str("rate=00"), Err(()));
+        assert_eq!(Pacing::from_str("burst=0"), Err(()));
+        assert_eq!(Pacing::from_str("queue=0"), Err(()));
+        assert_eq!(Pacing::from_str("per-peer=10"), Err(()));
+    }
+
+    /// Tests that [`Pacer`] takes turns between destinations, limits the length of each
+    /// destination's queue, and waits between packets beyond a burst.
+    #[test]
+    fn pace_packets() {
+        let pacer = Pacer::new(
+            10,
+            &Pacing {
+                rate: Some(10),
+                burst: Some(2),
+                max_queue_len: 3,
+            },
+        );
+        let a: SocketAddrV4 = "192.0.2.1:4000".parse().unwrap();
+        let b: SocketAddrV4 = "192.0.2.2:4000".parse().unwrap();
+
+        for i in 0..3 {
+            assert!(pacer.push(a, vec![i]));
+        }
+        assert!(!pacer.push(a, vec![3]));
+        assert!(pacer.push(b, vec![10]));
+        assert_eq!(pacer.len(), 4);
+
+        // `b` doesn't wait for all of `a`'s packets.
+        assert_eq!(pacer.try_pop(), Some((a, vec![0])));
+        assert_eq!(pacer.try_pop(), Some((b, vec![10])));
+        assert_eq!(pacer.try_pop(), Some((a, vec![1])));
+        assert_eq!(pacer.try_pop(), Some((a, vec![2])));
+        assert_eq!(pacer.try_pop(), None);
+        assert_eq!(pacer.len(), 0);
+
+        // A destination's queue has room again once its packets are sent.
+        assert!(pacer.push(a, vec![3]));
+
+        // A burst of packets is sent right away, but the next packet waits.
+        assert_eq!(pacer.charge(), Duration::ZERO);
+        assert_eq!(pacer.charge(), Duration::ZERO);
+        let wait = pacer.charge();
+        assert!(wait > Duration::from_millis(50) && wait <= Duration::from_millis(100));
+    }
+
+    /// Tests the `TryFrom<&Noun>` implementation for [`Block`].
+    #[test]
+    fn block_from_noun() {
+        let noun = Noun::from(Cell::from([
+            Noun::from(Cell::from([Atom::from(0x0a000000u64), Atom::from(8u64)])),
+            Noun::from(Cell::from([Atom::from(0xc0000207u64), Atom::from(32u64)])),
+            Noun::null(),
+        ]));
+        let req = Block::try_from(&noun).expect("&Noun to Block");
+        assert_eq!(
+            req.networks,
+            Networks::from_str("10.0.0.0/8,192.0.2.7").unwrap().0
+        );
+        assert!(Block::try_from(&Noun::null())
+            .expect("&Noun to Block")
+            .networks
+            .is_empty());
+
+        // Malformed request: prefix is longer than 32 bits.
+        let noun = Noun::from(Cell::from([
+            Noun::from(Cell::from([Atom::from(0x0a000000u64), Atom::from(33u64)])),
+            Noun::null(),
+        ]));
+        assert!(Block::try_from(&noun).is_err());
+    }
+
+    /// Tests the `TryFrom<&Noun>` implementation for [`Discover`].
+    #[test]
+    fn discover_from_noun() {
+        let discover = |tag: &str, data: Noun| {
+            Discover::try_from(&Noun::from(Cell::from([Noun::from(Atom::from(tag)), data])))
+        };
+
+        // ~marzod.
+        assert_eq!(
+            discover("start", Noun::from(Atom::from(0x100u64))),
+            Ok(Discover::Start(0x100))
+        );
+        // A comet.
+        let comet = (1u128 << 127) | 0x1234;
+        assert_eq!(
+            discover("start", Noun::from(ship_to_atom(comet))),
+            Ok(Discover::Start(comet))
+        );
+        assert_eq!(discover("stop", Noun::null()), Ok(Discover::Stop));
+
+        // Malformed: ship doesn't fit in 128 bits.
+        let mut bytes = vec![0; 16];
+        bytes.push(1);
+        assert_eq!(
+            discover("start", Noun::from(Atom::from(bytes))),
+            Err(convert::Error::AtomToUint)
+        );
+
+        // Malformed: unknown tag, and `%stop` with data.
+        assert_eq!(
+            discover("pause", Noun::null()),
+            Err(convert::Error::ImplType)
+        );
+        assert_eq!(
+            discover("stop", Noun::from(Atom::from(1u64))),
+            Err(convert::Error::ExpectedNull)
+        );
+    }
+
+    /// Tests the `FromStr` implementation for [`PortMapping`].
+    #[test]
+    fn port_mapping_from_str() {
+        assert_eq!(PortMapping::from_str("none"), Ok(PortMapping::None));
+        assert_eq!(PortMapping::from_str("auto"), Ok(PortMapping::Auto));
+        assert_eq!(PortMapping::from_str("nat-pmp"), Ok(PortMapping::NatPmp));
+        assert_eq!(PortMapping::from_str("upnp"), Ok(PortMapping::Upnp));
+        assert_eq!(PortMapping::from_str("pcp"), Err(()));
+    }
+
+    /// Tests [`parse_default_gateway()`].
+    #[test]
+    fn default_gateway_from_routes() {
+        let routes = if cfg!(target_endian = "little") {
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+             eth0\t0002A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0\n\
+             eth0\t00000000\t0102A8C0\t0003\t0\t0\t0\t00000000\t0\t0\t0\n"
+        } else {
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT\n\
+             eth0\tC0A80200\t00000000\t0001\t0\t0\t0\tFFFFFF00\t0\t0\t0\n\
+             eth0\t00000000\tC0A80201\t0003\t0\t0\t0\t00000000\t0\t0\t0\n"
+        };
+        assert_eq!(
+            parse_default_gateway(routes),
+            Some(Ipv4Addr::new(192, 168, 2, 1))
+        );
+        assert_eq!(parse_default_gateway(routes.lines().next().unwrap()), None);
+    }
+
+    /// Tests the parsing of UPnP device descriptions and URLs.
+    #[test]
+    fn parse_upnp_descriptions() {
+        let desc = r#"<?xml version="1.0"?>
+<root xmlns="urn:schemas-upnp-org:device-1-0">
+  <device>
+    <serviceList>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:Layer3Forwarding:1</serviceType>
+        <controlURL>/ctl/L3F</controlURL>
+      </service>
+      <service>
+        <serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType>
+        <controlURL>/ctl/IPConn</controlURL>
+      </service>
+    </serviceList>
+  </device>
+</root>"#;
+        assert_eq!(
+            upnp::wan_connection_service(desc),
+            Some((
+                "urn:schemas-upnp-org:service:WANIPConnection:1",
+                "/ctl/IPConn"
+            ))
+        );
+        assert_eq!(upnp::wan_connection_service("<root></root>"), None);
+
+        let (addr, path) =
+            upnp::parse_http_url("http://192.168.1.1:5000/rootDesc.xml").expect("parse URL");
+        assert_eq!(addr, "192.168.1.1:5000".parse().unwrap());
+        assert_eq!(path, "/rootDesc.xml");
+        let (addr, path) = upnp::parse_http_url("http://10.0.0.1").expect("parse URL");
+        assert_eq!(addr, "10.0.0.1:80".parse().unwrap());
+        assert_eq!(path, "/");
+        assert!(upnp::parse_http_url("https://10.0.0.1/desc.xml").is_err());
+        assert!(upnp::parse_http_url("http://router.local/desc.xml").is_err());
+    }
+
+    /// Forwards a port with a NAT-PMP server.
+    #[tokio::test]
+    async fn nat_pmp_mapping() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.expect("bind server");
+        let server_addr = loopback_lane(&server).0;
+        let serve = async {
+            let mut buf = [0; 16];
+            // The public address request.
+            let (len, client) = server.recv_from(&mut buf).await.expect("receive request");
+            assert_eq!(&buf[..len], &[0, 0]);
+            server
+                .send_to(&[0, 128, 0, 0, 0, 0, 0, 1, 203, 0, 113, 5], client)
+                .await
+                .expect("send response");
+            // The mapping request, which is answered with a different public port.
+            let (len, client) = server.recv_from(&mut buf).await.expect("receive request");
+            assert_eq!(
+                &buf[..len],
+                &[0, 1, 0, 0, 0x9c, 0x40, 0x9c, 0x40, 0, 0, 0x0e, 0x10]
+            );
+            server
+                .send_to(
+                    &[
+                        0, 129, 0, 0, 0, 0, 0, 1, 0x9c, 0x40, 0x9c, 0x41, 0, 0, 0x0e, 0x10,
+                    ],
+                    client,
+                )
+                .await
+                .expect("send response");
+        };
+        let (public_addr, ()) = tokio::join!(
+            nat_pmp::map(server_addr, 40000, Duration::from_secs(3600)),
+            serve
+        );
+        assert_eq!(
+            public_addr.expect("map port"),
+            "203.0.113.5:40001".parse().unwrap()
+        );
+
+        // A refused request fails.
+        let serve = async {
+            let mut buf = [0; 16];
+            let (_len, client) = server.recv_from(&mut buf).await.expect("receive request");
+            server
+                .send_to(&[0, 128, 0, 2, 0, 0, 0, 1, 0, 0, 0, 0], client)
+                .await
+                .expect("send response");
+        };
+        let (public_addr, ()) = tokio::join!(
+            nat_pmp::map(server_addr, 40000, Duration::from_secs(3600)),
+            serve
+        );
+        assert!(public_addr.is_err());
+    }
+
+    /// Tests [`StunMessage::parse()`] and [`StunMessage::encode()`].
+    #[test]
+    fn stun_messages() {
+        // A response with a `SOFTWARE` attribute, whose value is padded, followed by an
+        // `XOR-MAPPED-ADDRESS` attribute, from the sample response of RFC 5769.
+        {
+            let id = [
+                0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae,
+            ];
+            let mut packet = vec![0x01, 0x01, 0x00, 0x1c, 0x21, 0x12, 0xa4, 0x42];
+            packet.extend_from_slice(&id);
+            packet.extend_from_slice(&[0x80, 0x22, 0x00, 0x0b]);
+            packet.extend_from_slice(b"test vector ");
+            packet.extend_from_slice(&[0x00, 0x20, 0x00, 0x08, 0x00, 0x01, 0xa1, 0x47]);
+            packet.extend_from_slice(&[0xe1, 0x12, 0xa6, 0x43]);
+            assert_eq!(
+                StunMessage::parse(&packet),
+                Some(StunMessage::Response(
+                    id,
+                    Some("192.0.2.1:32853".parse().unwrap())
+                ))
+            );
+
+            // The length doesn't match the packet.
+            packet.push(0);
+            assert_eq!(StunMessage::parse(&packet), None);
+        }
+
+        // Encoded messages parse as themselves.
+        {
+            let id = [7; 12];
+            let req = StunMessage::Request(id);
+            let packet = req.encode();
+            assert_eq!(packet.len(), 28);
+            assert_eq!(StunMessage::parse(&packet), Some(req));
+
+            let resp = StunMessage::Response(id, Some("203.0.113.9:4321".parse().unwrap()));
+            let packet = resp.encode();
+            assert_eq!(packet.len(), 40);
+            assert_eq!(StunMessage::parse(&packet), Some(resp));
+        }
+
+        // Packets that aren't STUN messages.
+        {
+            assert_eq!(StunMessage::parse(&[0; 19]), None);
+            assert_eq!(StunMessage::parse(&[0; 20]), None);
+        }
+
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    /// Tests [`mdns::parse()`], [`mdns::query()`], and [`mdns::announcement()`].
+    #[test]
+    fn mdns_messages() {
+        assert_eq!(
+            mdns::parse(&mdns::query()),
+            Some(mdns::Message::Query(true))
+        );
+        assert_eq!(
+            mdns::parse(&mdns::announcement(0x100, 31337)),
+            Some(mdns::Message::Response(vec![(0x100, 31337)]))
+        );
+
+        // A query for another service.
+        {
+            let mut msg = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+            msg.extend_from_slice(b"\x05_http\x04_tcp\x05local\x00\x00\x0c\x00\x01");
+            assert_eq!(mdns::parse(&msg), Some(mdns::Message::Query(false)));
+        }
+
+        // A response whose `SRV` record's name points to the service name of its `PTR` record, in
+        // uppercase.
+        {
+            let mut msg = vec![0, 0, 0x84, 0, 0, 0, 0, 2, 0, 0, 0, 0];
+            msg.extend_from_slice(b"\x0b_URBIT-AMES\x04_udp\x05local\x00");
+            msg.extend_from_slice(&[0, 12, 0, 1, 0, 0, 0, 120, 0, 6]);
+            msg.extend_from_slice(b"\x03abc\xc0\x0c");
+            msg.extend_from_slice(b"\x03abc\xc0\x0c");
+            msg.extend_from_slice(&[0, 33, 0x80, 1, 0, 0, 0, 120, 0, 8, 0, 0, 0, 0, 0x7a, 0x69]);
+            msg.extend_from_slice(b"\xc0\x0c");
+            assert_eq!(
+                mdns::parse(&msg),
+                Some(mdns::Message::Response(vec![(0xabc, 31337)]))
+            );
+
+            // Withdrawn with a TTL of 0.
+            let ttl = msg.len() - 14;
+            msg[ttl..ttl + 4].copy_from_slice(&[0; 4]);
+            assert_eq!(mdns::parse(&msg), Some(mdns::Message::Response(vec![])));
+
+            // A pointer that doesn't point backwards.
+            let ptr = msg.len() - 19;
+            msg[ptr] = 0xff;
+            assert_eq!(mdns::parse(&msg), None);
+
+            // Cut off.
+            assert_eq!(mdns::parse(&msg[..40]), None);
+        }
+    }
+
+    /// Discovers the driver's public address with a STUN server, and answers a STUN request.
+    #[tokio::test]
+    async fn discover_public_addr() {
+        let driver = Ames::with_config(Config {
+            local: true,
+            stun_interval: Duration::from_millis(50),
+            ..Config::default()
+        });
+        let (input_tx, input_rx) = mpsc::channel(8);
+        let (output_tx, mut output_rx) = mpsc::channel(8);
+        let task = driver.handle_requests(input_rx, output_tx);
+        let port = bound_port(&recv_response(&mut output_rx).await);
+
+        let server = UdpSocket::bind("127.0.0.1:0").await.expect("bind server");
+        let req = Noun::from(Cell::from([
+            Noun::from(Atom::from("stun")),
+            Noun::from(Atom::from("start")),
+            Noun::from(loopback_lane(&server)),
+        ]));
+        input_tx.send(req).await.expect("send request");
+
+        // Answers the next binding request from the driver with `public_addr`.
+        let answer = |public_addr: &'static str| {
+            let server = &server;
+            async move {
+                let mut buf = [0; 64];
+                let (len, addr) = server.recv_from(&mut buf).await.expect("receive request");
+                assert_eq!(addr.port(), port);
+                let id = match StunMessage::parse(&buf[..len]) {
+                    Some(StunMessage::Request(id)) => id,
+                    msg => panic!("unexpected STUN message {:?}", msg),
+                };
+                let resp = StunMessage::Response(id, Some(public_addr.parse().unwrap()));
+                server
+                    .send_to(&resp.encode(), addr)
+                    .await
+                    .expect("send response");
+            }
+        };
+        let once = |public_addr: &str| {
+            let public_addr: SocketAddrV4 = public_addr.parse().unwrap();
+            Noun::from(Cell::from([
+                Noun::from(Atom::from("stun")),
+                Noun::from(Atom::from("once")),
+                Noun::from(Lane(public_addr)),
+            ]))
+        };
+
+        // The first public address is reported, as is a change, but not an unchanged address.
+        answer("198.51.100.7:40000").await;
+        assert_eq!(
+            recv_response(&mut output_rx).await,
+            once("198.51.100.7:40000")
+        );
+        answer("198.51.100.7:40000").await;
+        answer("198.51.100.7:40001").await;
+        assert_eq!(
+            recv_response(&mut output_rx).await,
+            once("198.51.100.7:40001")
+        );
+
+        // A STUN request sent to the driver is answered with the address it was sent from, and
+        // isn't forwarded as a packet.
+        let peer = UdpSocket::bind("127.0.0.1:0").await.expect("bind peer");
+        let id = [3; 12];
+        peer.send_to(&StunMessage::Request(id).encode(), ("127.0.0.1", port))
+            .await
+            .expect("send request");
+        let mut buf = [0; 64];
+        let (len, _addr) = time::timeout(Duration::from_secs(5), peer.recv_from(&mut buf))
+            .await
+            .expect("response in time")
+            .expect("receive response");
+        assert_eq!(
+            StunMessage::parse(&buf[..len]),
+            Some(StunMessage::Response(id, Some(loopback_lane(&peer).0)))
+        );
+
+        // A server that stops responding is reported once.
+        drop(server);
+        let fail = Noun::from(Cell::from([
+            Noun::from(Atom::from("stun")),
+            Noun::from(Atom::from("fail")),
+            Noun::null(),
+        ]));
+        assert_eq!(recv_response(&mut output_rx).await, fail);
+
+        drop(input_tx);
+        assert!(task.await.expect("handling task") == Status::Success);
+    }
+
+    /// Advertises two ships on the local network, which discover each other.
+    #[tokio::test]
+    async fn discover_local_ships() {
+        let (output_tx, mut output_rx) = mpsc::channel(8);
+        let interval = Duration::from_millis(50);
+        let zod = tokio::spawn(Ames::discover_ships(0, 31337, interval, output_tx.clone()));
+        let nec = tokio::spawn(Ames::discover_ships(1, 31338, interval, output_tx));
+
+        // Each ship is found once by the other.
+        let mut found = Vec::new();
+        for _ in 0..2 {
+            let resp = match recv_response(&mut output_rx).await {
+                Noun::Cell(resp) => resp,
+                Noun::Atom(_) => panic!("response is an atom"),
+            };
+            let [tag, kind, ship, lane] = resp.to_array::<4>().expect("response to array");
+            assert_eq!(*tag, Noun::from(Atom::from("discover")));
+            assert_eq!(*kind, Noun::from(Atom::from("found")));
+            let ship = match &*ship {
+                Noun::Atom(ship) => ship_from_atom(ship).expect("ship"),
+                Noun::Cell(_) => panic!("ship is a cell"),
+            };
+            let lane = Lane::try_from(&*lane).expect("lane");
+            found.push((ship, lane.0.port()));
+        }
+        found.sort();
+        assert_eq!(found, [(0, 31337), (1, 31338)]);
+        assert!(
+            time::timeout(Duration::from_millis(200), output_rx.recv())
+                .await
+                .is_err(),
+            "ship found twice at the same lane"
+        );
+
+        zod.abort();
+        nec.abort();
+    }
+
+    /// Sends and receives packets, and rebinds the driver's socket to a new port.
+    #[tokio::test]
+    async fn send_and_hear_packets() {
+        let driver = Ames::with_config(Config {
+            local: true,
+            ..Config::default()
+        });
+        let (input_tx, input_rx) = mpsc::channel(8);
+        let (output_tx, mut output_rx) = mpsc::channel(8);
+        let task = driver.handle_requests(input_rx, output_tx);
+
+        // The driver binds its socket when it starts.
+        let port = bound_port(&recv_response(&mut output_rx).await);
+        assert_ne!(port, 0);
+
+        let peer = UdpSocket::bind("127.0.0.1:0").await.expect("bind peer");
+        let peer_lane = loopback_lane(&peer);
+
+        // Receive a packet with a trailing null byte.
+        peer.send_to(&[1, 2, 3, 4, 0], ("127.0.0.1", port))
+            .await
+            .expect("send packet");
+        let expected = Noun::from(Cell::from([
+            Noun::from(Atom::from("hear")),
+            Noun::from(peer_lane),
+            Noun::from(Atom::from(5u64)),
+            Noun::from(Atom::from(vec![1_u8, 2, 3, 4])),
+        ]));
+        assert_eq!(recv_response(&mut output_rx).await, expected);
+
+        // Send a packet.
+        let req = Noun::from(Cell::from([
+            Noun::from(Atom::from("send")),
+            Noun::from(peer_lane),
+            Noun::from(Atom::from(2u64)),
+            Noun::from(Atom::from(vec![9_u8])),
+        ]));
+        input_tx.send(req).await.expect("send request");
+        let mut buf = [0; 16];
+        let (len, addr) = time::timeout(Duration::from_secs(5), peer.recv_from(&mut buf))
+            .await
+            .expect("packet in time")
+            .expect("receive packet");
+        assert_eq!(&buf[..len], &[9, 0]);
+        assert_eq!(addr.port(), port);
+
+        // Binding to the current port leaves the socket as is.
+        let req = Noun::from(Cell::from([
+            Atom::from("bind"),
+            Atom::from(u64::from(port)),
+        ]));
+        input_tx.send(req).await.expect("send request");
+        assert_eq!(bound_port(&recv_response(&mut output_rx).await), port);
+
+        // Rebind to a new port.
+        let req = Noun::from(Cell::from([Atom::from("bind"), Atom::from(0u64)]));
+        input_tx.send(req).await.expect("send request");
+        let new_port = bound_port(&recv_response(&mut output_rx).await);
+        assert_ne!(new_port, port);
+        peer.send_to(&[7, 7, 7, 7], ("127.0.0.1", new_port))
+            .await
+            .expect("send packet");
+        let expected = Noun::from(Cell::from([
+            Noun::from(Atom::from("hear")),
+            Noun::from(peer_lane),
+            Noun::from(Atom::from(4u64)),
+            Noun::from(Atom::from(vec![7_u8, 7, 7, 7])),
+        ]));
+        assert_eq!(recv_response(&mut output_rx).await, expected);
+
+        drop(input_tx);
+        assert!(task.await.expect("handling task") == Status::Success);
+    }
+}
@@ -0,0 +1,587 @@
+//! Control socket driver.
+//!
+//! This module implements the control socket IO driver, which lets programs running on the same
+//! machine as the ship, e.g. command-line tools like `click`, run threads on the ship, scry into
+//! it, and inject events into it. The driver listens on a Unix socket, forwards each command it
+//! receives over the socket to the king, and returns the king's result to the program that sent
+//! the command. Because it listens on a Unix socket, the driver is only available on Unix
+//! platforms.
+//!
+//! Each request to the driver arrives as a length-encoded jammed (i.e. serialized) noun from some
+//! input source--`stdin`, a socket, etc. The driver understands one type of request:
+//! - return the result of a command to the program that sent it (`%give`).
+//!
+//! ### Commands
+//!
+//! Programs connect to the socket at `URBIT_IO_DRIVERS_CONN_SOCKET` (see [Configuration]), which
+//! only the driver's user may connect to. Each connection is a client with an ID, starting at 1,
+//! and generates a response of the form:
+//! ```text
+//! [%open <client>]
+//! ```
+//! When the connection closes, the driver generates a response of the form:
+//! ```text
+//! [%shut <client>]
+//! ```
+//! after which results for the client are dropped.
+//!
+//! A client sends commands to the driver over the connection, each of which is a version byte of
+//! 0, then the length of the payload as a little-endian 32-bit integer, then the payload, which is
+//! a jammed noun of the form:
+//! ```text
+//! [<id> <command>]
+//! ```
+//! where `<id>` is an atom the client chooses to match the command's result to the command, and
+//! `<command>` is one of:
+//! - `[%fyrd <data>]`: run a thread, as `<data>` describes to [Khan].
+//! - `[%peek <data>]`: scry into the ship at the path `<data>` describes.
+//! - `[%ovum <data>]`: inject the event `<data>`, e.g. a poke.
+//!
+//! Each command generates a response of the form:
+//! ```text
+//! [%conn <client> <id> <command>]
+//! ```
+//! where `<client>` is the ID of the client and `<id>` and `<command>` are as the client sent
+//! them. Commands with another tag aren't forwarded, and are answered with
+//! `[<id> %error 'unknown command']`. Payloads that aren't jammed `[<id> <command>]` cells are
+//! skipped, and the client is disconnected if it sends a message of another version or a payload
+//! longer than 128 MiB.
+//!
+//! ### `%give`
+//!
+//! A jammed noun representing a `%give` request has the following structure:
+//! ```text
+//! [%give <client> <id> <result>]
+//! ```
+//! where `<client>` is the ID of the client that sent the command, `<id>` is the command's ID, and
+//! `<result>` is any noun. The driver sends `[<id> <result>]` to the client, framed like the
+//! client's commands. `%give` requests don't generate responses, and are dropped if the client has
+//! disconnected.
+//!
+//! ### Configuration
+//!
+//! The driver is configured by environment variables, which are read when the driver is
+//! initialized:
+//! - `URBIT_IO_DRIVERS_CONN_SOCKET`: the path of the socket. Defaults to `.urb/conn.sock` relative
+//!   to the driver's working directory, which is the pier. Any file already at the path is
+//!   replaced, and the socket is removed when the driver exits.
+//!
+//! [Configuration]: #configuration
+//! [Khan]: https://developers.urbit.org/reference/arvo/khan/khan
+
+use crate::{atom_as_str, env_var, listen_unix, newt, Driver, Status};
+use log::{debug, error, info, warn};
+use noun::{
+    atom::Atom,
+    cell::Cell,
+    convert,
+    serdes::{Cue, Jam},
+    Noun,
+};
+use std::{
+    collections::HashMap,
+    fs, io, mem,
+    os::unix::net::UnixListener as StdUnixListener,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+};
+use tokio::{
+    io::{AsyncWriteExt, Stdin, Stdout},
+    net::{
+        unix::{OwnedReadHalf, OwnedWriteHalf},
+        UnixListener,
+    },
+    sync::mpsc::{self, Receiver, Sender},
+    task::JoinHandle,
+};
+
+//==================================================================================================
+// Request Types
+//==================================================================================================
+
+/// Requests that can be handled by the control socket driver.
+enum Request {
+    /// A request to return the result of a command to the client that sent it.
+    Give(Give),
+}
+
+impl_try_from_noun_for_request!(Request, "give" => Give);
+
+/// A request to return the result of a command to the client that sent it.
+struct Give {
+    /// The ID of the client.
+    client: u64,
+
+    /// The `[<id> <result>]` cell to send to the client.
+    msg: Noun,
+}
+
+impl TryFrom<&Noun> for Give {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// [<client> <id> <result>]
+    /// ```
+    ///
+    /// where `<client>` is the ID of the client, `<id>` is the ID of the command, and `<result>` is
+    /// any noun.
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        if let Noun::Cell(data) = data {
+            match (data.head_ref(), data.tail_ref()) {
+                (Noun::Atom(client), Noun::Cell(msg))
+                    if matches!(msg.head_ref(), Noun::Atom(_)) =>
+                {
+                    Ok(Self {
+                        client: client.as_u64().ok_or(convert::Error::AtomToUint)?,
+                        msg: data.tail_ref().clone(),
+                    })
+                }
+                (Noun::Atom(_), Noun::Cell(_)) | (Noun::Cell(_), _) => {
+                    Err(convert::Error::UnexpectedCell)
+                }
+                (Noun::Atom(_), Noun::Atom(_)) => Err(convert::Error::UnexpectedAtom),
+            }
+        } else {
+            Err(convert::Error::UnexpectedAtom)
+        }
+    }
+}
+
+/// A command from a client.
+#[derive(Debug, Eq, PartialEq)]
+enum Command {
+    /// Run a thread.
+    Fyrd,
+
+    /// Inject an event.
+    Ovum,
+
+    /// Scry into the ship.
+    Peek,
+}
+
+impl Command {
+    /// Identifies the `[<id> <command>]` noun a client sent, returning its ID and the kind of
+    /// command, or `None` if the tag of the command is unknown.
+    fn identify(msg: &Noun) -> Result<(&Atom, Option<Self>), convert::Error> {
+        if let Noun::Cell(msg) = msg {
+            match (msg.head_ref(), msg.tail_ref()) {
+                (Noun::Atom(id), Noun::Cell(cmd)) => {
+                    let cmd = match cmd.head_ref() {
+                        Noun::Atom(tag) => match atom_as_str(tag) {
+                            Ok("fyrd") => Some(Self::Fyrd),
+                            Ok("ovum") => Some(Self::Ovum),
+                            Ok("peek") => Some(Self::Peek),
+                            _ => None,
+                        },
+                        Noun::Cell(_) => None,
+                    };
+                    Ok((id, cmd))
+                }
+                (Noun::Atom(_), Noun::Atom(_)) => Err(convert::Error::UnexpectedAtom),
+                (Noun::Cell(_), _) => Err(convert::Error::UnexpectedCell),
+            }
+        } else {
+            Err(convert::Error::UnexpectedAtom)
+        }
+    }
+}
+
+//==================================================================================================
+// Driver
+//==================================================================================================
+
+/// Configuration for the control socket driver.
+struct Config {
+    /// The path of the socket.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_CONN_SOCKET`. Defaults to [`Config::SOCKET`].
+    socket: PathBuf,
+}
+
+impl Config {
+    /// The default path of the socket, relative to the pier.
+    const SOCKET: &'static str = ".urb/conn.sock";
+
+    /// Reads the configuration from the environment.
+    fn from_env() -> Self {
+        Self {
+            socket: env_var("URBIT_IO_DRIVERS_CONN_SOCKET")
+                .unwrap_or_else(|| PathBuf::from(Self::SOCKET)),
+        }
+    }
+}
+
+/// The control socket driver.
+pub struct Conn {
+    /// The socket, until the handling task takes it.
+    listener: Option<StdUnixListener>,
+
+    /// The driver configuration.
+    config: Config,
+}
+
+impl Conn {
+    /// Handles a [`Give`] request.
+    async fn give(clients: &Clients, req: Give) {
+        let msg_tx = clients
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&req.client)
+            .map(|client| client.msg_tx.clone());
+        match msg_tx {
+            Some(msg_tx) => {
+                if let Err(_msg) = msg_tx.send(req.msg).await {
+                    debug!(
+                        target: Self::name(),
+                        "dropped result to closed client {}", req.client
+                    );
+                }
+            }
+            None => debug!(
+                target: Self::name(),
+                "dropped result to unknown client {}", req.client
+            ),
+        }
+    }
+}
+
+/// Implements the [`Driver`] trait for the [`Conn`] driver.
+macro_rules! impl_driver {
+    ($input_src:ty, $output_sink:ty) => {
+        impl Driver<$input_src, $output_sink> for Conn {
+            fn new() -> Result<Self, Status> {
+                let config = Config::from_env();
+                let listener = match listen_unix(&config.socket) {
+                    Ok(listener) => listener,
+                    Err(err) => {
+                        error!(
+                            target: Self::name(),
+                            "failed to listen on {}: {}",
+                            config.socket.display(),
+                            err
+                        );
+                        return Err(Status::NoDriver);
+                    }
+                };
+                info!(
+                    target: Self::name(),
+                    "listening on {}",
+                    config.socket.display()
+                );
+                Ok(Self {
+                    listener: Some(listener),
+                    config,
+                })
+            }
+
+            fn name() -> &'static str {
+                "conn"
+            }
+
+            fn handle_requests(
+                mut self,
+                mut input_rx: Receiver<Noun>,
+                output_tx: Sender<Noun>,
+            ) -> JoinHandle<Status> {
+                let task = tokio::spawn(async move {
+                    let listener = match self.listener.take().map(UnixListener::from_std) {
+                        Some(Ok(listener)) => listener,
+                        Some(Err(err)) => {
+                            error!(target: Self::name(), "failed to register socket: {}", err);
+                            return Status::NoDriver;
+                        }
+                        None => return Status::NoDriver,
+                    };
+                    let clients = Arc::new(Clients::default());
+                    let accept_task =
+                        tokio::spawn(accept_clients(listener, clients.clone(), output_tx));
+                    while let Some(req) = input_rx.recv().await {
+                        match Request::try_from(req) {
+                            Ok(Request::Give(req)) => Self::give(&clients, req).await,
+                            Err(_) => {
+                                warn!(target: Self::name(), "skipping unidentifiable request");
+                            }
+                        }
+                    }
+                    accept_task.abort();
+                    let _ = accept_task.await;
+                    let _ = fs::remove_file(&self.config.socket);
+                    // Disconnect every client.
+                    let clients =
+                        mem::take(&mut *clients.lock().unwrap_or_else(PoisonError::into_inner));
+                    for (_id, client) in clients {
+                        client.close().await;
+                    }
+                    Status::Success
+                });
+                debug!(target: Self::name(), "spawned handling task");
+                task
+            }
+        }
+    };
+}
+
+impl_driver!(Stdin, Stdout);
+
+/// Provides an FFI-friendly interface for running the control socket driver with `stdin` as the
+/// input source and `stdout` as the output sink.
+#[no_mangle]
+pub extern "C" fn conn_run() -> Status {
+    match Conn::new() {
+        Ok(driver) => driver.run(tokio::io::stdin(), tokio::io::stdout()),
+        Err(status) => status,
+    }
+}
+
+//==================================================================================================
+// Clients
+//==================================================================================================
+
+/// The clients connected to the driver by ID.
+type Clients = Mutex<HashMap<u64, Client>>;
+
+/// A program connected to the driver's socket.
+struct Client {
+    /// Sends the results of commands to the client.
+    msg_tx: Sender<Noun>,
+
+    /// The tasks that read commands from and write results to the client.
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Client {
+    /// The number of results that can wait to be sent to a client.
+    const QUEUE_SIZE: usize = 32;
+
+    /// Stops the client's tasks and waits for them to stop.
+    async fn close(mut self) {
+        for task in mem::take(&mut self.tasks) {
+            task.abort();
+            let _ = task.await;
+        }
+    }
+}
+
+impl Drop for Client {
+    /// Stops the client's tasks.
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Accepts clients on the driver's socket until the driver exits.
+async fn accept_clients(listener: UnixListener, clients: Arc<Clients>, output_tx: Sender<Noun>) {
+    let mut next_id = 0u64;
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _addr)) => stream,
+            Err(err) => {
+                warn!(target: Conn::name(), "failed to accept client: {}", err);
+                continue;
+            }
+        };
+        next_id = next_id.checked_add(1).unwrap_or(1);
+        let id = next_id;
+        info!(target: Conn::name(), "connected client {}", id);
+        if let Err(_resp) = output_tx.send(opened(id)).await {
+            warn!(target: Conn::name(), "failed to send client to output task");
+            return;
+        }
+        let (stream_rx, stream_tx) = stream.into_split();
+        let (msg_tx, msg_rx) = mpsc::channel(Client::QUEUE_SIZE);
+        // Hold the lock until the client is added so that it can't be removed first.
+        let mut clients_guard = clients.lock().unwrap_or_else(PoisonError::into_inner);
+        let client = Client {
+            msg_tx: msg_tx.clone(),
+            tasks: vec![
+                tokio::spawn(read_commands(
+                    stream_rx,
+                    id,
+                    clients.clone(),
+                    msg_tx,
+                    output_tx.clone(),
+                )),
+                tokio::spawn(write_results(stream_tx, id, msg_rx)),
+            ],
+        };
+        clients_guard.insert(id, client);
+    }
+}
+
+/// Forwards the commands from client `id` to the output task until the client disconnects, and
+/// then removes the client.
+///
+/// Commands the driver doesn't know are answered over `msg_tx`.
+async fn read_commands(
+    mut stream_rx: OwnedReadHalf,
+    id: u64,
+    clients: Arc<Clients>,
+    msg_tx: Sender<Noun>,
+    output_tx: Sender<Noun>,
+) {
+    loop {
+        let msg = match newt::read(&mut stream_rx).await {
+            Ok(msg) => msg,
+            Err(err) => {
+                if err.kind() != io::ErrorKind::UnexpectedEof {
+                    warn!(target: Conn::name(), "failed to read from client {}: {}", id, err);
+                }
+                break;
+            }
+        };
+        let msg = match Noun::cue(Atom::from(msg)) {
+            Ok(msg) => msg,
+            Err(_err) => {
+                warn!(target: Conn::name(), "skipping malformed message from client {}", id);
+                continue;
+            }
+        };
+        match Command::identify(&msg) {
+            Ok((_cmd_id, Some(cmd))) => {
+                debug!(target: Conn::name(), "client {} sent {:?} command", id, cmd);
+                let resp = Noun::from(Cell::from([
+                    Noun::from(Atom::from("conn")),
+                    Noun::from(Atom::from(id)),
+                    msg,
+                ]));
+                if let Err(_resp) = output_tx.send(resp).await {
+                    warn!(target: Conn::name(), "failed to send command to output task");
+                    return;
+                }
+            }
+            Ok((cmd_id, None)) => {
+                debug!(target: Conn::name(), "client {} sent unknown command", id);
+                let result = Noun::from(Cell::from([
+                    Noun::from(cmd_id.clone()),
+                    Noun::from(Atom::from("error")),
+                    Noun::from(Atom::from("unknown command")),
+                ]));
+                if let Err(_result) = msg_tx.send(result).await {
+                    debug!(target: Conn::name(), "dropped result to closed client {}", id);
+                }
+            }
+            Err(_err) => {
+                warn!(target: Conn::name(), "skipping malformed command from client {}", id);
+            }
+        }
+    }
+    info!(target: Conn::name(), "disconnected client {}", id);
+    if let Err(_resp) = output_tx.send(closed(id)).await {
+        warn!(target: Conn::name(), "failed to send client to output task");
+    }
+    // This aborts the current task, which is finishing anyway.
+    clients
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&id);
+}
+
+/// Writes the results of client `id`'s commands to the client until it's disconnected.
+async fn write_results(mut stream_tx: OwnedWriteHalf, id: u64, mut msg_rx: Receiver<Noun>) {
+    while let Some(msg) = msg_rx.recv().await {
+        let msg = match newt::encode(&msg.jam().into_vec()) {
+            Some(msg) => msg,
+            None => {
+                warn!(target: Conn::name(), "dropped result too long for client {}", id);
+                continue;
+            }
+        };
+        if let Err(err) = stream_tx.write_all(&msg).await {
+            warn!(target: Conn::name(), "failed to write to client {}: {}", id, err);
+            return;
+        }
+    }
+}
+
+/// Returns the response reporting that client `id` connected.
+fn opened(id: u64) -> Noun {
+    Noun::from(Cell::from([Atom::from("open"), Atom::from(id)]))
+}
+
+/// Returns the response reporting that client `id` disconnected.
+fn closed(id: u64) -> Noun {
+    Noun::from(Cell::from([Atom::from("shut"), Atom::from(id)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests the `TryFrom<Noun>` implementation for [`Request`].
+    #[test]
+    fn request_from_noun() {
+        let req = Noun::from(Cell::from([
+            Noun::from(Atom::from("give")),
+            Noun::from(Atom::from(3u64)),
+            Noun::from(Atom::from(7u64)),
+            Noun::from(Cell::from([Atom::from("avow"), Atom::from(42u64)])),
+        ]));
+        match Request::try_from(req) {
+            Ok(Request::Give(req)) => {
+                assert_eq!(req.client, 3);
+                assert_eq!(
+                    req.msg,
+                    Noun::from(Cell::from([
+                        Atom::from(7u64),
+                        Atom::from("avow"),
+                        Atom::from(42u64),
+                    ]))
+                );
+            }
+            _ => panic!("expected %give request"),
+        }
+
+        // Malformed: no result.
+        let req = Noun::from(Cell::from([
+            Atom::from("give"),
+            Atom::from(3u64),
+            Atom::from(7u64),
+        ]));
+        assert!(Request::try_from(req).is_err());
+
+        // Malformed: the command ID is a cell.
+        let req = Noun::from(Cell::from([
+            Noun::from(Atom::from("give")),
+            Noun::from(Atom::from(3u64)),
+            Noun::from(Cell::from([Atom::from(7u64), Atom::from(8u64)])),
+            Noun::null(),
+        ]));
+        assert!(Request::try_from(req).is_err());
+    }
+
+    /// Tests how [`Command::identify()`] identifies the commands of clients.
+    #[test]
+    fn identify_commands() {
+        for (tag, cmd) in [
+            ("fyrd", Some(Command::Fyrd)),
+            ("ovum", Some(Command::Ovum)),
+            ("peek", Some(Command::Peek)),
+            ("zap", None),
+        ] {
+            let msg = Noun::from(Cell::from([
+                Atom::from(9u64),
+                Atom::from(tag),
+                Atom::null(),
+            ]));
+            let (id, identified) = Command::identify(&msg).expect("identify command");
+            assert_eq!(id.as_u64(), Some(9));
+            assert_eq!(identified, cmd);
+        }
+
+        // Malformed: no command.
+        let msg = Noun::from(Cell::from([Atom::from(9u64), Atom::from("peek")]));
+        assert!(Command::identify(&msg).is_err());
+
+        // Malformed: the ID is a cell.
+        let msg = Noun::from(Cell::from([
+            Noun::from(Cell::from([Atom::from(9u64), Atom::from(9u64)])),
+            Noun::from(Cell::from([Atom::from("peek"), Atom::null()])),
+        ]));
+        assert!(Command::identify(&msg).is_err());
+    }
+}
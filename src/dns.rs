@@ -0,0 +1,607 @@
+//! DNS binding driver.
+//!
+//! This module implements the DNS binding IO driver, which checks on behalf of an [Arvo] kernel
+//! that a domain the ship has asked to be bound to its address, such as `zod.arvo.network`,
+//! actually reaches the ship. Once the kernel has asked a collector to create the domain's DNS
+//! record, the driver waits for the domain to resolve to the ship's address and then sends an
+//! HTTP request to the domain that the ship's own web server should answer, so that a turf is only
+//! added once it's known to work.
+//!
+//! Each request to the driver arrives as a length-encoded jammed (i.e. serialized) noun from some
+//! input source--`stdin`, a socket, etc. The driver understands two types of requests:
+//! - check a domain binding (`%bind`), and
+//! - stop checking a domain binding (`%cancel`).
+//!
+//! ### `%bind`
+//!
+//! A jammed noun representing a `%bind` request has the following structure:
+//! ```text
+//! [
+//!   %bind
+//!   <id>
+//!   <turf>
+//!   <address>
+//!   <port>
+//!   <path>
+//! ]
+//! ```
+//! where `<id>` identifies the binding in responses, `<turf>` is the domain as a null-terminated
+//! list of labels starting with the top-level domain (i.e. a `$turf`, so `zod.arvo.network` is
+//! `~['network' 'arvo' 'zod']`), `<address>` is the ship's public IPv4 address (i.e. an `@if`),
+//! `<port>` is the port the ship's web server listens on, and `<path>` is the path, starting with
+//! `/`, of the confirmation request. A `%bind` request with the `<id>` of a binding that's still
+//! being checked replaces it.
+//!
+//! The driver resolves the domain until one of its addresses is `<address>`, and then generates a
+//! response of the form:
+//! ```text
+//! [%resolved <id>]
+//! ```
+//! It then sends a `GET` request to `http://<domain>:<port><path>` until the response has a
+//! successful (i.e. 2xx) status, and then generates a response of the form:
+//! ```text
+//! [%confirmed <id>]
+//! ```
+//! Each step is retried with an increasing delay, since DNS records take a while to propagate. If
+//! a step hasn't succeeded within `URBIT_IO_DRIVERS_DNS_TIMEOUT` (see [Configuration]) of the
+//! request, the driver gives up and generates a response of the form:
+//! ```text
+//! [%failed <id> <step> <reason>]
+//! ```
+//! where `<step>` is `%resolve` or `%confirm` and `<reason>` is the error of the last attempt as a
+//! `@t`.
+//!
+//! ### `%cancel`
+//!
+//! A jammed noun representing a `%cancel` request has the following structure:
+//! ```text
+//! [%cancel <id>]
+//! ```
+//! The driver stops checking the binding with the given ID without generating a response.
+//!
+//! ### Configuration
+//!
+//! The driver is configured by environment variables, which are read when the driver is
+//! initialized:
+//! - `URBIT_IO_DRIVERS_DNS_TIMEOUT`: how long to try each step of checking a binding in seconds.
+//!   Defaults to 600.
+//!
+//! [Arvo]: https://developers.urbit.org/reference/arvo
+//! [Configuration]: #configuration
+
+use crate::{atom_as_str, env_var, Driver, Status};
+use hyper::{
+    client::{Client, HttpConnector},
+    StatusCode, Uri,
+};
+use log::{debug, info, warn};
+use noun::{atom::Atom, cell::Cell, convert, Noun};
+use std::{
+    cmp,
+    collections::HashMap,
+    fmt,
+    future::Future,
+    io,
+    net::{IpAddr, Ipv4Addr},
+    time::Duration,
+};
+use tokio::{
+    io::{Stdin, Stdout},
+    net,
+    sync::mpsc::{Receiver, Sender},
+    task::JoinHandle,
+    time::{self, Instant},
+};
+
+//==================================================================================================
+// Request Types
+//==================================================================================================
+
+/// Requests that can be handled by the DNS binding driver.
+enum Request {
+    /// A request to check a domain binding.
+    Bind(Bind),
+
+    /// A request to stop checking a domain binding.
+    Cancel(Cancel),
+}
+
+impl_try_from_noun_for_request!(Request, "bind" => Bind, "cancel" => Cancel);
+
+/// A request to check a domain binding.
+#[derive(Debug)]
+struct Bind {
+    /// The ID of the binding.
+    id: u64,
+
+    /// The domain, e.g. `zod.arvo.network`.
+    domain: String,
+
+    /// The address the domain should resolve to.
+    addr: Ipv4Addr,
+
+    /// The port the ship's web server listens on.
+    port: u16,
+
+    /// The URI of the confirmation request.
+    uri: Uri,
+}
+
+impl TryFrom<&Noun> for Bind {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// [<id> <turf> <address> <port> <path>]
+    /// ```
+    ///
+    /// where `<turf>` is a non-empty null-terminated list of valid domain labels and `<path>`
+    /// starts with `/`.
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        if let Noun::Cell(data) = data {
+            let [id, turf, addr, port, path] =
+                data.to_array::<5>().ok_or(convert::Error::MissingValue)?;
+            match (&*id, &*addr, &*port, &*path) {
+                (Noun::Atom(id), Noun::Atom(addr), Noun::Atom(port), Noun::Atom(path)) => {
+                    let domain = domain_from_turf(&turf)?;
+                    let addr = addr
+                        .as_u64()
+                        .and_then(|addr| u32::try_from(addr).ok())
+                        .ok_or(convert::Error::AtomToUint)?;
+                    let port = port
+                        .as_u64()
+                        .and_then(|port| u16::try_from(port).ok())
+                        .ok_or(convert::Error::AtomToUint)?;
+                    let path = atom_as_str(path)?;
+                    if !path.starts_with('/') {
+                        return Err(convert::Error::ImplType);
+                    }
+                    let uri = format!("http://{}:{}{}", domain, port, path)
+                        .parse()
+                        .map_err(|_| convert::Error::ImplType)?;
+                    Ok(Self {
+                        id: id.as_u64().ok_or(convert::Error::AtomToUint)?,
+                        domain,
+                        addr: Ipv4Addr::from(addr),
+                        port,
+                        uri,
+                    })
+                }
+                _ => Err(convert::Error::UnexpectedCell),
+            }
+        } else {
+            Err(convert::Error::UnexpectedAtom)
+        }
+    }
+}
+
+/// A request to stop checking a domain binding.
+#[derive(Debug)]
+struct Cancel {
+    /// The ID of the binding.
+    id: u64,
+}
+
+impl TryFrom<&Noun> for Cancel {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// <id>
+    /// ```
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        if let Noun::Atom(id) = data {
+            Ok(Self {
+                id: id.as_u64().ok_or(convert::Error::AtomToUint)?,
+            })
+        } else {
+            Err(convert::Error::UnexpectedCell)
+        }
+    }
+}
+
+/// Converts a `$turf`, which lists the labels of a domain starting with the top-level domain, to
+/// a lowercase domain.
+fn domain_from_turf(turf: &Noun) -> Result<String, convert::Error> {
+    let mut labels = Vec::new();
+    let mut list = turf;
+    loop {
+        match list {
+            Noun::Atom(null) if null.is_null() && !labels.is_empty() => break,
+            Noun::Atom(_) => return Err(convert::Error::ExpectedNull),
+            Noun::Cell(cell) => {
+                if let Noun::Atom(label) = cell.head_ref() {
+                    let label = atom_as_str(label)?;
+                    let valid = !label.is_empty()
+                        && label.len() <= 63
+                        && !label.starts_with('-')
+                        && !label.ends_with('-')
+                        && label
+                            .bytes()
+                            .all(|b| b.is_ascii_alphanumeric() || b == b'-');
+                    if !valid {
+                        return Err(convert::Error::ImplType);
+                    }
+                    labels.push(label.to_ascii_lowercase());
+                    list = cell.tail_ref();
+                } else {
+                    return Err(convert::Error::UnexpectedCell);
+                }
+            }
+        }
+    }
+    labels.reverse();
+    Ok(labels.join("."))
+}
+
+//==================================================================================================
+// Driver
+//==================================================================================================
+
+/// Configuration for the DNS binding driver.
+struct Config {
+    /// How long to try each step of checking a binding.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_DNS_TIMEOUT` as a number of seconds.
+    timeout: Duration,
+}
+
+impl Config {
+    /// Reads the configuration from the environment.
+    fn from_env() -> Self {
+        Self {
+            timeout: env_var("URBIT_IO_DRIVERS_DNS_TIMEOUT")
+                .map(Duration::from_secs)
+                .unwrap_or(Duration::from_secs(600)),
+        }
+    }
+}
+
+/// The DNS binding driver.
+pub struct Dns {
+    /// The client that sends confirmation requests.
+    client: Client<HttpConnector>,
+
+    /// Map from binding ID to the task checking the binding. Must only be accessed from a single
+    /// task.
+    bindings: HashMap<u64, JoinHandle<()>>,
+
+    /// The driver configuration.
+    config: Config,
+}
+
+impl Dns {
+    /// How long to wait before retrying a step the first time.
+    const MIN_RETRY: Duration = Duration::from_secs(1);
+
+    /// The longest time to wait before retrying a step.
+    const MAX_RETRY: Duration = Duration::from_secs(64);
+
+    /// The longest time a single attempt at a step may take.
+    const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Starts checking a binding, replacing any check of a binding with the same ID.
+    fn bind(&mut self, req: Bind, output_tx: Sender<Noun>) {
+        let id = req.id;
+        let client = self.client.clone();
+        let timeout = self.config.timeout;
+        let task = tokio::spawn(async move {
+            let resp = Self::check(req, client, timeout, &output_tx).await;
+            if let Err(_resp) = output_tx.send(resp).await {
+                warn!(
+                    target: Self::name(),
+                    "failed to send result of binding #{} to output task", id
+                );
+            }
+        });
+        // Forget bindings that have been checked.
+        self.bindings.retain(|_id, task| !task.is_finished());
+        if let Some(prev) = self.bindings.insert(id, task) {
+            debug!(target: Self::name(), "replacing binding #{}", id);
+            prev.abort();
+        }
+    }
+
+    /// Stops checking a binding.
+    fn cancel(&mut self, req: Cancel) {
+        if let Some(task) = self.bindings.remove(&req.id) {
+            debug!(target: Self::name(), "canceled binding #{}", req.id);
+            task.abort();
+        }
+    }
+
+    /// Checks that `req.domain` resolves to `req.addr` and that the confirmation request
+    /// succeeds, sending `%resolved` to the output task in between and returning either
+    /// `%confirmed` or `%failed`.
+    async fn check(
+        req: Bind,
+        client: Client<HttpConnector>,
+        timeout: Duration,
+        output_tx: &Sender<Noun>,
+    ) -> Noun {
+        let deadline = Instant::now() + timeout;
+        let resolve = || Self::resolve(&req.domain, req.addr, req.port);
+        if let Err(err) = Self::retry(deadline, resolve).await {
+            warn!(target: Self::name(), "failed to resolve {}: {}", req.domain, err);
+            return failed(req.id, "resolve", err);
+        }
+        info!(target: Self::name(), "{} resolves to {}", req.domain, req.addr);
+        let resolved = Noun::from(Cell::from([Atom::from("resolved"), Atom::from(req.id)]));
+        if let Err(_resp) = output_tx.send(resolved).await {
+            warn!(
+                target: Self::name(),
+                "failed to send resolution of binding #{} to output task", req.id
+            );
+        }
+
+        let deadline = Instant::now() + timeout;
+        let confirm = || Self::confirm(&client, &req.uri);
+        if let Err(err) = Self::retry(deadline, confirm).await {
+            warn!(target: Self::name(), "failed to confirm {}: {}", req.uri, err);
+            return failed(req.id, "confirm", err);
+        }
+        info!(target: Self::name(), "confirmed {}", req.uri);
+        Noun::from(Cell::from([Atom::from("confirmed"), Atom::from(req.id)]))
+    }
+
+    /// Makes attempts until one succeeds or `deadline` passes, doubling the delay between
+    /// attempts from [`Self::MIN_RETRY`] up to [`Self::MAX_RETRY`].
+    async fn retry<F, Fut>(deadline: Instant, mut attempt: F) -> Result<(), CheckError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<(), CheckError>>,
+    {
+        let mut delay = Self::MIN_RETRY;
+        loop {
+            let attempt_deadline = cmp::min(deadline, Instant::now() + Self::ATTEMPT_TIMEOUT);
+            let err = match time::timeout_at(attempt_deadline, attempt()).await {
+                Ok(Ok(())) => return Ok(()),
+                Ok(Err(err)) => err,
+                Err(_elapsed) => CheckError::TimedOut,
+            };
+            if Instant::now() + delay >= deadline {
+                return Err(err);
+            }
+            debug!(target: Self::name(), "retrying in {:?}: {}", delay, err);
+            time::sleep(delay).await;
+            delay = cmp::min(2 * delay, Self::MAX_RETRY);
+        }
+    }
+
+    /// Resolves `domain`, succeeding if one of its addresses is `addr`.
+    async fn resolve(domain: &str, addr: Ipv4Addr, port: u16) -> Result<(), CheckError> {
+        let found: Vec<IpAddr> = net::lookup_host((domain, port))
+            .await
+            .map_err(CheckError::Lookup)?
+            .map(|addr| addr.ip())
+            .collect();
+        if found.contains(&IpAddr::V4(addr)) {
+            Ok(())
+        } else {
+            Err(CheckError::WrongAddress(found))
+        }
+    }
+
+    /// Sends the confirmation request to `uri`, succeeding if its response has a successful status.
+    async fn confirm(client: &Client<HttpConnector>, uri: &Uri) -> Result<(), CheckError> {
+        let resp = client.get(uri.clone()).await.map_err(CheckError::Hyper)?;
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(CheckError::Status(resp.status()))
+        }
+    }
+}
+
+/// Implements the [`Driver`] trait for the [`Dns`] driver.
+macro_rules! impl_driver {
+    ($input_src:ty, $output_sink:ty) => {
+        impl Driver<$input_src, $output_sink> for Dns {
+            fn new() -> Result<Self, Status> {
+                let config = Config::from_env();
+                debug!(
+                    target: Self::name(),
+                    "initialized driver with timeout {:?}", config.timeout
+                );
+                Ok(Self {
+                    client: Client::new(),
+                    bindings: HashMap::new(),
+                    config,
+                })
+            }
+
+            fn name() -> &'static str {
+                "dns"
+            }
+
+            fn handle_requests(
+                mut self,
+                mut input_rx: Receiver<Noun>,
+                output_tx: Sender<Noun>,
+            ) -> JoinHandle<Status> {
+                let task = tokio::spawn(async move {
+                    while let Some(req) = input_rx.recv().await {
+                        match Request::try_from(req) {
+                            Ok(Request::Bind(req)) => self.bind(req, output_tx.clone()),
+                            Ok(Request::Cancel(req)) => self.cancel(req),
+                            Err(_) => {
+                                warn!(target: Self::name(), "skipping unidentifiable request");
+                            }
+                        }
+                    }
+                    for (_id, task) in self.bindings.drain() {
+                        task.abort();
+                    }
+                    Status::Success
+                });
+                debug!(target: Self::name(), "spawned handling task");
+                task
+            }
+        }
+    };
+}
+
+impl_driver!(Stdin, Stdout);
+
+/// Provides an FFI-friendly interface for running the DNS binding driver with `stdin` as the input
+/// source and `stdout` as the output sink.
+#[no_mangle]
+pub extern "C" fn dns_run() -> Status {
+    match Dns::new() {
+        Ok(driver) => driver.run(tokio::io::stdin(), tokio::io::stdout()),
+        Err(status) => status,
+    }
+}
+
+//==================================================================================================
+// Miscellaneous
+//==================================================================================================
+
+/// Errors that can occur while checking a binding.
+#[derive(Debug)]
+enum CheckError {
+    /// The domain couldn't be resolved.
+    Lookup(io::Error),
+
+    /// The domain resolved to other addresses.
+    WrongAddress(Vec<IpAddr>),
+
+    /// The confirmation request failed.
+    Hyper(hyper::Error),
+
+    /// The confirmation request got a response with an unsuccessful status.
+    Status(StatusCode),
+
+    /// An attempt took too long.
+    TimedOut,
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Lookup(err) => write!(f, "lookup failed: {}", err),
+            Self::WrongAddress(found) if found.is_empty() => write!(f, "no addresses"),
+            Self::WrongAddress(found) => {
+                write!(f, "resolves to ")?;
+                for (i, addr) in found.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", addr)?;
+                }
+                Ok(())
+            }
+            Self::Hyper(err) => write!(f, "request failed: {}", err),
+            Self::Status(status) => write!(f, "unexpected status {}", status.as_u16()),
+            Self::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+/// Returns the response reporting that `step` of checking binding `id` failed with `err`.
+fn failed(id: u64, step: &str, err: CheckError) -> Noun {
+    Noun::from(Cell::from([
+        Atom::from("failed"),
+        Atom::from(id),
+        Atom::from(step),
+        Atom::from(err.to_string().as_str()),
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the `$turf` of `labels`, which start with the top-level domain.
+    fn turf(labels: &[&str]) -> Noun {
+        let mut list = Noun::null();
+        for label in labels.iter().rev() {
+            list = Noun::from(Cell::from([Noun::from(Atom::from(*label)), list]));
+        }
+        list
+    }
+
+    /// Tests the `TryFrom<&Noun>` implementation for [`Bind`].
+    #[test]
+    fn bind_from_noun() {
+        let bind = |turf: Noun, path: &str| {
+            Bind::try_from(&Noun::from(Cell::from([
+                Noun::from(Atom::from(3u64)),
+                turf,
+                Noun::from(Atom::from(0xc000_0201u64)),
+                Noun::from(Atom::from(8080u64)),
+                Noun::from(Atom::from(path)),
+            ])))
+        };
+
+        let req = bind(turf(&["network", "arvo", "Zod"]), "/~/dns/abc").expect("bind from noun");
+        assert_eq!(req.id, 3);
+        assert_eq!(req.domain, "zod.arvo.network");
+        assert_eq!(req.addr, Ipv4Addr::new(192, 0, 2, 1));
+        assert_eq!(req.port, 8080);
+        assert_eq!(req.uri, "http://zod.arvo.network:8080/~/dns/abc");
+
+        // Malformed: no labels.
+        assert_eq!(
+            bind(Noun::null(), "/").unwrap_err(),
+            convert::Error::ExpectedNull
+        );
+
+        // Malformed: invalid labels.
+        for label in ["", "-zod", "zod-", "z.od", "z/od"] {
+            assert_eq!(
+                bind(turf(&["network", label]), "/").unwrap_err(),
+                convert::Error::ImplType
+            );
+        }
+
+        // Malformed: relative path.
+        assert_eq!(
+            bind(turf(&["network"]), "dns").unwrap_err(),
+            convert::Error::ImplType
+        );
+
+        // Malformed: address too large.
+        let req = Noun::from(Cell::from([
+            Noun::from(Atom::from(3u64)),
+            turf(&["network"]),
+            Noun::from(Atom::from(1u64 << 32)),
+            Noun::from(Atom::from(80u64)),
+            Noun::from(Atom::from("/")),
+        ]));
+        assert_eq!(
+            Bind::try_from(&req).unwrap_err(),
+            convert::Error::AtomToUint
+        );
+    }
+
+    /// Tests how errors are reported in `%failed` responses.
+    #[test]
+    fn failed_to_noun() {
+        let err = CheckError::WrongAddress(vec![
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)),
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 3)),
+        ]);
+        assert_eq!(
+            failed(3, "resolve", err),
+            Noun::from(Cell::from([
+                Atom::from("failed"),
+                Atom::from(3u64),
+                Atom::from("resolve"),
+                Atom::from("resolves to 192.0.2.2, 192.0.2.3"),
+            ]))
+        );
+        let err = CheckError::Status(StatusCode::NOT_FOUND);
+        assert_eq!(
+            failed(4, "confirm", err),
+            Noun::from(Cell::from([
+                Atom::from("failed"),
+                Atom::from(4u64),
+                Atom::from("confirm"),
+                Atom::from("unexpected status 404"),
+            ]))
+        );
+    }
+}
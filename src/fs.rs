@@ -1,21 +1,65 @@
+//! File system driver.
+//!
+//! This module implements the file system IO driver, which keeps the mount points of an [Arvo]
+//! kernel's [Clay] vane in sync with directories on the host. Each request to the driver arrives as
+//! a length-encoded jammed (i.e. serialized) noun from some input source--`stdin`, a socket, etc.
+//! The driver understands requests to:
+//! - scan the mount points (`%hill`),
+//! - commit a mount point (`%dirk`),
+//! - preview a commit of a mount point (`%dirk-dry-run`),
+//! - update mount points from a list of changes (`%ergo`),
+//! - delete a mount point (`%ogre`),
+//! - export a mount point to an archive and import one from an archive (`%export` and `%import`),
+//! - move a mount point to a different directory (`%remount`),
+//! - report the disk usage of a mount point (`%usage`), and
+//! - compute a manifest of a mount point (`%verify`).
+//!
+//! The structure of each request and of its response is documented on the request's type.
+//!
+//! ### `%dirk-dry-run`
+//!
+//! A jammed noun representing a `%dirk-dry-run` request has the following structure:
+//! ```text
+//! [%dirk-dry-run <mount_point>]
+//! ```
+//! where `<mount_point>` is the name of the mount point to preview a commit of. The driver
+//! computes the changes that a `%dirk` request would report without recording them, and generates
+//! a response of the form:
+//! ```text
+//! [%dirk-dry-run <mount_point> <changes>]
+//! ```
+//! where `<changes>` has the structure of the response to a `%dirk` request. The response carries
+//! the request's tag so that it can't be mistaken for the bare list of changes of an actual commit.
+//!
+//! [Arvo]: https://developers.urbit.org/reference/glossary/arvo
+//! [Clay]: https://developers.urbit.org/reference/arvo/clay/clay
+
 #![allow(dead_code)]
 
-use crate::{atom_as_str, Driver, Status};
+use crate::{atom_as_str, env_var, Driver, Status};
 use log::{debug, info, warn};
 use noun::{atom::Atom, cell::Cell, convert, marker::Atomish, Noun, Rc};
+use sha2::{Digest, Sha256};
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap},
-    env, fmt, fs,
-    hash::Hasher,
-    io,
+    collections::{HashMap, HashSet},
+    env,
+    ffi::{OsStr, OsString},
+    fmt, fs, io, mem,
     path::{self, Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::{
     io::{Stdin, Stdout},
-    sync::mpsc::{Receiver, Sender},
-    task::JoinHandle,
+    runtime::Handle,
+    sync::mpsc::{self, Receiver, Sender, UnboundedReceiver, UnboundedSender},
+    task::{self, JoinHandle},
 };
 
+#[cfg(feature = "test-util")]
+/// Fixtures for testing the file system driver.
+pub mod test_util;
+
 //==================================================================================================
 // Request Types
 //==================================================================================================
@@ -28,11 +72,29 @@ enum Request {
     /// A request to delete a mount point.
     DeleteMountPoint(DeleteMountPoint),
 
+    /// A request to report the disk usage of a mount point.
+    DiskUsage(DiskUsage),
+
+    /// A request to compute the changes a commit would produce without applying them.
+    DryRunCommitMountPoint(DryRunCommitMountPoint),
+
+    /// A request to write a mount point to a tar archive.
+    ExportMountPoint(ExportMountPoint),
+
+    /// A request to populate a mount point from a tar archive.
+    ImportMountPoint(ImportMountPoint),
+
+    /// A request to move a mount point to a different directory.
+    RemountMountPoint(RemountMountPoint),
+
     /// A request to scan a list of mount points.
     ScanMountPoints(ScanMountPoints),
 
     /// A request to update the file system from a list of changes.
     UpdateFileSystem(UpdateFileSystem),
+
+    /// A request to compute a manifest of a mount point.
+    VerifyMountPoint(VerifyMountPoint),
 }
 
 impl_try_from_noun_for_request!(
@@ -40,11 +102,70 @@ impl_try_from_noun_for_request!(
     // "dirk", "ogre", etc are terrible names, but we can't do anything about it here.
     "dirk" => CommitMountPoint,
     "ogre" => DeleteMountPoint,
+    "dirk-dry-run" => DryRunCommitMountPoint,
+    "export" => ExportMountPoint,
+    "import" => ImportMountPoint,
     "hill" => ScanMountPoints,
+    "remount" => RemountMountPoint,
     "ergo" => UpdateFileSystem,
+    "usage" => DiskUsage,
+    "verify" => VerifyMountPoint,
 );
 
+impl Request {
+    /// Splits a request into requests that each concern a single mount point, each of which is
+    /// paired with the name of its mount point.
+    fn into_mount_point_requests(self) -> Vec<(PathComponent, Self)> {
+        match self {
+            Self::CommitMountPoint(req) => {
+                vec![(req.mount_point.clone(), Self::CommitMountPoint(req))]
+            }
+            Self::DeleteMountPoint(req) => {
+                vec![(req.mount_point.clone(), Self::DeleteMountPoint(req))]
+            }
+            Self::DiskUsage(req) => vec![(req.mount_point.clone(), Self::DiskUsage(req))],
+            Self::DryRunCommitMountPoint(req) => {
+                vec![(req.mount_point.clone(), Self::DryRunCommitMountPoint(req))]
+            }
+            Self::ExportMountPoint(req) => {
+                vec![(req.mount_point.clone(), Self::ExportMountPoint(req))]
+            }
+            Self::ImportMountPoint(req) => {
+                vec![(req.mount_point.clone(), Self::ImportMountPoint(req))]
+            }
+            Self::RemountMountPoint(req) => {
+                vec![(req.mount_point.clone(), Self::RemountMountPoint(req))]
+            }
+            Self::ScanMountPoints(mut req) => req
+                .mount_points
+                .into_iter()
+                .map(|name| {
+                    let mut prefixes = HashMap::new();
+                    if let Some(name_prefixes) = req.prefixes.remove(&name) {
+                        prefixes.insert(name.clone(), name_prefixes);
+                    }
+                    let req = ScanMountPoints {
+                        mount_points: vec![name.clone()],
+                        prefixes,
+                    };
+                    (name, Self::ScanMountPoints(req))
+                })
+                .collect(),
+            Self::UpdateFileSystem(req) => {
+                vec![(req.mount_point.clone(), Self::UpdateFileSystem(req))]
+            }
+            Self::VerifyMountPoint(req) => {
+                vec![(req.mount_point.clone(), Self::VerifyMountPoint(req))]
+            }
+        }
+    }
+}
+
 /// A request to commit a mount point.
+///
+/// The response to this request is the list of changes made to the mount point since it was last
+/// committed. A file that was renamed since the last commit is reported as the removal of its old
+/// path next to the addition of its new path.
 struct CommitMountPoint {
     /// The name of the mount point to commit.
     mount_point: PathComponent,
@@ -90,10 +211,188 @@ impl TryFrom<&Noun> for DeleteMountPoint {
     }
 }
 
+/// A request to report the disk usage of a mount point.
+struct DiskUsage {
+    /// The name of the mount point to report the disk usage of.
+    mount_point: PathComponent,
+}
+
+impl TryFrom<&Noun> for DiskUsage {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// <mount_point>
+    /// ```
+    ///
+    /// where `<mount_point>` is the name of the mount point to report the disk usage of.
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        Ok(Self {
+            mount_point: PathComponent::try_from(Knot::try_from(data)?)?,
+        })
+    }
+}
+
+/// A request to compute the changes a commit would produce without applying them.
+///
+/// The response to this request is:
+///
+/// ```text
+/// [%dirk-dry-run <mount_point> <changes>]
+/// ```
+///
+/// where `<changes>` is the list of changes that a [`CommitMountPoint`] request would respond with.
+/// The driver's record of the mount point is left untouched, so a subsequent commit yields the
+/// same changes.
+struct DryRunCommitMountPoint {
+    /// The name of the mount point to preview a commit of.
+    mount_point: PathComponent,
+}
+
+impl TryFrom<&Noun> for DryRunCommitMountPoint {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// <mount_point>
+    /// ```
+    ///
+    /// where `<mount_point>` is the name of the mount point to preview a commit of.
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        Ok(Self {
+            mount_point: PathComponent::try_from(Knot::try_from(data)?)?,
+        })
+    }
+}
+
+/// A request to write a mount point to a tar archive.
+struct ExportMountPoint {
+    /// The name of the mount point to export.
+    mount_point: PathComponent,
+
+    /// The path of the archive to write.
+    archive: PathBuf,
+}
+
+impl TryFrom<&Noun> for ExportMountPoint {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// [<mount_point> <archive>]
+    /// ```
+    ///
+    /// where `<mount_point>` is the name of the mount point to export and `<archive>` is the path
+    /// of the archive to write, which is relative to the current working directory unless it's
+    /// absolute.
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        if let Noun::Cell(data) = data {
+            if let Noun::Atom(archive) = data.tail_ref() {
+                Ok(Self {
+                    mount_point: PathComponent::try_from(Knot::try_from(data.head_ref())?)?,
+                    archive: PathBuf::from(atom_as_str(archive)?),
+                })
+            } else {
+                Err(convert::Error::UnexpectedCell)
+            }
+        } else {
+            Err(convert::Error::UnexpectedAtom)
+        }
+    }
+}
+
+/// A request to populate a mount point from a tar archive.
+///
+/// The response to this request is identical to the response to a [`CommitMountPoint`] request
+/// issued right after the archive is unpacked.
+struct ImportMountPoint {
+    /// The name of the mount point to import into.
+    mount_point: PathComponent,
+
+    /// The path of the archive to read.
+    archive: PathBuf,
+}
+
+impl TryFrom<&Noun> for ImportMountPoint {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// [<mount_point> <archive>]
+    /// ```
+    ///
+    /// where `<mount_point>` is the name of the mount point to import into and `<archive>` is the
+    /// path of the archive to read, which is relative to the current working directory unless
+    /// it's absolute.
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        if let Noun::Cell(data) = data {
+            if let Noun::Atom(archive) = data.tail_ref() {
+                Ok(Self {
+                    mount_point: PathComponent::try_from(Knot::try_from(data.head_ref())?)?,
+                    archive: PathBuf::from(atom_as_str(archive)?),
+                })
+            } else {
+                Err(convert::Error::UnexpectedCell)
+            }
+        } else {
+            Err(convert::Error::UnexpectedAtom)
+        }
+    }
+}
+
+/// A request to move a mount point to a different directory.
+///
+/// The response to this request is identical to the response to a [`CommitMountPoint`] request,
+/// where the changes are the differences between the mount point as of its last commit and the
+/// contents of the new directory.
+struct RemountMountPoint {
+    /// The name of the mount point to move.
+    mount_point: PathComponent,
+
+    /// The directory to move the mount point to.
+    path: PathBuf,
+}
+
+impl TryFrom<&Noun> for RemountMountPoint {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// [<mount_point> <path>]
+    /// ```
+    ///
+    /// where `<mount_point>` is the name of the mount point to move and `<path>` is the existing
+    /// directory to move it to, which is relative to the current working directory unless it's
+    /// absolute.
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        if let Noun::Cell(data) = data {
+            if let Noun::Atom(path) = data.tail_ref() {
+                Ok(Self {
+                    mount_point: PathComponent::try_from(Knot::try_from(data.head_ref())?)?,
+                    path: PathBuf::from(atom_as_str(path)?),
+                })
+            } else {
+                Err(convert::Error::UnexpectedCell)
+            }
+        } else {
+            Err(convert::Error::UnexpectedAtom)
+        }
+    }
+}
+
 /// A request to scan a list of mount points.
 struct ScanMountPoints {
     /// The names of the mount points to scan.
     mount_points: Vec<PathComponent>,
+
+    /// The path prefixes that mount points are limited to, for mount points that are to be
+    /// limited to a subset of their contents.
+    prefixes: HashMap<PathComponent, Vec<PathBuf>>,
 }
 
 impl TryFrom<&Noun> for ScanMountPoints {
@@ -105,14 +404,71 @@ impl TryFrom<&Noun> for ScanMountPoints {
     /// <mount_point_list>
     /// ```
     ///
-    /// where `<mount_point_list>` is a null-terminated list of mount point names.
+    /// where `<mount_point_list>` is a null-terminated list of mount points. See [`ScanTarget`]
+    /// for the structure of a single mount point.
     fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        let mut mount_points = Vec::new();
+        let mut prefixes = HashMap::new();
+        for target in convert!(data => Vec<ScanTarget>)? {
+            if let Some(target_prefixes) = target.prefixes {
+                prefixes.insert(target.mount_point.clone(), target_prefixes);
+            }
+            mount_points.push(target.mount_point);
+        }
         Ok(Self {
-            mount_points: convert!(data => Vec<PathComponent>)?,
+            mount_points,
+            prefixes,
         })
     }
 }
 
+/// A mount point to scan.
+struct ScanTarget {
+    /// The name of the mount point.
+    mount_point: PathComponent,
+
+    /// The path prefixes that the mount point is limited to, if any.
+    prefixes: Option<Vec<PathBuf>>,
+}
+
+impl TryFrom<&Noun> for ScanTarget {
+    type Error = convert::Error;
+
+    /// A properly structured noun is one of:
+    ///
+    /// ```text
+    /// <mount_point>
+    /// [<mount_point> <prefix_list>]
+    /// ```
+    ///
+    /// where `<mount_point>` is the name of the mount point and `<prefix_list>` is a
+    /// null-terminated list of mount-point-relative directory paths, each of which is a
+    /// null-terminated list of directory names. The former structure scans the entire mount point,
+    /// whereas the latter structure limits the mount point to the files within the directories in
+    /// `<prefix_list>`.
+    ///
+    /// For example, limiting the mount point `base` to `gen/` and `lib/` yields:
+    ///
+    /// ```text
+    /// [%base [%gen 0] [%lib 0] 0]
+    /// ```
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        match data {
+            Noun::Atom(_) => Ok(Self {
+                mount_point: PathComponent::try_from(Knot::try_from(data)?)?,
+                prefixes: None,
+            }),
+            Noun::Cell(data) => {
+                let prefixes = convert!(data.tail_ref() => Vec<PathPrefix>)?;
+                Ok(Self {
+                    mount_point: PathComponent::try_from(Knot::try_from(data.head_ref())?)?,
+                    prefixes: Some(prefixes.into_iter().map(|prefix| prefix.0).collect()),
+                })
+            }
+        }
+    }
+}
+
 /// A request to update the file system from a list of changes.
 struct UpdateFileSystem {
     /// The name of the mount point to update.
@@ -146,96 +502,537 @@ impl TryFrom<&Noun> for UpdateFileSystem {
     }
 }
 
+/// A request to compute a manifest of a mount point.
+///
+/// The manifest maps the mount-point-relative path of every file in the mount point to the
+/// [`Hash`] of the file's contents, which allows the ship to verify that the mount point matches
+/// its own record of the mount point's contents.
+struct VerifyMountPoint {
+    /// The name of the mount point to compute a manifest of.
+    mount_point: PathComponent,
+}
+
+impl TryFrom<&Noun> for VerifyMountPoint {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// <mount_point>
+    /// ```
+    ///
+    /// where `<mount_point>` is the name of the mount point to compute a manifest of.
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        Ok(Self {
+            mount_point: PathComponent::try_from(Knot::try_from(data)?)?,
+        })
+    }
+}
+
+/// A mount-point-relative directory path that a mount point is limited to.
+struct PathPrefix(PathBuf);
+
+impl TryFrom<&Noun> for PathPrefix {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// <dir_list>
+    /// ```
+    ///
+    /// where `<dir_list>` is a null-terminated list of directory names.
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        Ok(Self(
+            convert!(data => Vec<PathComponent>)?.into_iter().collect(),
+        ))
+    }
+}
+
 //==================================================================================================
 // Driver
 //==================================================================================================
 
+/// Configuration of the file system driver.
+///
+/// Each option is read from an environment variable when the driver is initialized.
+#[derive(Clone)]
+struct Config {
+    /// How long a deleted mount point is kept in the pier's trash directory before being removed
+    /// permanently.
+    ///
+    /// If `None`, deleted mount points are removed permanently right away.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_FS_TRASH_RETENTION` as a number of seconds.
+    trash_retention: Option<Duration>,
+
+    /// Whether files and directories changed by an [`UpdateFileSystem`] request are flushed to
+    /// disk before the next request is handled.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_FS_FSYNC` as `true` or `false`.
+    fsync: bool,
+
+    /// Whether a [`DeleteMountPoint`] request only stops tracking the mount point, leaving its
+    /// files on disk.
+    ///
+    /// Takes precedence over `trash_retention`.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_FS_KEEP_UNMOUNTED` as `true` or `false`.
+    keep_unmounted: bool,
+
+    /// The file extensions of files that are never synced to or from each mount point.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_FS_EXCLUDE`. See [`Exclusions`] for the format.
+    exclusions: Exclusions,
+
+    /// Whether symlinks within mount points are followed during scans, in which case the targets
+    /// of symlinks are treated as if they were regular files and directories. Updates to files
+    /// within those targets are then written through the symlinks.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_FS_FOLLOW_SYMLINKS` as `true` or `false`.
+    follow_symlinks: bool,
+}
+
+impl Config {
+    /// Reads the configuration from the environment.
+    fn from_env() -> Self {
+        Self {
+            trash_retention: env_var("URBIT_IO_DRIVERS_FS_TRASH_RETENTION")
+                .map(Duration::from_secs),
+            fsync: env_var("URBIT_IO_DRIVERS_FS_FSYNC").unwrap_or(false),
+            keep_unmounted: env_var("URBIT_IO_DRIVERS_FS_KEEP_UNMOUNTED").unwrap_or(false),
+            exclusions: env_var("URBIT_IO_DRIVERS_FS_EXCLUDE").unwrap_or_default(),
+            follow_symlinks: env_var("URBIT_IO_DRIVERS_FS_FOLLOW_SYMLINKS").unwrap_or(false),
+        }
+    }
+
+    /// Returns the file extensions excluded from a mount point.
+    fn excluded(&self, mount_point: &PathComponent) -> HashSet<String> {
+        self.exclusions
+            .0
+            .get(&mount_point.0)
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+/// The file extensions excluded from each mount point, keyed by mount point name.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Exclusions(HashMap<String, HashSet<String>>);
+
+impl FromStr for Exclusions {
+    type Err = ();
+
+    /// A properly structured string is a `;`-separated list of `<mount_point>=<extension_list>`
+    /// pairs, where `<extension_list>` is a `,`-separated list of file extensions with or without
+    /// a leading `.`.
+    ///
+    /// For example, excluding `.bin` and `.mp4` files from `base` and `.exe` files from `kids`
+    /// yields:
+    ///
+    /// ```text
+    /// base=bin,mp4;kids=.exe
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut exclusions = HashMap::new();
+        for pair in s.split(';').filter(|pair| !pair.trim().is_empty()) {
+            let (mount_point, extensions) = pair.split_once('=').ok_or(())?;
+            let mount_point = mount_point.trim();
+            if mount_point.is_empty() {
+                return Err(());
+            }
+            let excluded: &mut HashSet<String> =
+                exclusions.entry(mount_point.to_string()).or_default();
+            for extension in extensions.split(',') {
+                let extension = extension.trim().trim_start_matches('.');
+                if !extension.is_empty() {
+                    excluded.insert(extension.to_string());
+                }
+            }
+        }
+        Ok(Self(exclusions))
+    }
+}
+
 /// The file system driver.
+///
+/// Each mount point's requests are handled by a separate instance of the driver, so the
+/// `mount_points` of an instance that handles requests contains at most one mount point.
 pub struct FileSystem {
     /// The list of actively mounted mount points.
     mount_points: HashMap<PathComponent, MountPoint>,
+
+    /// The driver configuration.
+    config: Config,
 }
 
 impl FileSystem {
+    /// The maximum number of entries in a single chunk of a manifest.
+    const MANIFEST_CHUNK_LEN: usize = 1000;
+
+    /// The number of files listed in the response to a [`DiskUsage`] request.
+    const LARGEST_FILES_LEN: usize = 10;
+
     /// Handles a [`CommitMountPoint`] request.
-    fn commit_mount_point(&mut self, req: CommitMountPoint) -> Option<Noun> {
-        // We have to remove our mount point from `self.mount_points` so that we take ownership
-        // (as opposed to having a reference).
-        let mount_point = match self.mount_points.remove(&req.mount_point) {
+    fn commit_mount_point(
+        &mut self,
+        req: CommitMountPoint,
+        output_tx: &Sender<Noun>,
+    ) -> Option<Noun> {
+        self.commit(req.mount_point, false, output_tx)
+    }
+
+    /// Handles a [`DiskUsage`] request.
+    ///
+    /// The disk usage is computed from the sizes recorded by the most recent scan, commit, or
+    /// update, so the mount point isn't walked again. The resulting noun is:
+    ///
+    /// ```text
+    /// [%usage <mount_point> <total_bytes> <file_count> <largest_list>]
+    /// ```
+    ///
+    /// where `<largest_list>` is a null-terminated list of the [`Self::LARGEST_FILES_LEN`] largest
+    /// files in the mount point in descending order of size, each of the form
+    /// `[<path_list> <bytes>]`.
+    fn disk_usage(&self, req: DiskUsage) -> Option<Noun> {
+        let mount_point = match self.mount_points.get(&req.mount_point) {
             Some(mount_point) => mount_point,
             None => {
-                info!("mount point {} is not actively mounted", req.mount_point);
+                info!(
+                    target: Self::name(),
+                    "mount point {} is not actively mounted", req.mount_point
+                );
                 return None;
             }
         };
 
-        let (mut mount_point, old_entries) = match mount_point.scan() {
-            Ok(res_tuple) => res_tuple,
+        let total_bytes: u64 = mount_point.entries.values().map(|entry| entry.len).sum();
+        let mut files: Vec<(&PathBuf, u64)> = mount_point
+            .entries
+            .iter()
+            .map(|(path, entry)| (path, entry.len))
+            .collect();
+        files.sort_by(|(a_path, a_len), (b_path, b_len)| {
+            b_len.cmp(a_len).then_with(|| a_path.cmp(b_path))
+        });
+        let largest = files
+            .into_iter()
+            .take(Self::LARGEST_FILES_LEN)
+            .filter_map(|(path, len)| {
+                let knots = Self::relative_knots(&mount_point.path, path)?;
+                Some(Cell::from([Noun::from(knots), Noun::from(Atom::from(len))]))
+            });
+
+        Some(Noun::from(Cell::from([
+            Noun::from(Atom::from("usage")),
+            Noun::from(Knot::from(req.mount_point)),
+            Noun::from(Atom::from(total_bytes)),
+            Noun::from(Atom::from(mount_point.entries.len())),
+            // This is safe to unwrap because the conversion from `Cell` to `Noun` will never fail.
+            convert!(largest => Noun).unwrap(),
+        ])))
+    }
+
+    /// Handles a [`DryRunCommitMountPoint`] request.
+    fn dry_run_commit_mount_point(
+        &mut self,
+        req: DryRunCommitMountPoint,
+        output_tx: &Sender<Noun>,
+    ) -> Option<Noun> {
+        let changes = self.commit(req.mount_point.clone(), true, output_tx)?;
+        // The preview is tagged so that it can't be mistaken for the changes of an actual commit.
+        Some(Noun::from(Cell::from([
+            Noun::from(Atom::from("dirk-dry-run")),
+            Noun::from(Knot::from(req.mount_point)),
+            changes,
+        ])))
+    }
+
+    /// Handles an [`ExportMountPoint`] request.
+    fn export_mount_point(&self, req: ExportMountPoint) {
+        let mount_point = match self.mount_points.get(&req.mount_point) {
+            Some(mount_point) => mount_point.clone(),
+            None => {
+                info!(
+                    target: Self::name(),
+                    "mount point {} is not actively mounted", req.mount_point
+                );
+                return;
+            }
+        };
+        let mount_point = match mount_point.scan(None) {
+            Ok((mount_point, _old_entries)) => mount_point,
             Err((mount_point, err)) => {
                 warn!(
                     target: Self::name(),
-                    "failed to scan{}: {}",
+                    "failed to scan {}: {}",
                     mount_point.path.display(),
                     err
                 );
-                self.mount_points.insert(req.mount_point, mount_point);
-                return None;
+                return;
             }
         };
 
-        let mut changes: Vec<Cell> = Vec::new();
-        let null = Rc::new(Noun::null());
+        match mount_point.export(&req.archive) {
+            Ok(count) => info!(
+                target: Self::name(),
+                "exported {} files from {} to {}",
+                count,
+                req.mount_point,
+                req.archive.display()
+            ),
+            Err(err) => warn!(
+                target: Self::name(),
+                "failed to export {} to {}: {}",
+                req.mount_point,
+                req.archive.display(),
+                err
+            ),
+        }
+    }
 
-        // Record entries that have been added or updated.
-        for (path, old_hash) in &mut mount_point.entries {
-            // Read the contents of the file.
-            let bytes = match fs::read(path) {
-                Ok(bytes) => bytes,
+    /// Handles an [`ImportMountPoint`] request.
+    fn import_mount_point(
+        &mut self,
+        req: ImportMountPoint,
+        output_tx: &Sender<Noun>,
+    ) -> Option<Noun> {
+        let name = req.mount_point;
+        let mount_point = match self.mount_points.remove(&name) {
+            Some(mount_point) => mount_point,
+            // Create a new mount point if the driver doesn't recognize the mount point name.
+            None => match MountPoint::new(name.clone(), &self.config) {
+                Ok(mount_point) => mount_point,
                 Err(err) => {
-                    warn!(
-                        target: Self::name(),
-                        "failed to read {}: {}",
-                        path.display(),
-                        err
-                    );
-                    continue;
+                    warn!("failed to create new mount point {}: {}", name, err);
+                    return None;
+                }
+            },
+        };
+
+        let res = mount_point.import(&req.archive);
+        self.mount_points.insert(name.clone(), mount_point);
+        match res {
+            Ok(count) => {
+                info!(
+                    target: Self::name(),
+                    "imported {} files from {} into {}",
+                    count,
+                    req.archive.display(),
+                    name
+                );
+                self.commit(name, false, output_tx)
+            }
+            Err(err) => {
+                warn!(
+                    target: Self::name(),
+                    "failed to import {} into {}: {}",
+                    req.archive.display(),
+                    name,
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    /// Computes the list of changes made to a mount point since it was last committed.
+    ///
+    /// If `dry_run` is `true`, the changes are computed against a copy of the mount point, leaving
+    /// the driver's record of the mount point untouched. [`Progress`] is reported to the output
+    /// task while the mount point is scanned and hashed.
+    fn commit(
+        &mut self,
+        name: PathComponent,
+        dry_run: bool,
+        output_tx: &Sender<Noun>,
+    ) -> Option<Noun> {
+        // We have to remove our mount point from `self.mount_points` so that we take ownership
+        // (as opposed to having a reference).
+        let mount_point = if dry_run {
+            self.mount_points.get(&name).cloned()
+        } else {
+            self.mount_points.remove(&name)
+        };
+        let mount_point = match mount_point {
+            Some(mount_point) => mount_point,
+            None => {
+                info!("mount point {} is not actively mounted", name);
+                return None;
+            }
+        };
+
+        let mut progress = Progress::new(name.clone(), output_tx);
+        let (mut mount_point, old_entries) = match mount_point.scan(Some(&mut progress)) {
+            Ok(res_tuple) => res_tuple,
+            Err((mount_point, err)) => {
+                warn!(
+                    target: Self::name(),
+                    "failed to scan{}: {}",
+                    mount_point.path.display(),
+                    err
+                );
+                if !dry_run {
+                    self.mount_points.insert(name, mount_point);
+                }
+                return None;
+            }
+        };
+
+        let mut changes: Vec<Cell> = Vec::new();
+        let null = Rc::new(Noun::null());
+        let mime = Rc::<Noun>::from(Cell::from([
+            Atom::from("text"),
+            Atom::from("plain"),
+            Atom::null(),
+        ]));
+        // Files with identical contents share a single noun, which cuts the memory used by a
+        // commit of a mount point with many identical files.
+        let mut contents: HashMap<Hash, Rc<Noun>> = HashMap::new();
+
+        // Index the committed entries that have been removed by identity and by hash so that
+        // renamed files can be detected.
+        let mut removed_by_id: HashMap<FileId, PathBuf> = HashMap::new();
+        let mut removed_by_hash: HashMap<Hash, Vec<PathBuf>> = HashMap::new();
+        for (path, entry) in &old_entries {
+            if let Some(hash) = &entry.hash {
+                if let Some(id) = entry.id {
+                    removed_by_id.insert(id, path.clone());
+                }
+                removed_by_hash
+                    .entry(hash.clone())
+                    .or_default()
+                    .push(path.clone());
+            }
+        }
+        let mut removed = old_entries;
+
+        // The hash and size of each file that has been read, so that a file with several hard
+        // links is only read and hashed once.
+        let mut hashed: HashMap<FileId, (Hash, u64)> = HashMap::new();
+
+        // Record entries that have been added or updated.
+        for (path, entry) in &mut mount_point.entries {
+            // A new file with the same identity, size, and modification time as a removed file was
+            // renamed without being edited, so its contents don't need to be hashed again.
+            let moved = match (&entry.hash, entry.id) {
+                (None, Some(id)) => removed_by_id
+                    .get(&id)
+                    .filter(|old_path| {
+                        removed.get(*old_path).is_some_and(|old_entry| {
+                            old_entry.len == entry.len && old_entry.mtime == entry.mtime
+                        })
+                    })
+                    .and_then(|old_path| removed.remove_entry(old_path)),
+                _ => None,
+            };
+            // This is safe to unwrap because only committed entries are indexed by identity.
+            let moved_hash = moved
+                .as_ref()
+                .map(|(_old_path, old_entry)| old_entry.hash.clone().unwrap());
+            let linked = entry.id.and_then(|id| hashed.get(&id)).cloned();
+            let (new_hash, bytes) = match (moved_hash, linked) {
+                // The renamed file's contents have already been recorded.
+                (Some(hash), _) if contents.contains_key(&hash) => (hash, None),
+                // Another hard link to the file has already been hashed, and either this link is
+                // unchanged or the file's contents have already been recorded.
+                (None, Some((hash, len)))
+                    if Some(&hash) == entry.hash.as_ref() || contents.contains_key(&hash) =>
+                {
+                    entry.len = len;
+                    (hash, None)
+                }
+                (moved_hash, _) => {
+                    // Read the contents of the file.
+                    let bytes = match fs::read(path) {
+                        Ok(bytes) => bytes,
+                        Err(err) => {
+                            warn!(
+                                target: Self::name(),
+                                "failed to read {}: {}",
+                                path.display(),
+                                err
+                            );
+                            // The file can't be recorded, so its removal from its old path is
+                            // recorded instead.
+                            if let Some((old_path, old_entry)) = moved {
+                                removed.insert(old_path, old_entry);
+                            }
+                            continue;
+                        }
+                    };
+
+                    entry.len = bytes.len() as u64;
+
+                    // Compute the file's new hash unless it was renamed.
+                    let hash = match moved_hash {
+                        Some(hash) => hash,
+                        None => {
+                            progress.hashed_bytes(entry.len);
+                            Hash::from(&bytes[..])
+                        }
+                    };
+                    if let Some(id) = entry.id {
+                        hashed.insert(id, (hash.clone(), entry.len));
+                    }
+                    (hash, Some(bytes))
                 }
             };
 
-            // Compute the file's new hash and compare it to the old hash.
-            let new_hash = Hash::from(&bytes[..]);
             // If the hash didn't change, skip this entry.
-            if Some(&new_hash) == old_hash.as_ref() {
+            if Some(&new_hash) == entry.hash.as_ref() {
                 continue;
             }
 
-            // Convert the file's absolute path to a mount-point-relative path.
-            let path = match path.strip_prefix(&mount_point.path) {
-                Ok(path) => path,
-                Err(err) => {
-                    warn!(
-                        target: Self::name(),
-                        "failed to strip {} from {}: {}",
-                        mount_point.path.display(),
-                        path.display(),
-                        err
-                    );
+            let knots = match Self::relative_knots(&mount_point.path, path) {
+                Some(knots) => knots,
+                None => {
+                    // The file can't be recorded, so its removal from its old path is recorded
+                    // instead.
+                    if let Some((old_path, old_entry)) = moved {
+                        removed.insert(old_path, old_entry);
+                    }
                     continue;
                 }
             };
 
-            // Convert into a list of knots.
-            let path = match KnotList::try_from(path) {
-                Ok(path) => path,
-                Err(_err) => {
-                    warn!(
+            // A new file with the same contents as a removed file is the result of a rename, in
+            // which case the removal and the addition are recorded next to each other.
+            let old_path = match moved {
+                Some((old_path, _old_entry)) => Some(old_path),
+                None if entry.hash.is_none() => {
+                    removed_by_hash.get_mut(&new_hash).and_then(|old_paths| {
+                        while let Some(old_path) = old_paths.pop() {
+                            if removed.remove(&old_path).is_some() {
+                                return Some(old_path);
+                            }
+                        }
+                        None
+                    })
+                }
+                None => None,
+            };
+            if let Some(old_path) = old_path {
+                info!(
+                    target: Self::name(),
+                    "detected rename of {} to {}",
+                    old_path.display(),
+                    path.display()
+                );
+                match Self::relative_knots(&mount_point.path, &old_path) {
+                    // Append [<old_path> 0] to the list of changes.
+                    Some(old_knots) => changes.push(Cell::from([
+                        Rc::<Noun>::from(Noun::from(old_knots)),
+                        null.clone(),
+                    ])),
+                    None => warn!(
                         target: Self::name(),
-                        "failed to convert {} into a list of knots",
+                        "failed to record removal of {} renamed to {}",
+                        old_path.display(),
                         path.display()
-                    );
-                    continue;
+                    ),
                 }
-            };
+            }
 
             // Append
             //
@@ -246,65 +1043,77 @@ impl FileSystem {
             // ]
             //
             // to the list of changes.
-            let change = Cell::from([
-                Rc::<Noun>::from(Noun::from(path)),
-                null.clone(),
-                Rc::<Noun>::from(Cell::from([
-                    Noun::from(Cell::from([
-                        Atom::from("text"),
-                        Atom::from("plain"),
-                        Atom::null(),
-                    ])),
-                    Noun::from(Atom::from(bytes.len())),
-                    Noun::from(Atom::from(bytes)),
-                ])),
-            ]);
+            let content = contents
+                .entry(new_hash.clone())
+                .or_insert_with(|| {
+                    // This is safe to unwrap because the contents of a file are only left unread
+                    // if they've already been recorded.
+                    let bytes = bytes.unwrap();
+                    Rc::<Noun>::from(Cell::from([
+                        mime.clone(),
+                        Rc::<Noun>::from(Noun::from(Atom::from(bytes.len()))),
+                        Rc::<Noun>::from(Noun::from(Atom::from(bytes))),
+                    ]))
+                })
+                .clone();
+            let change = Cell::from([Rc::<Noun>::from(Noun::from(knots)), null.clone(), content]);
             changes.push(change);
 
             // TODO: verify this updates the map in-place.
-            *old_hash = Some(new_hash);
+            entry.hash = Some(new_hash);
         }
 
         // Record entries that have been removed.
-        for (path, _hash) in old_entries {
-            // Convert the file's absolute path to a mount-point-relative path.
-            let path = match path.strip_prefix(&mount_point.path) {
-                Ok(path) => path,
-                Err(err) => {
-                    warn!(
-                        target: Self::name(),
-                        "failed to strip {} from {}: {}",
-                        mount_point.path.display(),
-                        path.display(),
-                        err
-                    );
-                    continue;
-                }
-            };
-
-            // Convert into a list of knots.
-            let path = match KnotList::try_from(path) {
-                Ok(path) => path,
-                Err(_err) => {
-                    warn!(
-                        target: Self::name(),
-                        "failed to convert {} into a list of knots",
-                        path.display()
-                    );
-                    continue;
-                }
+        for path in removed.into_keys() {
+            let knots = match Self::relative_knots(&mount_point.path, &path) {
+                Some(knots) => knots,
+                None => continue,
             };
 
             // Append [<path> 0] to the list of changes.
-            let change = Cell::from([Rc::<Noun>::from(Noun::from(path)), null.clone()]);
+            let change = Cell::from([Rc::<Noun>::from(Noun::from(knots)), null.clone()]);
             changes.push(change);
         }
 
-        self.mount_points.insert(req.mount_point, mount_point);
+        if !dry_run {
+            self.mount_points.insert(name, mount_point);
+        }
         // This is safe to unwrap because the conversion from `Cell` to `Noun` will never fail.
         Some(convert!(changes.into_iter() => Noun).unwrap())
     }
 
+    /// Converts the absolute path to a file within a mount point into a mount-point-relative list
+    /// of knots, logging a warning if the conversion fails.
+    fn relative_knots(mount_point_path: &Path, path: &Path) -> Option<KnotList<Atom>> {
+        // Convert the file's absolute path to a mount-point-relative path.
+        let path = match path.strip_prefix(mount_point_path) {
+            Ok(path) => path,
+            Err(err) => {
+                warn!(
+                    target: Self::name(),
+                    "failed to strip {} from {}: {}",
+                    mount_point_path.display(),
+                    path.display(),
+                    err
+                );
+                return None;
+            }
+        };
+
+        // Convert into a list of knots.
+        match KnotList::try_from(path) {
+            Ok(knots) => Some(knots),
+            Err(_err) => {
+                warn!(
+                    target: Self::name(),
+                    "failed to convert {} into a list of knots",
+                    path.display()
+                );
+                None
+            }
+        }
+    }
+
     /// Handles a [`DeleteMountPoint`] request.
     fn delete_mount_point(&mut self, req: DeleteMountPoint) {
         let mount_point = match self.mount_points.remove(&req.mount_point) {
@@ -315,9 +1124,41 @@ impl FileSystem {
             }
         };
 
-        // Remove the mount point from the file system.
         let path = &mount_point.path;
-        if let Err(err) = fs::remove_dir_all(path) {
+        if self.config.keep_unmounted {
+            info!(
+                target: Self::name(),
+                "unmounted {} without removing it",
+                path.display()
+            );
+        } else if let Some(retention) = self.config.trash_retention {
+            // Move the mount point into the trash instead of removing it.
+            let trash = match Trash::new() {
+                Ok(trash) => trash,
+                Err(err) => {
+                    warn!(target: Self::name(), "failed to locate trash: {}", err);
+                    return;
+                }
+            };
+            if let Err(err) = trash.put(path, &req.mount_point) {
+                warn!(
+                    target: Self::name(),
+                    "failed to move {} to {}: {}",
+                    path.display(),
+                    trash.path.display(),
+                    err
+                );
+            }
+            if let Err(err) = trash.empty(retention) {
+                warn!(
+                    target: Self::name(),
+                    "failed to empty {}: {}",
+                    trash.path.display(),
+                    err
+                );
+            }
+        } else if let Err(err) = fs::remove_dir_all(path) {
+            // Remove the mount point from the file system.
             warn!(
                 target: Self::name(),
                 "failed to remove {}: {}",
@@ -327,13 +1168,68 @@ impl FileSystem {
         }
     }
 
+    /// Handles a [`RemountMountPoint`] request.
+    ///
+    /// The files already in the new directory are left as is and committed, so nothing is copied
+    /// from the old directory, which is also left as is.
+    fn remount_mount_point(
+        &mut self,
+        req: RemountMountPoint,
+        output_tx: &Sender<Noun>,
+    ) -> Option<Noun> {
+        let mount_point = match self.mount_points.get_mut(&req.mount_point) {
+            Some(mount_point) => mount_point,
+            None => {
+                info!(
+                    target: Self::name(),
+                    "mount point {} is not actively mounted", req.mount_point
+                );
+                return None;
+            }
+        };
+
+        let path = match fs::canonicalize(&req.path) {
+            Ok(path) if path.is_dir() => path,
+            Ok(path) => {
+                warn!(
+                    target: Self::name(),
+                    "failed to move {} to {}: not a directory",
+                    req.mount_point,
+                    path.display()
+                );
+                return None;
+            }
+            Err(err) => {
+                warn!(
+                    target: Self::name(),
+                    "failed to move {} to {}: {}",
+                    req.mount_point,
+                    req.path.display(),
+                    err
+                );
+                return None;
+            }
+        };
+        info!(
+            target: Self::name(),
+            "moving {} from {} to {}",
+            req.mount_point,
+            mount_point.path.display(),
+            path.display()
+        );
+        mount_point.rebase(path);
+        self.commit(req.mount_point, false, output_tx)
+    }
+
     /// Handles a [`ScanMountPoints`] request.
-    fn scan_mount_points(&mut self, req: ScanMountPoints) {
+    ///
+    /// [`Progress`] is reported to the output task while each mount point is scanned.
+    fn scan_mount_points(&mut self, mut req: ScanMountPoints, output_tx: &Sender<Noun>) {
         for name in req.mount_points {
-            let mount_point = match self.mount_points.remove(&name) {
+            let mut mount_point = match self.mount_points.remove(&name) {
                 Some(mount_point) => mount_point,
                 // Create a new mount point if the driver doesn't recognize the mount point name.
-                None => match MountPoint::new(name.clone()) {
+                None => match MountPoint::new(name.clone(), &self.config) {
                     Ok(mount_point) => mount_point,
                     Err(err) => {
                         warn!("failed to create new mount point {}: {}", name, err);
@@ -341,7 +1237,11 @@ impl FileSystem {
                     }
                 },
             };
-            match mount_point.scan() {
+            if let Some(prefixes) = req.prefixes.remove(&name) {
+                mount_point.limit(prefixes);
+            }
+            let mut progress = Progress::new(name.clone(), output_tx);
+            match mount_point.scan(Some(&mut progress)) {
                 Ok((mount_point, _old_entries)) => {
                     self.mount_points.insert(name, mount_point);
                 }
@@ -360,6 +1260,7 @@ impl FileSystem {
 
     /// Handles an [`UpdateFileSystem`] request.
     fn update_file_system(&mut self, req: UpdateFileSystem) {
+        let fsync = self.config.fsync;
         let mount_point = match self.mount_points.get_mut(&req.mount_point) {
             Some(mount_point) => mount_point,
             None => {
@@ -371,23 +1272,68 @@ impl FileSystem {
             }
         };
 
+        // The files and directories to flush to disk once all changes have been applied.
+        let mut dirty_files = HashSet::new();
+        let mut dirty_dirs = HashSet::new();
+
         for change in req.changes {
             match change {
                 Change::EditFile { path, bytes } => {
-                    let path: PathBuf = [&mount_point.path, &path].iter().collect();
+                    if !mount_point.includes(&path) {
+                        debug!(
+                            target: Self::name(),
+                            "skipping change to {}, which is excluded from {}",
+                            path.display(),
+                            req.mount_point
+                        );
+                        continue;
+                    }
+
+                    let path = match mount_point.resolve(&path) {
+                        Ok(path) => path,
+                        Err(err) => {
+                            warn!(
+                                target: Self::name(),
+                                "rejecting change to {}: {}",
+                                path.display(),
+                                err
+                            );
+                            continue;
+                        }
+                    };
 
                     let new_hash = Hash::from(&bytes[..]);
                     // Don't update the file if the hash hasn't changed.
-                    if let Some(Some(old_hash)) = mount_point.entries.get(&path) {
+                    if let Some(Entry {
+                        hash: Some(old_hash),
+                        ..
+                    }) = mount_point.entries.get(&path)
+                    {
                         if new_hash == *old_hash {
                             continue;
                         }
                     }
 
                     // Write the updated file contents to the file system.
-                    match fs::write(&path, bytes) {
+                    let len = bytes.len() as u64;
+                    match mount_point.write(&path, &bytes) {
                         Ok(()) => {
-                            mount_point.entries.insert(path, Some(new_hash));
+                            if fsync {
+                                if let Some(parent) = path.parent() {
+                                    dirty_dirs.insert(parent.to_path_buf());
+                                }
+                                dirty_files.insert(path.clone());
+                            }
+                            let metadata = fs::metadata(&path).ok();
+                            let entry = Entry {
+                                hash: Some(new_hash),
+                                len,
+                                mtime: metadata
+                                    .as_ref()
+                                    .and_then(|metadata| metadata.modified().ok()),
+                                id: metadata.as_ref().and_then(file_id),
+                            };
+                            mount_point.entries.insert(path, entry);
                         }
                         Err(err) => {
                             warn!(
@@ -401,10 +1347,38 @@ impl FileSystem {
                 }
 
                 Change::RemoveFile { path } => {
-                    let path: PathBuf = [&mount_point.path, &path].iter().collect();
+                    if !mount_point.includes(&path) {
+                        debug!(
+                            target: Self::name(),
+                            "skipping change to {}, which is excluded from {}",
+                            path.display(),
+                            req.mount_point
+                        );
+                        continue;
+                    }
+
+                    let path = match mount_point.resolve(&path) {
+                        Ok(path) => path,
+                        Err(err) => {
+                            warn!(
+                                target: Self::name(),
+                                "rejecting change to {}: {}",
+                                path.display(),
+                                err
+                            );
+                            continue;
+                        }
+                    };
+
                     // Remove the file from the file system.
                     match fs::remove_file(&path) {
                         Ok(()) => {
+                            if fsync {
+                                if let Some(parent) = path.parent() {
+                                    dirty_dirs.insert(parent.to_path_buf());
+                                }
+                                dirty_files.remove(&path);
+                            }
                             mount_point.entries.remove(&path);
                         }
                         Err(err) => {
@@ -419,7 +1393,311 @@ impl FileSystem {
                 }
             }
         }
+
+        // Flush file contents before the directory entries that refer to them.
+        for path in dirty_files.iter().chain(dirty_dirs.iter()) {
+            if let Err(err) = sync_path(path) {
+                warn!(
+                    target: Self::name(),
+                    "failed to flush {} to disk: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    /// Handles a [`VerifyMountPoint`] request.
+    ///
+    /// The manifest is sent to the output task in chunks of at most [`Self::MANIFEST_CHUNK_LEN`]
+    /// entries as it's computed, so that the manifest of a large mount point is never held in
+    /// memory all at once. Each chunk is of the form:
+    ///
+    /// ```text
+    /// [%manifest <mount_point> <last> <entry_list>]
+    /// ```
+    ///
+    /// where `<mount_point>` is the name of the mount point, `<last>` is a loobean that is `%.y`
+    /// only for the final chunk, and `<entry_list>` is a null-terminated list of entries of the
+    /// form `[<path_list> <hash> <mtime>]`. `<path_list>` is the mount-point-relative path of a
+    /// file, `<hash>` is the [`Hash`] of the file's contents, and `<mtime>` is the [`Date`] the
+    /// file was last modified, or `0` if the modification time is unavailable. Entries are sorted
+    /// by path.
+    fn verify_mount_point(&self, req: VerifyMountPoint, output_tx: &Sender<Noun>) {
+        let mount_point = match self.mount_points.get(&req.mount_point) {
+            Some(mount_point) => mount_point.clone(),
+            None => {
+                info!(
+                    target: Self::name(),
+                    "mount point {} is not actively mounted", req.mount_point
+                );
+                return;
+            }
+        };
+        let mount_point = match mount_point.scan(None) {
+            Ok((mount_point, _old_entries)) => mount_point,
+            Err((mount_point, err)) => {
+                warn!(
+                    target: Self::name(),
+                    "failed to scan {}: {}",
+                    mount_point.path.display(),
+                    err
+                );
+                return;
+            }
+        };
+
+        let mut files: Vec<(PathBuf, Entry)> = mount_point.entries.into_iter().collect();
+        files.sort_by(|(a_path, _), (b_path, _)| a_path.cmp(b_path));
+        let chunk_count = files.chunks(Self::MANIFEST_CHUNK_LEN).len().max(1);
+        let mut files = files.into_iter();
+        for i in 0..chunk_count {
+            let mut entries = Vec::new();
+            for (path, entry) in files.by_ref().take(Self::MANIFEST_CHUNK_LEN) {
+                let bytes = match fs::read(&path) {
+                    Ok(bytes) => bytes,
+                    Err(err) => {
+                        warn!(
+                            target: Self::name(),
+                            "failed to read {}: {}",
+                            path.display(),
+                            err
+                        );
+                        continue;
+                    }
+                };
+                let knots = match Self::relative_knots(&mount_point.path, &path) {
+                    Some(knots) => knots,
+                    None => continue,
+                };
+                let mtime = match entry.mtime {
+                    Some(mtime) => Noun::from(Date(mtime)),
+                    None => Noun::null(),
+                };
+                entries.push(Cell::from([
+                    Noun::from(knots),
+                    Noun::from(Hash::from(&bytes[..])),
+                    mtime,
+                ]));
+            }
+
+            let last = i + 1 == chunk_count;
+            let chunk = Noun::from(Cell::from([
+                Noun::from(Atom::from("manifest")),
+                Noun::from(Knot::from(req.mount_point.clone())),
+                // `%.y` is 0 and `%.n` is 1.
+                Noun::from(Atom::from(u8::from(!last))),
+                // This is safe to unwrap because the conversion from `Cell` to `Noun` will never
+                // fail.
+                convert!(entries.into_iter() => Noun).unwrap(),
+            ]));
+            if let Err(_chunk) = output_tx.blocking_send(chunk) {
+                warn!(
+                    target: Self::name(),
+                    "failed to send manifest chunk {} of {} to output task",
+                    i + 1,
+                    chunk_count
+                );
+                return;
+            }
+        }
+        info!(
+            target: Self::name(),
+            "sent manifest of {} to output task", req.mount_point
+        );
+    }
+
+    /// Spawns a task to handle the requests for a single mount point in the order they're
+    /// received.
+    ///
+    /// The driver dispatches each request to the task for the request's mount point, so requests
+    /// for distinct mount points are handled concurrently while requests for the same mount point
+    /// are handled one at a time. Handling a request walks and hashes the mount point with
+    /// blocking file system calls, so the task runs on a thread of its own.
+    ///
+    /// If `prev_task` is the task of a deleted mount point of the same name, the new task waits
+    /// for it to finish before handling any requests so that the two never touch the same
+    /// directory at once.
+    fn handle_mount_point_requests(
+        mut self,
+        prev_task: Option<JoinHandle<()>>,
+        mut queue_rx: UnboundedReceiver<Request>,
+        output_tx: Sender<Noun>,
+    ) -> JoinHandle<()> {
+        task::spawn_blocking(move || {
+            if let Some(prev_task) = prev_task {
+                if let Err(err) = Handle::current().block_on(prev_task) {
+                    warn!(target: Self::name(), "previous handling task failed: {}", err);
+                }
+            }
+            while let Some(req) = queue_rx.blocking_recv() {
+                match req {
+                    Request::CommitMountPoint(req) => {
+                        if let Some(resp) = self.commit_mount_point(req, &output_tx) {
+                            if let Err(_resp) = output_tx.blocking_send(resp) {
+                                warn!(
+                                    target: Self::name(),
+                                    "failed to send committed file system changes to output task"
+                                );
+                            } else {
+                                info!(
+                                    target: Self::name(),
+                                    "sent committed file system changes to output task"
+                                );
+                            }
+                        }
+                    }
+                    Request::DeleteMountPoint(req) => self.delete_mount_point(req),
+                    Request::DiskUsage(req) => {
+                        if let Some(resp) = self.disk_usage(req) {
+                            if let Err(_resp) = output_tx.blocking_send(resp) {
+                                warn!(
+                                    target: Self::name(),
+                                    "failed to send disk usage to output task"
+                                );
+                            } else {
+                                info!(
+                                    target: Self::name(),
+                                    "sent disk usage to output task"
+                                );
+                            }
+                        }
+                    }
+                    Request::DryRunCommitMountPoint(req) => {
+                        if let Some(resp) = self.dry_run_commit_mount_point(req, &output_tx) {
+                            if let Err(_resp) = output_tx.blocking_send(resp) {
+                                warn!(
+                                    target: Self::name(),
+                                    "failed to send previewed file system changes to output task"
+                                );
+                            } else {
+                                info!(
+                                    target: Self::name(),
+                                    "sent previewed file system changes to output task"
+                                );
+                            }
+                        }
+                    }
+                    Request::ExportMountPoint(req) => self.export_mount_point(req),
+                    Request::ImportMountPoint(req) => {
+                        if let Some(resp) = self.import_mount_point(req, &output_tx) {
+                            if let Err(_resp) = output_tx.blocking_send(resp) {
+                                warn!(
+                                    target: Self::name(),
+                                    "failed to send imported file system changes to output task"
+                                );
+                            } else {
+                                info!(
+                                    target: Self::name(),
+                                    "sent imported file system changes to output task"
+                                );
+                            }
+                        }
+                    }
+                    Request::RemountMountPoint(req) => {
+                        if let Some(resp) = self.remount_mount_point(req, &output_tx) {
+                            if let Err(_resp) = output_tx.blocking_send(resp) {
+                                warn!(
+                                    target: Self::name(),
+                                    "failed to send remounted file system changes to output task"
+                                );
+                            } else {
+                                info!(
+                                    target: Self::name(),
+                                    "sent remounted file system changes to output task"
+                                );
+                            }
+                        }
+                    }
+                    Request::ScanMountPoints(req) => self.scan_mount_points(req, &output_tx),
+                    Request::UpdateFileSystem(req) => self.update_file_system(req),
+                    Request::VerifyMountPoint(req) => self.verify_mount_point(req, &output_tx),
+                }
+            }
+        })
+    }
+}
+
+/// Reports the progress of a long-running scan or commit of a mount point to the output task.
+///
+/// Progress is reported at most once every [`Progress::INTERVAL`], and only if the output channel
+/// has room for it, so reporting progress never holds up the scan or commit itself. A progress
+/// report is:
+///
+/// ```text
+/// [%progress <mount_point> <files_scanned> <bytes_hashed>]
+/// ```
+struct Progress<'a> {
+    /// The name of the mount point being scanned or committed.
+    mount_point: PathComponent,
+
+    /// The channel to the output task.
+    output_tx: &'a Sender<Noun>,
+
+    /// The number of files scanned so far.
+    files_scanned: u64,
+
+    /// The number of bytes hashed so far.
+    bytes_hashed: u64,
+
+    /// When progress was last reported.
+    last_report: Instant,
+}
+
+impl<'a> Progress<'a> {
+    /// The minimum time between progress reports.
+    const INTERVAL: Duration = Duration::from_secs(1);
+
+    fn new(mount_point: PathComponent, output_tx: &'a Sender<Noun>) -> Self {
+        Self {
+            mount_point,
+            output_tx,
+            files_scanned: 0,
+            bytes_hashed: 0,
+            last_report: Instant::now(),
+        }
+    }
+
+    /// Records that a file was scanned.
+    fn scanned_file(&mut self) {
+        self.files_scanned += 1;
+        self.report();
+    }
+
+    /// Records that a number of bytes were hashed.
+    fn hashed_bytes(&mut self, len: u64) {
+        self.bytes_hashed += len;
+        self.report();
+    }
+
+    /// Sends a progress report to the output task if one is due.
+    fn report(&mut self) {
+        if self.last_report.elapsed() < Self::INTERVAL {
+            return;
+        }
+        self.last_report = Instant::now();
+        let report = Noun::from(Cell::from([
+            Noun::from(Atom::from("progress")),
+            Noun::from(Knot::from(self.mount_point.clone())),
+            Noun::from(Atom::from(self.files_scanned)),
+            Noun::from(Atom::from(self.bytes_hashed)),
+        ]));
+        if let Err(_report) = self.output_tx.try_send(report) {
+            debug!(
+                target: FileSystem::name(),
+                "dropped progress report for {}", self.mount_point
+            );
+        }
+    }
+}
+
+/// Flushes a file or directory to disk.
+fn sync_path(path: &Path) -> io::Result<()> {
+    // Windows doesn't allow directories to be opened with `File::open()`.
+    if cfg!(windows) && path.is_dir() {
+        return Ok(());
     }
+    fs::File::open(path)?.sync_all()
 }
 
 /// Implements the [`Driver`] trait for the [`FileSystem`] driver.
@@ -427,7 +1705,13 @@ macro_rules! impl_driver {
     ($input_src:ty, $output_sink:ty) => {
         impl Driver<$input_src, $output_sink> for FileSystem {
             fn new() -> Result<Self, Status> {
-                todo!()
+                let mount_points = HashMap::new();
+                let config = Config::from_env();
+                debug!(target: Self::name(), "initialized driver");
+                Ok(Self {
+                    mount_points,
+                    config,
+                })
             }
 
             fn name() -> &'static str {
@@ -435,37 +1719,71 @@ macro_rules! impl_driver {
             }
 
             fn handle_requests(
-                mut self,
+                self,
                 mut input_rx: Receiver<Noun>,
                 output_tx: Sender<Noun>,
             ) -> JoinHandle<Status> {
                 let task = tokio::spawn(async move {
+                    // The queue of requests for each mount point, each of which is drained by a
+                    // separate task.
+                    //
+                    // The queues are unbounded so that a busy mount point never holds up the
+                    // requests for other mount points, and so that no request is ever dropped.
+                    let mut queues: HashMap<PathComponent, UnboundedSender<Request>> =
+                        HashMap::new();
+                    // The task draining each queue, which is kept after the mount point is
+                    // deleted so that a new task for the same name can wait for it.
+                    let mut tasks: HashMap<PathComponent, JoinHandle<()>> = HashMap::new();
                     while let Some(req) = input_rx.recv().await {
-                        // TODO: think about whether requests can/should be handled asyncrhonously.
-                        match Request::try_from(req) {
-                            Ok(Request::CommitMountPoint(req)) => {
-                                if let Some(resp) = self.commit_mount_point(req) {
-                                    if let Err(_resp) = output_tx.send(resp).await {
-                                        warn!(
-                                            target: Self::name(),
-                                            "failed to send committed file system changes to output task"
-                                        );
-                                    } else {
-                                        info!(
-                                            target: Self::name(),
-                                            "sent committed file system changes to output task"
-                                        );
-                                    }
-                                }
-                            }
-                            Ok(Request::DeleteMountPoint(req)) => self.delete_mount_point(req),
-                            Ok(Request::ScanMountPoints(req)) => self.scan_mount_points(req),
-                            Ok(Request::UpdateFileSystem(req)) => self.update_file_system(req),
-                            _ => {
+                        let reqs = match Request::try_from(req) {
+                            Ok(req) => req.into_mount_point_requests(),
+                            Err(_) => {
                                 warn!(target: Self::name(), "skipping unidentifiable request");
+                                continue;
+                            }
+                        };
+                        for (name, req) in reqs {
+                            let delete = matches!(req, Request::DeleteMountPoint(_));
+                            let queue = queues.entry(name.clone()).or_insert_with(|| {
+                                let (queue_tx, queue_rx) = mpsc::unbounded_channel();
+                                let driver = Self {
+                                    mount_points: HashMap::new(),
+                                    config: self.config.clone(),
+                                };
+                                let prev_task = tasks.remove(&name);
+                                let task = driver.handle_mount_point_requests(
+                                    prev_task,
+                                    queue_rx,
+                                    output_tx.clone(),
+                                );
+                                tasks.insert(name.clone(), task);
+                                debug!(target: Self::name(), "spawned handling task for {}", name);
+                                queue_tx
+                            });
+                            if let Err(_req) = queue.send(req) {
+                                warn!(
+                                    target: Self::name(),
+                                    "failed to send request to handling task for {}",
+                                    name
+                                );
+                            }
+                            // The task exits once it has deleted the mount point, and a later
+                            // request for a mount point of the same name spawns a new task that
+                            // waits for the old one to exit.
+                            if delete {
+                                queues.remove(&name);
                             }
                         }
                     }
+
+                    // Close every queue so that each mount point's task exits once it has handled
+                    // the requests remaining in its queue.
+                    drop(queues);
+                    for (_name, task) in tasks {
+                        if let Err(err) = task.await {
+                            warn!(target: Self::name(), "handling task failed: {}", err);
+                        }
+                    }
                     Status::Success
                 });
                 debug!(target: Self::name(), "spawned handling task");
@@ -477,6 +1795,16 @@ macro_rules! impl_driver {
 
 impl_driver!(Stdin, Stdout);
 
+/// Provides an FFI-friendly interface for running the file system driver with `stdin` as the input
+/// source and `stdout` as the output sink.
+#[no_mangle]
+pub extern "C" fn file_system_run() -> Status {
+    match FileSystem::new() {
+        Ok(driver) => driver.run(tokio::io::stdin(), tokio::io::stdout()),
+        Err(status) => status,
+    }
+}
+
 //==================================================================================================
 // Path Manipulation
 //==================================================================================================
@@ -484,8 +1812,9 @@ impl_driver!(Stdin, Stdout);
 /// A single component of a file system path.
 ///
 /// A [`PathComponent`] must only be created by converting a [`Knot`] with `try_from()`, which
-/// ensures that [`Knot`]s that cause issues as file system paths are properly escaped. As a result
-/// of this requirement, a [`PathComponent`] is guaranteed to never be:
+/// decodes the escaped non-ASCII characters of the [`Knot`] (see [`Knot`] for the escaping scheme)
+/// and ensures that [`Knot`]s that cause issues as file system paths are properly escaped. As a
+/// result of this requirement, a [`PathComponent`] is guaranteed to never be:
 /// - the empty string,
 /// - `.`,
 /// - `..`, or
@@ -495,6 +1824,13 @@ impl_driver!(Stdin, Stdout);
 /// - `!.`,
 /// - `!..`, and
 /// - `!!<some_chars>`.
+///
+/// On Windows, a [`PathComponent`] is additionally guaranteed to never be a reserved device name
+/// (`con`, `aux`, `com1`, etc, regardless of case or extension), which is escaped by prepending a
+/// `!` (e.g. `con.hoon` yields `!con.hoon`), and to never contain a character that Windows forbids
+/// in file names or end with a `.`. Such characters (along with `%`) are escaped as `%` followed by
+/// the two-digit uppercase hexadecimal value of the character (e.g. `a:b` yields `a%3Ab`, and `..`
+/// yields `!.%2E`).
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 struct PathComponent(String);
 
@@ -518,11 +1854,18 @@ impl TryFrom<Knot<&Atom>> for PathComponent {
         let knot = atom_as_str(knot.0)?;
         // A path component should not have spaces or path separators in it.
         if !knot.contains(" ") && !knot.contains(path::MAIN_SEPARATOR) {
-            if knot.is_empty() || knot == "." || knot == ".." || knot.starts_with("!") {
-                Ok(Self(format!("!{}", knot)))
+            let knot = unescape_knot(knot);
+            let file_name = if knot.is_empty()
+                || knot == "."
+                || knot == ".."
+                || knot.starts_with("!")
+                || is_reserved_file_name(&knot)
+            {
+                format!("!{}", knot)
             } else {
-                Ok(Self(String::from(knot)))
-            }
+                knot
+            };
+            Ok(Self(escape_file_name(&file_name)))
         } else {
             Err(convert::Error::ImplType)
         }
@@ -541,21 +1884,125 @@ impl TryFrom<&Noun> for PathComponent {
     }
 }
 
+/// Determines whether a file name is reserved by the platform.
+///
+/// Windows reserves the names of DOS devices, even when followed by an extension.
+#[cfg(windows)]
+fn is_reserved_file_name(file_name: &str) -> bool {
+    const RESERVED: [&str; 4] = ["con", "prn", "aux", "nul"];
+    const NUMBERED: [&str; 2] = ["com", "lpt"];
+    let base_name = match file_name.find('.') {
+        Some(i) => &file_name[..i],
+        None => file_name,
+    }
+    .to_ascii_lowercase();
+    RESERVED.contains(&&base_name[..])
+        || (base_name.len() == 4
+            && NUMBERED.contains(&&base_name[..3])
+            && base_name.as_bytes()[3].is_ascii_digit())
+}
+
+/// Determines whether a file name is reserved by the platform.
+#[cfg(not(windows))]
+fn is_reserved_file_name(_file_name: &str) -> bool {
+    false
+}
+
+/// Escapes the characters of a file name that the platform forbids.
+///
+/// Windows forbids `<`, `>`, `:`, `"`, `/`, `\`, `|`, `?`, `*`, and control characters anywhere
+/// in a file name and `.` at the end of a file name.
+#[cfg(windows)]
+fn escape_file_name(file_name: &str) -> String {
+    let mut escaped = String::with_capacity(file_name.len());
+    for (i, c) in file_name.char_indices() {
+        let is_last = i + c.len_utf8() == file_name.len();
+        if matches!(
+            c,
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' | '%'
+        ) || c.is_ascii_control()
+            || (c == '.' && is_last)
+        {
+            escaped.push_str(&format!("%{:02X}", u32::from(c)));
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// Escapes the characters of a file name that the platform forbids.
+#[cfg(not(windows))]
+fn escape_file_name(file_name: &str) -> String {
+    String::from(file_name)
+}
+
+/// Decodes the escaped characters of a file name.
+///
+/// This is the inverse of [`escape_file_name()`].
+#[cfg(windows)]
+fn unescape_file_name(file_name: &str) -> String {
+    let mut unescaped = String::with_capacity(file_name.len());
+    let mut rest = file_name;
+    while let Some(i) = rest.find('%') {
+        unescaped.push_str(&rest[..i]);
+        rest = &rest[i..];
+        let b = rest
+            .get(1..3)
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match b {
+            Some(b) if b.is_ascii() => {
+                unescaped.push(char::from(b));
+                rest = &rest[3..];
+            }
+            _ => {
+                unescaped.push('%');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+/// Decodes the escaped characters of a file name.
+#[cfg(not(windows))]
+fn unescape_file_name(file_name: &str) -> String {
+    String::from(file_name)
+}
+
 /// A Hoon `$knot`.
 ///
-/// A `$knot` is simply an ASCII string.
+/// A `$knot` is simply an ASCII string whose characters are escaped like those of an `@ta`: `~` is
+/// represented as `~~`, `.` may be represented as `~.`, and any other character may be
+/// represented as `~<hex>.`, where `<hex>` is the Unicode code point of the character in lowercase
+/// hexadecimal without leading zeros. File names only ever have their `~` and non-ASCII characters
+/// escaped, so `ö` is represented as `~f6.` and `~zod` as `~~zod`.
+///
+/// Any `~` that doesn't begin a well-formed escape is taken literally.
 struct Knot<A: Atomish>(A);
 
 impl From<PathComponent> for Knot<Atom> {
     fn from(path_component: PathComponent) -> Self {
         debug_assert!(!path_component.0.contains(path::MAIN_SEPARATOR));
 
-        let knot = if path_component.0.chars().nth(0) == Some('!') {
-            &path_component.0[1..]
+        let file_name = unescape_file_name(&path_component.0);
+        let knot = if file_name.chars().nth(0) == Some('!') {
+            &file_name[1..]
         } else {
-            &path_component.0[..]
+            &file_name[..]
         };
-        Knot(Atom::from(knot))
+        Knot(Atom::from(escape_knot(knot)))
+    }
+}
+
+impl TryFrom<&OsStr> for Knot<Atom> {
+    type Error = ();
+
+    fn try_from(os_str: &OsStr) -> Result<Self, Self::Error> {
+        let path_component = PathComponent(String::from(os_str.to_str().ok_or(())?));
+        Ok(Self::from(path_component))
     }
 }
 
@@ -581,6 +2028,63 @@ impl From<Knot<Atom>> for Noun {
     }
 }
 
+/// Escapes the `~` and non-ASCII characters of a file name to yield the text of a [`Knot`].
+fn escape_knot(file_name: &str) -> String {
+    let mut knot = String::with_capacity(file_name.len());
+    for c in file_name.chars() {
+        if c == '~' {
+            knot.push_str("~~");
+        } else if !c.is_ascii() {
+            knot.push_str(&format!("~{:x}.", u32::from(c)));
+        } else {
+            knot.push(c);
+        }
+    }
+    knot
+}
+
+/// Decodes the escaped characters of the text of a [`Knot`] to yield a file name.
+fn unescape_knot(knot: &str) -> String {
+    let mut file_name = String::with_capacity(knot.len());
+    let mut rest = knot;
+    while let Some(i) = rest.find('~') {
+        file_name.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if let Some((c, escape_len)) = parse_knot_escape(rest) {
+            file_name.push(c);
+            rest = &rest[escape_len..];
+        } else {
+            file_name.push('~');
+            rest = &rest[1..];
+        }
+    }
+    file_name.push_str(rest);
+    file_name
+}
+
+/// Parses the escape at the beginning of the text of a [`Knot`].
+///
+/// Returns the escaped character and the length of the escape if `knot` begins with a well-formed
+/// escape (`~~`, `~.`, or `~<hex>.`) and `None` otherwise.
+fn parse_knot_escape(knot: &str) -> Option<(char, usize)> {
+    let rest = knot.strip_prefix('~')?;
+    match rest.bytes().next()? {
+        b'~' => return Some(('~', 2)),
+        b'.' => return Some(('.', 2)),
+        _ => {}
+    }
+    let hex = &rest[..rest.find('.')?];
+    if hex.is_empty()
+        || hex.starts_with('0')
+        || !hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    {
+        return None;
+    }
+    let c = char::from_u32(u32::from_str_radix(hex, 16).ok()?)?;
+    // Account for the leading `~` and trailing `.`.
+    Some((c, hex.len() + 2))
+}
+
 /// A  list of [`Knot`]s.
 ///
 /// A list of [`Knot`]s can take three forms:
@@ -608,17 +2112,14 @@ impl TryFrom<&Path> for KnotList<Atom> {
         let mut knots = Vec::new();
         if let Some(parent) = path.parent() {
             for dir in parent.components() {
-                let dir = Atom::try_from(dir.as_os_str())?;
-                knots.push(Knot(dir));
+                knots.push(Knot::try_from(dir.as_os_str())?);
             }
         }
         if let Some(file_stem) = path.file_stem() {
-            let file_stem = Atom::try_from(file_stem)?;
-            knots.push(Knot(file_stem));
+            knots.push(Knot::try_from(file_stem)?);
         }
         if let Some(file_extension) = path.extension() {
-            let file_extension = Atom::try_from(file_extension)?;
-            knots.push(Knot(file_extension));
+            knots.push(Knot::try_from(file_extension)?);
         }
         Ok(Self(knots))
     }
@@ -680,31 +2181,271 @@ impl TryFrom<KnotList<&Atom>> for PathBuf {
 /// A file system mount point.
 ///
 /// TODO: handle single file mount points.
+#[derive(Clone)]
 struct MountPoint {
     /// The absolute path to the mount point.
     path: PathBuf,
 
     /// The file system entries that exist within the mount point.
     ///
-    /// This is a map from the absolute path to a file system entry to the hash of the entry's
-    /// contents.
-    entries: HashMap<PathBuf, Option<Hash>>,
+    /// This is a map from the absolute path to a file system entry to what's known about the
+    /// entry.
+    entries: HashMap<PathBuf, Entry>,
+
+    /// The mount-point-relative directory paths that the mount point is limited to.
+    ///
+    /// If empty, the mount point isn't limited.
+    prefixes: Vec<PathBuf>,
+
+    /// The file extensions of files that are never synced to or from the mount point.
+    excluded: HashSet<String>,
+
+    /// Whether symlinks within the mount point are followed during scans.
+    follow_symlinks: bool,
 }
 
 impl MountPoint {
+    /// The maximum number of levels below the root of a mount point that are scanned.
+    const MAX_DEPTH: usize = 512;
+
+    /// The suffix of the temporary files that are written next to files before being renamed
+    /// into place.
+    const TEMP_SUFFIX: &'static str = ".urbit-tmp";
+
     /// Creates a new mount point relative to the current working directory.
-    fn new(name: PathComponent) -> io::Result<Self> {
+    ///
+    /// If the mount point already exists, its path is canonicalized so that a mount point that is
+    /// itself a symlink is consistently referred to by the path of its target.
+    fn new(name: PathComponent, config: &Config) -> io::Result<Self> {
         let path = {
             let mut path = env::current_dir()?;
-            path.push(name);
-            path
+            path.push(&name);
+            fs::canonicalize(&path).unwrap_or(path)
         };
         Ok(Self {
             path,
             entries: HashMap::new(),
+            prefixes: Vec::new(),
+            excluded: config.excluded(&name),
+            follow_symlinks: config.follow_symlinks,
         })
     }
 
+    /// Limits the mount point to the files within a list of mount-point-relative directory
+    /// paths.
+    ///
+    /// Files that are no longer within the mount point are forgotten rather than treated as
+    /// removed, so they're never reported to have been deleted. An empty list lifts the limit.
+    fn limit(&mut self, prefixes: Vec<PathBuf>) {
+        self.prefixes = prefixes;
+        let root = &self.path;
+        let prefixes = &self.prefixes;
+        self.entries.retain(|path, _entry| {
+            path.strip_prefix(root)
+                .map(|path| is_within_prefixes(path, prefixes))
+                .unwrap_or(false)
+        });
+    }
+
+    /// Moves the mount point to a different directory.
+    ///
+    /// What's known about each file is kept and applies to the file at the same
+    /// mount-point-relative path in the new directory, so the next scan or commit only finds the
+    /// differences between the two directories.
+    fn rebase(&mut self, path: PathBuf) {
+        let old_path = mem::replace(&mut self.path, path);
+        let entries = mem::take(&mut self.entries);
+        self.entries = entries
+            .into_iter()
+            .filter_map(|(entry_path, entry)| {
+                let relative_path = entry_path.strip_prefix(&old_path).ok()?;
+                Some((self.path.join(relative_path), entry))
+            })
+            .collect();
+    }
+
+    /// Determines whether a mount-point-relative path is within the mount point's path prefixes,
+    /// doesn't have an excluded extension, and isn't a temporary file.
+    fn includes(&self, path: &Path) -> bool {
+        is_within_prefixes(path, &self.prefixes)
+            && !is_excluded(path, &self.excluded)
+            && !is_temp_file(path)
+    }
+
+    /// Replaces the contents of a file within the mount point atomically, creating the file and
+    /// its parent directories if they don't exist.
+    ///
+    /// The contents are written to a temporary file next to `path`, which is then renamed to
+    /// `path`. Because the two are in the same directory, the rename can only fail with `EXDEV`
+    /// if `path` is itself a mount, in which case the contents are copied over `path` instead.
+    /// The temporary file never outlives the write.
+    ///
+    /// If the mount point follows symlinks and `path` is a symlink, the symlink is left in place
+    /// and the contents of its target are replaced instead.
+    fn write(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        self.write_with(path, bytes, |from, to| fs::rename(from, to))
+    }
+
+    /// Like [`MountPoint::write`], but renames the temporary file into place with `rename`.
+    fn write_with(
+        &self,
+        path: &Path,
+        bytes: &[u8],
+        rename: impl FnOnce(&Path, &Path) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let target;
+        let path = if self.follow_symlinks
+            && fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink())
+        {
+            target = fs::canonicalize(path)?;
+            &target
+        } else {
+            path
+        };
+        let file_name = path.file_name().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} has no file name", path.display()),
+            )
+        })?;
+        // Like vere, create any missing parent directories.
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut temp_name = OsString::from(".");
+        temp_name.push(file_name);
+        temp_name.push(Self::TEMP_SUFFIX);
+        let temp_path = path.with_file_name(temp_name);
+
+        let res = fs::write(&temp_path, bytes).and_then(|()| {
+            rename(&temp_path, path).or_else(|err| {
+                debug!(
+                    target: FileSystem::name(),
+                    "failed to rename {} to {}, copying instead: {}",
+                    temp_path.display(),
+                    path.display(),
+                    err
+                );
+                fs::copy(&temp_path, path).map(|_len| ())
+            })
+        });
+        // Remove the temporary file, which is still there unless the rename succeeded.
+        let _ = fs::remove_file(&temp_path);
+        res
+    }
+
+    /// Resolves a mount-point-relative path into an absolute path within the mount point.
+    ///
+    /// Fails if the resulting path escapes the mount point, either because `path` isn't a plain
+    /// relative path or because a directory along the way is a symlink that leads outside of the
+    /// mount point. If the mount point follows symlinks, a scan treats the targets of symlinks as
+    /// part of the mount point, so symlinks that lead outside of the mount point are allowed.
+    fn resolve(&self, path: &Path) -> io::Result<PathBuf> {
+        let escapes = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} escapes {}", path.display(), self.path.display()),
+            )
+        };
+
+        if !path
+            .components()
+            .all(|component| matches!(component, path::Component::Normal(_)))
+        {
+            return Err(escapes());
+        }
+
+        let resolved = self.path.join(path);
+        if self.follow_symlinks {
+            return Ok(resolved);
+        }
+        let root = fs::canonicalize(&self.path)?;
+        // The file may not exist yet, so canonicalize its deepest existing ancestor instead.
+        let mut ancestor = resolved.as_path();
+        let canonical = loop {
+            match fs::canonicalize(ancestor) {
+                Ok(canonical) => break canonical,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    ancestor = ancestor.parent().ok_or_else(escapes)?;
+                }
+                Err(err) => return Err(err),
+            }
+        };
+        if canonical.starts_with(&root) {
+            Ok(resolved)
+        } else {
+            Err(escapes())
+        }
+    }
+
+    /// Writes the files of a mount point to a tar archive, returning the number of files written.
+    ///
+    /// The archive is deterministic: files are written in order of path, and every header has the
+    /// same mode, owner, and modification time, so the archive depends only on the paths and
+    /// contents of the files.
+    fn export(&self, archive: &Path) -> io::Result<usize> {
+        let mut paths: Vec<&PathBuf> = self.entries.keys().collect();
+        paths.sort();
+
+        let mut builder = tar::Builder::new(fs::File::create(archive)?);
+        for path in &paths {
+            let bytes = fs::read(path)?;
+            // This is safe to unwrap because every entry is within the mount point.
+            let relative_path = path.strip_prefix(&self.path).unwrap();
+            let mut header = tar::Header::new_ustar();
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_mode(0o644);
+            header.set_uid(0);
+            header.set_gid(0);
+            header.set_mtime(0);
+            header.set_size(bytes.len() as u64);
+            builder.append_data(&mut header, relative_path, &bytes[..])?;
+        }
+        builder.into_inner()?.sync_all()?;
+        Ok(paths.len())
+    }
+
+    /// Writes the regular files in a tar archive into a mount point, returning the number of files
+    /// written.
+    ///
+    /// Files outside of the mount point's path prefixes are skipped, as are files whose paths
+    /// would escape the mount point.
+    fn import(&self, archive: &Path) -> io::Result<usize> {
+        fs::create_dir_all(&self.path)?;
+        let mut archive = tar::Archive::new(fs::File::open(archive)?);
+        let mut count = 0;
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            // Archives commonly prefix paths with `./`.
+            let relative_path: PathBuf = entry
+                .path()?
+                .components()
+                .filter(|component| *component != path::Component::CurDir)
+                .collect();
+            if !self.includes(&relative_path) {
+                continue;
+            }
+            let path = match self.resolve(&relative_path) {
+                Ok(path) => path,
+                Err(err) => {
+                    warn!("skipping {}: {}", relative_path.display(), err);
+                    continue;
+                }
+            };
+
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            io::copy(&mut entry, &mut fs::File::create(&path)?)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
     /// Scans a mount point.
     ///
     /// On success, `scan()` returns a pair consisting of the up-to-date mount point and the set of
@@ -712,47 +2453,326 @@ impl MountPoint {
     ///
     /// On failure, `scan()` returns a pair consisting of the original mount point and the
     /// [`io::Error`] that prevented the mount point from being updated.
-    fn scan(mut self) -> Result<(Self, HashMap<PathBuf, Option<Hash>>), (Self, io::Error)> {
-        /// Recursively scans a directory, adding all discovered files to a map from absolute
-        /// path to hash of the file contents.
-        fn scan_dir(dir: &Path, entries: &mut HashMap<PathBuf, Option<Hash>>) -> io::Result<()> {
-            for entry in fs::read_dir(dir)? {
-                let entry = entry?;
-                let path = entry.path();
-                let file_type = entry.file_type()?;
-                if file_type.is_dir() {
-                    scan_dir(&path, entries)?;
-                } else if file_type.is_file() && !entries.contains_key(&path) {
-                    entries.insert(path, None);
+    ///
+    /// If `progress` is given, every file that's scanned is recorded in it.
+    fn scan(
+        mut self,
+        progress: Option<&mut Progress>,
+    ) -> Result<(Self, HashMap<PathBuf, Entry>), (Self, io::Error)> {
+        /// Scans a directory tree, adding all discovered files within the path prefixes to a map
+        /// from absolute path to entry and recording the current size of each file.
+        ///
+        /// The tree is walked iteratively rather than recursively so that deep trees can't
+        /// overflow the stack. Directories more than [`MountPoint::MAX_DEPTH`] levels below `root`
+        /// are skipped, as are directories that have already been visited by another path.
+        ///
+        /// Files with an excluded extension are skipped, and the number of such files is returned.
+        /// Symlinks are ignored unless `follow_symlinks` is `true`, and temporary files are
+        /// never scanned.
+        fn scan_tree(
+            root: &Path,
+            prefixes: &[PathBuf],
+            excluded: &HashSet<String>,
+            follow_symlinks: bool,
+            entries: &mut HashMap<PathBuf, Entry>,
+            mut progress: Option<&mut Progress>,
+        ) -> io::Result<usize> {
+            let mut skipped = 0;
+            let mut visited = HashSet::new();
+            let mut dirs = vec![(root.to_path_buf(), 0)];
+            while let Some((dir, depth)) = dirs.pop() {
+                if !visited.insert(dir_id(&dir)?) {
+                    warn!("skipping {}, which was already scanned", dir.display());
+                    continue;
+                }
+
+                for entry in fs::read_dir(&dir)? {
+                    let entry = entry?;
+                    let path = entry.path();
+                    // This is safe to unwrap because `path` was found by walking `root`.
+                    let relative_path = path.strip_prefix(root).unwrap();
+                    let mut file_type = entry.file_type()?;
+                    if file_type.is_symlink() {
+                        if !follow_symlinks {
+                            continue;
+                        }
+                        file_type = match fs::metadata(&path) {
+                            Ok(metadata) => metadata.file_type(),
+                            Err(err) => {
+                                warn!("skipping symlink {}: {}", path.display(), err);
+                                continue;
+                            }
+                        };
+                    }
+
+                    if file_type.is_dir() {
+                        if depth == MountPoint::MAX_DEPTH {
+                            warn!(
+                                "skipping {}, which is more than {} levels deep",
+                                path.display(),
+                                MountPoint::MAX_DEPTH
+                            );
+                            continue;
+                        }
+                        // Only descend into directories that are or lead to a path prefix.
+                        if prefixes.is_empty()
+                            || prefixes.iter().any(|prefix| {
+                                prefix.starts_with(relative_path)
+                                    || relative_path.starts_with(prefix)
+                            })
+                        {
+                            dirs.push((path, depth + 1));
+                        }
+                    } else if file_type.is_file()
+                        && is_within_prefixes(relative_path, prefixes)
+                        && !is_temp_file(relative_path)
+                    {
+                        if is_excluded(relative_path, excluded) {
+                            skipped += 1;
+                            continue;
+                        }
+                        // Unlike `entry.metadata()`, this follows symlinks.
+                        let metadata = fs::metadata(&path)?;
+                        let len = metadata.len();
+                        let mtime = metadata.modified().ok();
+                        let id = file_id(&metadata);
+                        let entry = entries.entry(path).or_insert(Entry {
+                            hash: None,
+                            len,
+                            mtime,
+                            id,
+                        });
+                        entry.len = len;
+                        entry.mtime = mtime;
+                        entry.id = id;
+                        if let Some(progress) = progress.as_deref_mut() {
+                            progress.scanned_file();
+                        }
+                    }
                 }
-                // Ignore symlinks.
             }
-            Ok(())
+            Ok(skipped)
         }
 
         let (entries, old_entries) = self
             .entries
             .into_iter()
-            .partition(|(entry, _hash)| entry.exists());
+            .partition(|(path, _entry)| path.exists());
 
         self.entries = entries;
-        if let Err(err) = scan_dir(&self.path, &mut self.entries) {
-            Err((self, err))
-        } else {
-            Ok((self, old_entries))
+        match scan_tree(
+            &self.path,
+            &self.prefixes,
+            &self.excluded,
+            self.follow_symlinks,
+            &mut self.entries,
+            progress,
+        ) {
+            Ok(skipped) => {
+                // Report skipped files once per scan rather than once per file.
+                if skipped > 0 {
+                    info!(
+                        "skipped {} files with excluded extensions in {}",
+                        skipped,
+                        self.path.display()
+                    );
+                }
+                Ok((self, old_entries))
+            }
+            Err(err) => Err((self, err)),
+        }
+    }
+}
+
+/// Determines whether a path has any of a list of excluded file extensions.
+fn is_excluded(path: &Path, excluded: &HashSet<String>) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .map(|extension| excluded.contains(extension))
+        .unwrap_or(false)
+}
+
+/// Determines whether a path is a temporary file written by [`MountPoint::write`].
+fn is_temp_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(OsStr::to_str)
+        .map(|name| name.starts_with('.') && name.ends_with(MountPoint::TEMP_SUFFIX))
+        .unwrap_or(false)
+}
+
+/// Uniquely identifies a directory, regardless of the path that it's reached by.
+#[cfg(unix)]
+fn dir_id(dir: &Path) -> io::Result<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = fs::metadata(dir)?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+/// Uniquely identifies a directory, regardless of the path that it's reached by.
+#[cfg(not(unix))]
+fn dir_id(dir: &Path) -> io::Result<PathBuf> {
+    fs::canonicalize(dir)
+}
+
+/// A file within a mount point.
+#[derive(Clone)]
+struct Entry {
+    /// The hash of the file's contents as of the last commit or update, if any.
+    hash: Option<Hash>,
+
+    /// The size of the file in bytes as of the last scan, commit, or update.
+    len: u64,
+
+    /// The time the file was last modified as of the last scan or update, if the platform
+    /// reports it.
+    mtime: Option<SystemTime>,
+
+    /// The identity of the file as of the last scan or update, if the platform reports it.
+    ///
+    /// Hard links to the same file share an identity, which lets a commit hash a file with several
+    /// links only once. When a hard-linked file is edited in place outside of the driver, every
+    /// link changes, and the next commit reports a change at each link's path. An
+    /// [`UpdateFileSystem`] request, on the other hand, replaces the file at the given path, which
+    /// breaks the link and leaves the file's other links untouched.
+    id: Option<FileId>,
+}
+
+/// The identity of a file, which hard links to the same file share.
+type FileId = (u64, u64);
+
+/// Returns the identity of a file from its metadata.
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<FileId> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((metadata.dev(), metadata.ino()))
+}
+
+/// Returns the identity of a file from its metadata.
+///
+/// The standard library doesn't expose file identities on this platform, so hard links aren't
+/// detected.
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> Option<FileId> {
+    None
+}
+
+/// Determines whether a mount-point-relative path is within any of a list of path prefixes.
+///
+/// Every path is within an empty list of path prefixes.
+fn is_within_prefixes(path: &Path, prefixes: &[PathBuf]) -> bool {
+    prefixes.is_empty() || prefixes.iter().any(|prefix| path.starts_with(prefix))
+}
+
+/// The trash directory of a pier, which holds deleted mount points.
+///
+/// Each deleted mount point is moved to `<pier>/.trash/<timestamp>/<mount_point>`, where
+/// `<timestamp>` is `<secs>.<nanos>`, the time of deletion since the Unix epoch, followed by
+/// `-<n>` if a mount point was already deleted at the same time.
+struct Trash {
+    /// The absolute path to the trash directory.
+    path: PathBuf,
+}
+
+impl Trash {
+    /// The name of the trash directory relative to the pier.
+    const DIR: &'static str = ".trash";
+
+    /// Locates the trash directory of the pier in the current working directory.
+    fn new() -> io::Result<Self> {
+        let mut path = env::current_dir()?;
+        path.push(Self::DIR);
+        Ok(Self { path })
+    }
+
+    /// Moves the mount point at `path` named `name` into the trash.
+    fn put(&self, path: &Path, name: &PathComponent) -> io::Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(io::Error::other)?;
+        let timestamp = format!("{}.{:09}", now.as_secs(), now.subsec_nanos());
+        fs::create_dir_all(&self.path)?;
+        let mut dest = self.path.join(&timestamp);
+        // The clock may be too coarse to tell deletions apart.
+        let mut n = 0;
+        while let Err(err) = fs::create_dir(&dest) {
+            if err.kind() != io::ErrorKind::AlreadyExists {
+                return Err(err);
+            }
+            n += 1;
+            dest = self.path.join(format!("{}-{}", timestamp, n));
         }
+        dest.push(name);
+        fs::rename(path, &dest)?;
+        info!("moved {} to {}", path.display(), dest.display());
+        Ok(())
+    }
+
+    /// Permanently removes mount points that have been in the trash for longer than `retention`.
+    fn empty(&self, retention: Duration) -> io::Result<()> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(io::Error::other)?;
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            let trashed_at = entry
+                .file_name()
+                .to_str()
+                .and_then(|timestamp| timestamp.split(['.', '-']).next()?.parse().ok())
+                .map(Duration::from_secs);
+            // Leave entries that weren't put there by the driver alone.
+            if let Some(trashed_at) = trashed_at {
+                if now.saturating_sub(trashed_at) > retention {
+                    fs::remove_dir_all(entry.path())?;
+                    info!("permanently removed {}", entry.path().display());
+                }
+            }
+        }
+        Ok(())
     }
 }
 
 /// A hash of a file system entry.
-#[derive(Eq, PartialEq)]
-struct Hash(u64);
+///
+/// The hash is the SHA-256 digest of the entry's contents, which is stable across runs of the
+/// driver and can be recomputed by the ship.
+#[derive(Clone, Eq, Hash, PartialEq)]
+struct Hash([u8; 32]);
 
 impl From<&[u8]> for Hash {
     fn from(bytes: &[u8]) -> Self {
-        let mut hasher = DefaultHasher::new();
-        hasher.write(&bytes);
-        Self(hasher.finish())
+        let mut hash = [0; 32];
+        hash.copy_from_slice(&Sha256::digest(bytes));
+        Self(hash)
+    }
+}
+
+impl From<Hash> for Noun {
+    /// The resulting noun is a 32-byte atom whose least significant byte is the first byte of the
+    /// digest.
+    fn from(hash: Hash) -> Self {
+        Self::from(Atom::from(hash.0.to_vec()))
+    }
+}
+
+/// A point in time, such as the modification time of a file system entry.
+struct Date(SystemTime);
+
+impl Date {
+    /// The number of seconds between the Urbit epoch and the Unix epoch.
+    const UNIX_EPOCH_SECS: u64 = 0x8000000cce9e0d80;
+}
+
+impl From<Date> for Noun {
+    /// The resulting noun is an `@da`: the upper 64 bits are the number of seconds since the
+    /// Urbit epoch, and the lower 64 bits are the fraction of a second. Times before the Unix
+    /// epoch are clamped to the Unix epoch.
+    fn from(date: Date) -> Self {
+        let since_unix = date.0.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let secs = u128::from(since_unix.as_secs() + Date::UNIX_EPOCH_SECS);
+        let frac = (u128::from(since_unix.subsec_nanos()) << 64) / 1_000_000_000;
+        Self::from(Atom::from((secs << 64) | frac))
     }
 }
 
@@ -775,6 +2795,14 @@ enum Change {
     },
 }
 
+impl Change {
+    /// The maximum length in bytes of the new contents of a file.
+    ///
+    /// The length is supplied by the ship separately from the contents, which are padded to it, so
+    /// it's checked before anything is allocated.
+    const MAX_LEN: usize = 1 << 30;
+}
+
 impl TryFrom<&Noun> for Change {
     type Error = convert::Error;
 
@@ -805,9 +2833,15 @@ impl TryFrom<&Noun> for Change {
     /// ]
     /// ```
     ///
-    /// Note that `14` is the length of the chnage to `example.hoon` plus one (for the record
-    /// separator i.e. ASCII `30`) and `0xa2961282b2020403d6120203d7c` is `|=  a=@  +(a)<RS>`
-    /// represented as an atom (where `<RS>` is the record separator).
+    /// Note that `14` is the length of the change to `example.hoon` plus one for the trailing
+    /// newline (ASCII `10`) that `+of-wain` appends to text files and that
+    /// `0xa2961282b2020403d6120203d7c` is `|=  a=@  +(a)\n` represented as an atom. Like vere, the
+    /// driver neither appends nor strips a trailing byte: `<bytes>` is written as is, and a file's
+    /// contents are sent as is when it's committed.
+    ///
+    /// `<byte_count>` is authoritative: because an atom can't represent trailing null bytes,
+    /// `<bytes>` is padded with null bytes up to `<byte_count>` bytes. A `<byte_count>` that's
+    /// less than the length of `<bytes>` or greater than [`Change::MAX_LEN`] is an error.
     ///
     /// Removing `<pier>/base/gen/example.hoon` yields:
     ///
@@ -834,11 +2868,19 @@ impl TryFrom<&Noun> for Change {
                     if null.is_null() {
                         if let Noun::Atom(byte_len) = &*byte_len {
                             if let Noun::Atom(bytes) = &*bytes {
-                                let bytes = bytes.to_vec();
-                                debug_assert_eq!(
-                                    byte_len.as_usize().expect("Atom to usize"),
-                                    bytes.len()
-                                );
+                                let byte_len = byte_len
+                                    .as_u64()
+                                    .and_then(|byte_len| usize::try_from(byte_len).ok())
+                                    .ok_or(convert::Error::AtomToUint)?;
+                                if byte_len > Self::MAX_LEN {
+                                    return Err(convert::Error::ImplType);
+                                }
+                                let mut bytes = bytes.to_vec();
+                                if bytes.len() > byte_len {
+                                    return Err(convert::Error::ImplType);
+                                }
+                                // Ensure trailing null bytes are retained.
+                                bytes.resize(byte_len, 0);
                                 Ok(Self::EditFile { path, bytes })
                             } else {
                                 Err(convert::Error::UnexpectedCell)
@@ -865,6 +2907,35 @@ impl TryFrom<&Noun> for Change {
 mod tests {
     use super::*;
 
+    /// Creates an empty directory for a test to build mount points in, replacing any that a
+    /// previous run left behind.
+    fn test_root(test: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("{}.fs.{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).expect("create test directory");
+        root
+    }
+
+    /// Creates an unscanned mount point at `path` that isn't limited, doesn't exclude any files,
+    /// and doesn't follow symlinks.
+    fn mount_point_at(path: impl Into<PathBuf>) -> MountPoint {
+        MountPoint {
+            path: path.into(),
+            entries: HashMap::new(),
+            prefixes: Vec::new(),
+            excluded: HashSet::new(),
+            follow_symlinks: false,
+        }
+    }
+
+    /// Creates a driver with a single mount point.
+    fn driver_with(name: &PathComponent, mount_point: MountPoint) -> FileSystem {
+        FileSystem {
+            mount_points: HashMap::from([(name.clone(), mount_point)]),
+            config: Config::from_env(),
+        }
+    }
+
     macro_rules! test_noun_to_mount_point {
         ($type:ty) => {
             macro_rules! test {
@@ -888,7 +2959,9 @@ mod tests {
             {
                 test!(Noun: Atom::from("mount-point-name"), PathComponent: "mount-point-name");
                 test!(Noun: Atom::from(""), PathComponent: "!");
+                #[cfg(not(windows))]
                 test!(Noun: Atom::from("."), PathComponent: "!.");
+                #[cfg(not(windows))]
                 test!(Noun: Atom::from(".."), PathComponent: "!..");
                 test!(Noun: Atom::from("!base"), PathComponent: "!!base");
             }
@@ -928,30 +3001,98 @@ mod tests {
             {
                 let noun = Noun::from(Cell::from([
                     Noun::from(Cell::from([
-                        Atom::from("gen"),
-                        Atom::from("example"),
-                        Atom::from("hoon"),
+                        Atom::from("gen"),
+                        Atom::from("example"),
+                        Atom::from("hoon"),
+                        Atom::null(),
+                    ])),
+                    Noun::null(),
+                    Noun::from(Cell::from([
+                        Atom::from("text"),
+                        Atom::from("x-hoon"),
+                        Atom::null(),
+                    ])),
+                    Noun::from(Atom::from(14u8)),
+                    Noun::from(Atom::from(0xa2961282b2020403d6120203d7cu128)),
+                ]));
+                let change = Change::try_from(&noun).expect("Noun to Change");
+                assert_eq!(
+                    change,
+                    Change::EditFile {
+                        path: PathBuf::from("gen/example.hoon"),
+                        bytes: b"|=  a=@  +(a)\n".to_vec(),
+                    }
+                );
+            }
+
+            // Trailing null bytes are retained.
+            {
+                let noun = Noun::from(Cell::from([
+                    Noun::from(Cell::from([
+                        Atom::from("blob"),
+                        Atom::from("bin"),
                         Atom::null(),
                     ])),
                     Noun::null(),
                     Noun::from(Cell::from([
-                        Atom::from("text"),
-                        Atom::from("x-hoon"),
+                        Atom::from("application"),
+                        Atom::from("octet-stream"),
                         Atom::null(),
                     ])),
-                    Noun::from(Atom::from(14u8)),
-                    Noun::from(Atom::from(0xa2961282b2020403d6120203d7cu128)),
+                    Noun::from(Atom::from(4u8)),
+                    Noun::from(Atom::from(0x102u16)),
                 ]));
                 let change = Change::try_from(&noun).expect("Noun to Change");
                 assert_eq!(
                     change,
                     Change::EditFile {
-                        path: PathBuf::from("gen/example.hoon"),
-                        bytes: Atom::from(0xa2961282b2020403d6120203d7cu128).into_vec(),
+                        path: PathBuf::from("blob.bin"),
+                        bytes: vec![2, 1, 0, 0],
                     }
                 );
             }
         }
+
+        // Noun -> Change: expect failure.
+        {
+            // The byte count is less than the number of bytes.
+            let noun = Noun::from(Cell::from([
+                Noun::from(Cell::from([
+                    Atom::from("blob"),
+                    Atom::from("bin"),
+                    Atom::null(),
+                ])),
+                Noun::null(),
+                Noun::from(Cell::from([
+                    Atom::from("application"),
+                    Atom::from("octet-stream"),
+                    Atom::null(),
+                ])),
+                Noun::from(Atom::from(1u8)),
+                Noun::from(Atom::from(0x102u16)),
+            ]));
+            assert!(Change::try_from(&noun).is_err());
+        }
+
+        {
+            // The byte count would pad the bytes beyond the maximum length.
+            let noun = Noun::from(Cell::from([
+                Noun::from(Cell::from([
+                    Atom::from("blob"),
+                    Atom::from("bin"),
+                    Atom::null(),
+                ])),
+                Noun::null(),
+                Noun::from(Cell::from([
+                    Atom::from("application"),
+                    Atom::from("octet-stream"),
+                    Atom::null(),
+                ])),
+                Noun::from(Atom::from(Change::MAX_LEN as u64 + 1)),
+                Noun::from(Atom::from(0x102u16)),
+            ]));
+            assert!(Change::try_from(&noun).is_err());
+        }
     }
 
     #[test]
@@ -964,6 +3105,110 @@ mod tests {
         test_noun_to_mount_point!(DeleteMountPoint);
     }
 
+    #[test]
+    fn convert_verify_mount_point_request() {
+        test_noun_to_mount_point!(VerifyMountPoint);
+    }
+
+    #[test]
+    fn convert_disk_usage_request() {
+        test_noun_to_mount_point!(DiskUsage);
+    }
+
+    #[test]
+    fn convert_dry_run_commit_mount_point_request() {
+        test_noun_to_mount_point!(DryRunCommitMountPoint);
+    }
+
+    #[test]
+    fn convert_date() {
+        macro_rules! test {
+            (Secs: $secs:expr, Nanos: $nanos:expr, Atom: $atom:expr) => {
+                let date = Date(UNIX_EPOCH + Duration::new($secs, $nanos));
+                assert_eq!(Noun::from(date), Noun::from(Atom::from($atom)));
+            };
+        }
+
+        // ~1970.1.1
+        test!(Secs: 0, Nanos: 0, Atom: 0x8000000cce9e0d80_0000000000000000u128);
+        // ~1970.1.1..00.00.00..8000
+        test!(Secs: 0, Nanos: 500_000_000, Atom: 0x8000000cce9e0d80_8000000000000000u128);
+        // ~2023.1.1
+        test!(Secs: 1_672_531_200, Nanos: 0, Atom: 0x8000000d324eda80_0000000000000000u128);
+    }
+
+    #[test]
+    fn convert_exclusions() {
+        // &str -> Exclusions: expect success.
+        {
+            let exclusions = Exclusions::from_str("base=bin,mp4; kids=.exe;;").expect("parse");
+            assert_eq!(exclusions.0.len(), 2);
+            assert_eq!(
+                exclusions.0["base"],
+                HashSet::from([String::from("bin"), String::from("mp4")])
+            );
+            assert_eq!(exclusions.0["kids"], HashSet::from([String::from("exe")]));
+            assert_eq!(
+                Exclusions::from_str("").expect("parse"),
+                Exclusions::default()
+            );
+        }
+
+        // &str -> Exclusions: expect failure.
+        {
+            assert!(Exclusions::from_str("base").is_err());
+            assert!(Exclusions::from_str("=bin").is_err());
+        }
+
+        let excluded = HashSet::from([String::from("bin")]);
+        assert!(is_excluded(Path::new("gen/blob.bin"), &excluded));
+        assert!(!is_excluded(Path::new("gen/blob.hoon"), &excluded));
+        assert!(!is_excluded(Path::new("gen/bin"), &excluded));
+    }
+
+    #[test]
+    fn convert_export_mount_point_request() {
+        // Noun -> ExportMountPoint: expect success.
+        {
+            let noun = Noun::from(Cell::from([
+                Atom::from("base"),
+                Atom::from("/tmp/base.tar"),
+            ]));
+            let req = ExportMountPoint::try_from(&noun).expect("Noun to ExportMountPoint");
+            assert_eq!(req.mount_point, PathComponent(String::from("base")));
+            assert_eq!(req.archive, PathBuf::from("/tmp/base.tar"));
+        }
+
+        // Noun -> ExportMountPoint: expect failure.
+        {
+            let noun = Noun::from(Atom::from("base"));
+            assert!(ExportMountPoint::try_from(&noun).is_err());
+
+            let noun = Noun::from(Cell::from([
+                Noun::from(Atom::from("base")),
+                Noun::from(Cell::from([Atom::from("base.tar"), Atom::null()])),
+            ]));
+            assert!(ExportMountPoint::try_from(&noun).is_err());
+        }
+    }
+
+    #[test]
+    fn convert_import_mount_point_request() {
+        // Noun -> ImportMountPoint: expect success.
+        {
+            let noun = Noun::from(Cell::from([Atom::from("!base"), Atom::from("base.tar")]));
+            let req = ImportMountPoint::try_from(&noun).expect("Noun to ImportMountPoint");
+            assert_eq!(req.mount_point, PathComponent(String::from("!!base")));
+            assert_eq!(req.archive, PathBuf::from("base.tar"));
+        }
+
+        // Noun -> ImportMountPoint: expect failure.
+        {
+            let noun = Noun::from(Atom::from("base.tar"));
+            assert!(ImportMountPoint::try_from(&noun).is_err());
+        }
+    }
+
     #[test]
     fn convert_knot() {
         macro_rules! test {
@@ -992,7 +3237,9 @@ mod tests {
             test!(Knot: "hello", PathComponent: "hello");
             test!(Knot: "goodbye!", PathComponent: "goodbye!");
             test!(Knot: "", PathComponent: "!");
+            #[cfg(not(windows))]
             test!(Knot: ".", PathComponent: "!.");
+            #[cfg(not(windows))]
             test!(Knot: "..", PathComponent: "!..");
             test!(Knot: "!", PathComponent: "!!");
             test!(Knot: "!water-bottle", PathComponent: "!!water-bottle");
@@ -1017,6 +3264,136 @@ mod tests {
         }
     }
 
+    #[test]
+    fn convert_unicode_knot() {
+        macro_rules! test {
+            // Knot -> PathComponent -> Knot: expect success.
+            (Knot: $knot:literal, PathComponent: $path_component:literal) => {
+                let atom = Atom::from($knot);
+                let path_component =
+                    PathComponent::try_from(Knot(&atom)).expect("Knot to PathComponent");
+                assert_eq!(path_component.0, $path_component);
+                assert_eq!(Knot::from(path_component).0, $knot);
+            };
+            // Knot -> PathComponent: expect success.
+            (Knot: $knot:literal, PathComponent: $path_component:literal, lossy) => {
+                let atom = Atom::from($knot);
+                let path_component =
+                    PathComponent::try_from(Knot(&atom)).expect("Knot to PathComponent");
+                assert_eq!(path_component.0, $path_component);
+            };
+        }
+
+        {
+            // Knot -> PathComponent -> Knot: expect success.
+            test!(Knot: "~f6.", PathComponent: "ö");
+            test!(Knot: "sch~f6.n", PathComponent: "schön");
+            test!(Knot: "~4e2d.~6587.", PathComponent: "中文");
+            test!(Knot: "~1f980.", PathComponent: "🦀");
+            test!(Knot: "~~", PathComponent: "~");
+            test!(Knot: "~~~~", PathComponent: "~~");
+            test!(Knot: "~~zod", PathComponent: "~zod");
+            test!(Knot: "~~.", PathComponent: "~.");
+            test!(Knot: "~~41.", PathComponent: "~41.");
+            test!(Knot: "~~f6.", PathComponent: "~f6.");
+            test!(Knot: "~~7e.", PathComponent: "~7e.");
+            test!(Knot: "!~f6.", PathComponent: "!!ö");
+        }
+
+        {
+            // Knot -> PathComponent: expect success, but the knot isn't in canonical form.
+            test!(Knot: "a~.b", PathComponent: "a.b", lossy);
+            test!(Knot: "~41.", PathComponent: "A", lossy);
+            test!(Knot: "~7e.", PathComponent: "~", lossy);
+            test!(Knot: "~zod", PathComponent: "~zod", lossy);
+            test!(Knot: "~", PathComponent: "~", lossy);
+            test!(Knot: "~F6.", PathComponent: "~F6.", lossy);
+            test!(Knot: "~0f6.", PathComponent: "~0f6.", lossy);
+            test!(Knot: "~d800.", PathComponent: "~d800.", lossy);
+            test!(Knot: "~110000.", PathComponent: "~110000.", lossy);
+        }
+
+        // Every file name made up of a character and the characters that can interfere with
+        // escapes survives the round trip through a knot.
+        {
+            let chars = (0u32..0x800)
+                .chain(0xfff0..0x10100)
+                .chain(0x10fff0..=0x10ffff)
+                .filter_map(char::from_u32);
+            for c in chars {
+                for file_name in [
+                    format!("{}", c),
+                    format!("~{}", c),
+                    format!("~~{}", c),
+                    format!("{}~{:x}.", c, u32::from(c)),
+                    format!("~{:x}.{}", u32::from(c), c),
+                ] {
+                    let knot = escape_knot(&file_name);
+                    assert!(knot.is_ascii());
+                    assert_eq!(unescape_knot(&knot), file_name);
+                }
+            }
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn convert_windows_knot() {
+        macro_rules! test {
+            // Knot -> PathComponent -> Knot: expect success.
+            (Knot: $knot:literal, PathComponent: $path_component:literal) => {
+                let atom = Atom::from($knot);
+                let path_component =
+                    PathComponent::try_from(Knot(&atom)).expect("Knot to PathComponent");
+                assert_eq!(path_component.0, $path_component);
+                assert_eq!(Knot::from(path_component).0, $knot);
+            };
+        }
+
+        // Reserved device names.
+        {
+            test!(Knot: "con", PathComponent: "!con");
+            test!(Knot: "CON", PathComponent: "!CON");
+            test!(Knot: "aux", PathComponent: "!aux");
+            test!(Knot: "nul.d", PathComponent: "!nul.d");
+            test!(Knot: "prn", PathComponent: "!prn");
+            test!(Knot: "com1", PathComponent: "!com1");
+            test!(Knot: "Lpt9", PathComponent: "!Lpt9");
+            test!(Knot: "console", PathComponent: "console");
+            test!(Knot: "com", PathComponent: "com");
+            test!(Knot: "com10", PathComponent: "com10");
+            test!(Knot: "!con", PathComponent: "!!con");
+        }
+
+        // Forbidden characters.
+        {
+            test!(Knot: "a:b", PathComponent: "a%3Ab");
+            test!(Knot: "<>", PathComponent: "%3C%3E");
+            test!(Knot: "why?", PathComponent: "why%3F");
+            test!(Knot: "a*b|c", PathComponent: "a%2Ab%7Cc");
+            test!(Knot: "a/b", PathComponent: "a%2Fb");
+            test!(Knot: "50%", PathComponent: "50%25");
+            test!(Knot: "%3A", PathComponent: "%253A");
+            test!(Knot: "trailing.", PathComponent: "trailing%2E");
+            test!(Knot: "not.trailing", PathComponent: "not.trailing");
+            test!(Knot: ".", PathComponent: "!%2E");
+            test!(Knot: "..", PathComponent: "!.%2E");
+        }
+
+        // Escaped file names are turned into paths that stay within the parent directory.
+        {
+            let noun = Noun::from(Cell::from([
+                Atom::from("lib"),
+                Atom::from("con"),
+                Atom::from("hoon"),
+                Atom::null(),
+            ]));
+            let knots = KnotList::try_from(&noun).expect("Noun to KnotList");
+            let path = PathBuf::try_from(knots).expect("KnotList to PathBuf");
+            assert_eq!(path, Path::new("lib/!con.hoon"));
+        }
+    }
+
     #[test]
     fn convert_knot_list() {
         macro_rules! test {
@@ -1079,11 +3456,13 @@ mod tests {
                 test!(Noun: noun, PathBuf: "!");
             }
 
+            #[cfg(not(windows))]
             {
                 let noun = Noun::from(Cell::from([Atom::from("."), Atom::null()]));
                 test!(Noun: noun, PathBuf: "!.");
             }
 
+            #[cfg(not(windows))]
             {
                 let noun = Noun::from(Cell::from([Atom::from(".."), Atom::null()]));
                 test!(Noun: noun, PathBuf: "!..");
@@ -1099,6 +3478,7 @@ mod tests {
                 test!(Noun: noun, PathBuf: "!!escaped");
             }
 
+            #[cfg(not(windows))]
             {
                 let noun = Noun::from(Cell::from([
                     Atom::from(".."),
@@ -1134,6 +3514,509 @@ mod tests {
         }
     }
 
+    #[test]
+    fn scan_deep_tree() {
+        const DEPTH: usize = MountPoint::MAX_DEPTH + 100;
+
+        let root = test_root("scan_deep_tree");
+        let mut dir = root.clone();
+        for _ in 0..DEPTH {
+            dir.push("d");
+            fs::create_dir_all(&dir).expect("create directory");
+            fs::write(dir.join("f.txt"), "f").expect("create file");
+        }
+
+        let mount_point = mount_point_at(&root);
+        let (mount_point, old_entries) = mount_point.scan(None).map_err(|(_, err)| err).unwrap();
+        assert!(old_entries.is_empty());
+        assert_eq!(mount_point.entries.len(), MountPoint::MAX_DEPTH);
+        for (path, entry) in &mount_point.entries {
+            let depth = path.strip_prefix(&root).unwrap().components().count() - 1;
+            assert!(depth <= MountPoint::MAX_DEPTH);
+            assert_eq!(entry.len, 1);
+        }
+
+        fs::remove_dir_all(&root).expect("remove mount point");
+    }
+
+    #[test]
+    fn export_and_import_mount_point() {
+        let root = test_root("export_and_import");
+        let src = root.join("src");
+        fs::create_dir_all(src.join("gen")).expect("create mount point");
+        fs::write(src.join("gen/foo.hoon"), "foo").expect("create file");
+        fs::write(src.join("sys.kelvin"), "[%zuse 413]").expect("create file");
+
+        let mount_point = mount_point_at(src);
+        let (mount_point, _old_entries) = mount_point.scan(None).map_err(|(_, err)| err).unwrap();
+
+        // Exporting the same files twice yields identical archives.
+        let archive = root.join("src.tar");
+        assert_eq!(mount_point.export(&archive).expect("export"), 2);
+        let bytes = fs::read(&archive).expect("read archive");
+        assert_eq!(mount_point.export(&archive).expect("export"), 2);
+        assert_eq!(fs::read(&archive).expect("read archive"), bytes);
+
+        let dst = root.join("dst");
+        let mount_point = MountPoint {
+            prefixes: vec![PathBuf::from("gen")],
+            ..mount_point_at(&dst)
+        };
+        assert_eq!(mount_point.import(&archive).expect("import"), 1);
+        assert_eq!(fs::read(dst.join("gen/foo.hoon")).unwrap(), b"foo");
+        assert!(!dst.join("sys.kelvin").exists());
+
+        fs::remove_dir_all(&root).expect("remove mount points");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scan_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let root = test_root("scan_symlinks");
+        let mount_point_path = root.join("base");
+        fs::create_dir_all(mount_point_path.join("gen")).expect("create mount point");
+        fs::create_dir_all(root.join("lib")).expect("create directory");
+        fs::write(mount_point_path.join("gen/foo.hoon"), "foo").expect("create file");
+        fs::write(root.join("lib/bar.hoon"), "bar").expect("create file");
+        symlink(root.join("lib"), mount_point_path.join("lib")).expect("create symlink");
+        symlink(
+            mount_point_path.join("gen/foo.hoon"),
+            mount_point_path.join("gen/baz.hoon"),
+        )
+        .expect("create symlink");
+        // A symlink back to the root of the mount point creates a cycle.
+        symlink(&mount_point_path, mount_point_path.join("gen/cycle")).expect("create symlink");
+        symlink(root.join("missing"), mount_point_path.join("broken")).expect("create symlink");
+
+        for follow_symlinks in [false, true] {
+            let mount_point = MountPoint {
+                follow_symlinks,
+                ..mount_point_at(&mount_point_path)
+            };
+            let (mount_point, _old_entries) =
+                mount_point.scan(None).map_err(|(_, err)| err).unwrap();
+            let mut paths: Vec<&Path> = mount_point
+                .entries
+                .keys()
+                .map(|path| path.strip_prefix(&mount_point_path).unwrap())
+                .collect();
+            paths.sort();
+            if follow_symlinks {
+                assert_eq!(
+                    paths,
+                    [
+                        Path::new("gen/baz.hoon"),
+                        Path::new("gen/foo.hoon"),
+                        Path::new("lib/bar.hoon")
+                    ]
+                );
+            } else {
+                assert_eq!(paths, [Path::new("gen/foo.hoon")]);
+            }
+        }
+
+        fs::remove_dir_all(&root).expect("remove mount point");
+    }
+
+    #[test]
+    fn report_progress() {
+        let (output_tx, mut output_rx) = mpsc::channel(1);
+        let mut progress = Progress::new(PathComponent(String::from("base")), &output_tx);
+
+        // Too soon after the progress began.
+        progress.scanned_file();
+        assert!(output_rx.try_recv().is_err());
+
+        progress.last_report -= Progress::INTERVAL;
+        progress.hashed_bytes(42);
+        assert_eq!(
+            output_rx.try_recv().expect("progress report"),
+            Noun::from(Cell::from([
+                Noun::from(Atom::from("progress")),
+                Noun::from(Atom::from("base")),
+                Noun::from(Atom::from(1u64)),
+                Noun::from(Atom::from(42u64)),
+            ]))
+        );
+
+        // Too soon after the last report.
+        progress.scanned_file();
+        assert!(output_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn write_file() {
+        let root = test_root("write_file");
+        fs::create_dir_all(root.join("gen")).expect("create mount point");
+        let mount_point = mount_point_at(&root);
+
+        let path = root.join("gen/foo.hoon");
+        let files = || {
+            fs::read_dir(root.join("gen"))
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect::<Vec<_>>()
+        };
+        mount_point.write(&path, b"foo").expect("write new file");
+        assert_eq!(fs::read(&path).unwrap(), b"foo");
+        mount_point.write(&path, b"bar").expect("overwrite file");
+        assert_eq!(fs::read(&path).unwrap(), b"bar");
+        assert_eq!(files(), ["foo.hoon"]);
+
+        // A rename across file systems fails with EXDEV, so the contents are copied instead.
+        const EXDEV: i32 = 18;
+        mount_point
+            .write_with(&path, b"baz", |_from, _to| {
+                Err(io::Error::from_raw_os_error(EXDEV))
+            })
+            .expect("copy file");
+        assert_eq!(fs::read(&path).unwrap(), b"baz");
+        assert_eq!(files(), ["foo.hoon"]);
+
+        // The temporary file is removed even if the copy fails too.
+        fs::create_dir(root.join("gen/bar.hoon")).expect("create directory");
+        mount_point
+            .write_with(&root.join("gen/bar.hoon"), b"bar", |_from, _to| {
+                Err(io::Error::from_raw_os_error(EXDEV))
+            })
+            .expect_err("copy over directory");
+        fs::remove_dir(root.join("gen/bar.hoon")).expect("remove directory");
+        assert_eq!(files(), ["foo.hoon"]);
+
+        // A leftover temporary file is never scanned.
+        fs::write(root.join("gen/.baz.hoon.urbit-tmp"), "baz").expect("create file");
+        assert!(!mount_point.includes(Path::new("gen/.baz.hoon.urbit-tmp")));
+        let (mount_point, _old_entries) = mount_point.scan(None).map_err(|(_, err)| err).unwrap();
+        assert_eq!(mount_point.entries.keys().collect::<Vec<_>>(), [&path]);
+
+        // A followed symlink is kept, and its target is written to in place.
+        #[cfg(unix)]
+        {
+            fs::create_dir(root.join("lib")).expect("create directory");
+            fs::write(root.join("lib/bar.hoon"), "bar").expect("create file");
+            let link = root.join("gen/bar.hoon");
+            std::os::unix::fs::symlink(root.join("lib/bar.hoon"), &link).expect("create symlink");
+            let mount_point = MountPoint {
+                follow_symlinks: true,
+                ..mount_point
+            };
+            mount_point
+                .write(&link, b"qux")
+                .expect("write through symlink");
+            assert!(fs::symlink_metadata(&link)
+                .unwrap()
+                .file_type()
+                .is_symlink());
+            assert_eq!(fs::read(root.join("lib/bar.hoon")).unwrap(), b"qux");
+            assert_eq!(fs::read_dir(root.join("lib")).unwrap().count(), 1);
+        }
+
+        fs::remove_dir_all(&root).expect("remove mount point");
+    }
+
+    #[test]
+    fn write_file_in_missing_directory() {
+        let root = test_root("write_file_in_missing_directory");
+        let mount_point = mount_point_at(&root);
+
+        let path = root.join("lib/nested/deeply/util.hoon");
+        mount_point.write(&path, b"util").expect("write file");
+        assert_eq!(fs::read(&path).unwrap(), b"util");
+
+        // A file in the way of a parent directory can't be replaced.
+        fs::write(root.join("app"), "app").expect("create file");
+        assert!(mount_point
+            .write(&root.join("app/foo.hoon"), b"foo")
+            .is_err());
+        assert_eq!(fs::read(root.join("app")).unwrap(), b"app");
+
+        fs::remove_dir_all(&root).expect("remove mount point");
+    }
+
+    #[test]
+    fn trash_mount_points() {
+        let root = test_root("trash_mount_points");
+        let trash = Trash {
+            path: root.join(Trash::DIR),
+        };
+        let name = PathComponent(String::from("base"));
+
+        // Mount points with the same name that are deleted in quick succession don't collide.
+        for contents in ["foo", "bar"] {
+            let path = root.join("base");
+            fs::create_dir_all(&path).expect("create mount point");
+            fs::write(path.join("foo.hoon"), contents).expect("create file");
+            trash.put(&path, &name).expect("put mount point in trash");
+            assert!(!path.exists());
+        }
+        let mut trashed: Vec<_> = fs::read_dir(&trash.path)
+            .unwrap()
+            .map(|entry| fs::read(entry.unwrap().path().join("base/foo.hoon")).unwrap())
+            .collect();
+        trashed.sort();
+        assert_eq!(trashed, [b"bar", b"foo"]);
+
+        trash.empty(Duration::from_secs(3600)).expect("empty trash");
+        assert_eq!(fs::read_dir(&trash.path).unwrap().count(), 2);
+        trash.empty(Duration::ZERO).expect("empty trash");
+        assert_eq!(fs::read_dir(&trash.path).unwrap().count(), 0);
+
+        fs::remove_dir_all(&root).expect("remove pier");
+    }
+
+    #[test]
+    fn commit_identical_files() {
+        let root = test_root("commit_identical");
+        fs::create_dir_all(root.join("mar")).expect("create mount point");
+        fs::write(root.join("mar/a.hoon"), "same").expect("create file");
+        fs::write(root.join("mar/b.hoon"), "same").expect("create file");
+        fs::write(root.join("mar/c.hoon"), "different").expect("create file");
+
+        let name = PathComponent(String::from("base"));
+        let mut driver = driver_with(&name, mount_point_at(&root));
+        let (output_tx, _output_rx) = mpsc::channel(1);
+        let changes = driver.commit(name, false, &output_tx).expect("commit");
+
+        // Map each file name to the address of its contents.
+        let mut contents = HashMap::new();
+        let mut changes = &changes;
+        while let Noun::Cell(cell) = changes {
+            let change = match cell.head_ref() {
+                Noun::Cell(change) => change,
+                Noun::Atom(_) => panic!("expected a cell"),
+            };
+            let path = PathBuf::try_from(KnotList::try_from(change.head_ref()).unwrap()).unwrap();
+            let content = match change.tail_ref() {
+                Noun::Cell(tail) => tail.tail_ref() as *const Noun,
+                Noun::Atom(_) => panic!("expected a cell"),
+            };
+            contents.insert(path, content);
+            changes = cell.tail_ref();
+        }
+        assert_eq!(contents.len(), 3);
+        assert_eq!(
+            contents[Path::new("mar/a.hoon")],
+            contents[Path::new("mar/b.hoon")]
+        );
+        assert_ne!(
+            contents[Path::new("mar/a.hoon")],
+            contents[Path::new("mar/c.hoon")]
+        );
+
+        fs::remove_dir_all(&root).expect("remove mount point");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn commit_hard_links() {
+        let root = test_root("commit_hard_links");
+        fs::create_dir_all(root.join("gen")).expect("create mount point");
+        fs::write(root.join("gen/foo.hoon"), "foo").expect("create file");
+        fs::hard_link(root.join("gen/foo.hoon"), root.join("gen/bar.hoon")).expect("create link");
+
+        let name = PathComponent(String::from("base"));
+        let mut driver = driver_with(&name, mount_point_at(&root));
+        let (output_tx, _output_rx) = mpsc::channel(1);
+        let changes = driver
+            .commit(name.clone(), false, &output_tx)
+            .expect("commit");
+        let changes = convert!(&changes => Vec<Change>).expect("Noun to Vec<Change>");
+        assert_eq!(changes.len(), 2);
+        for change in changes {
+            match change {
+                Change::EditFile { bytes, .. } => assert_eq!(bytes, b"foo"),
+                Change::RemoveFile { path } => panic!("unexpected removal of {}", path.display()),
+            }
+        }
+
+        // Both links share an identity and a hash.
+        let mount_point = &driver.mount_points[&name];
+        let foo = &mount_point.entries[&root.join("gen/foo.hoon")];
+        let bar = &mount_point.entries[&root.join("gen/bar.hoon")];
+        assert!(foo.id.is_some());
+        assert_eq!(foo.id, bar.id);
+        assert!(foo.hash.is_some() && foo.hash == bar.hash);
+
+        fs::remove_dir_all(&root).expect("remove mount point");
+    }
+
+    #[test]
+    fn commit_renamed_files() {
+        let root = test_root("commit_renamed_files");
+        fs::create_dir_all(root.join("gen")).expect("create mount point");
+        fs::write(root.join("gen/foo.hoon"), "foo").expect("create file");
+        fs::write(root.join("gen/bar.hoon"), "bar").expect("create file");
+        fs::write(root.join("gen/baz.hoon"), "baz").expect("create file");
+
+        let name = PathComponent(String::from("base"));
+        let mut driver = driver_with(&name, mount_point_at(&root));
+        let (output_tx, _output_rx) = mpsc::channel(1);
+        driver
+            .commit(name.clone(), false, &output_tx)
+            .expect("commit");
+
+        // A moved file keeps its identity, whereas a copied file only keeps its contents.
+        fs::create_dir(root.join("app")).expect("create directory");
+        fs::rename(root.join("gen/foo.hoon"), root.join("app/foo.hoon")).expect("move file");
+        fs::copy(root.join("gen/bar.hoon"), root.join("gen/qux.hoon")).expect("copy file");
+        fs::remove_file(root.join("gen/bar.hoon")).expect("remove file");
+        fs::remove_file(root.join("gen/baz.hoon")).expect("remove file");
+        let changes = driver
+            .commit(name.clone(), false, &output_tx)
+            .expect("commit");
+
+        let path = |knots: &[&str]| {
+            let mut noun = Noun::null();
+            for knot in knots.iter().rev() {
+                noun = Noun::from(Cell::from([Noun::from(Atom::from(*knot)), noun]));
+            }
+            noun
+        };
+        let remove = |path: Noun| Noun::from(Cell::from([path, Noun::null()]));
+        let edit = |path: Noun, bytes: &str| {
+            Noun::from(Cell::from([
+                path,
+                Noun::null(),
+                Noun::from(Cell::from([
+                    Atom::from("text"),
+                    Atom::from("plain"),
+                    Atom::null(),
+                ])),
+                Noun::from(Atom::from(bytes.len())),
+                Noun::from(Atom::from(bytes)),
+            ]))
+        };
+        // The contents of renamed files are sent in full.
+        let mut expected = vec![
+            remove(path(&["gen", "foo", "hoon"])),
+            edit(path(&["app", "foo", "hoon"]), "foo"),
+            remove(path(&["gen", "bar", "hoon"])),
+            edit(path(&["gen", "qux", "hoon"]), "bar"),
+            remove(path(&["gen", "baz", "hoon"])),
+        ];
+        let mut changes = &changes;
+        while let Noun::Cell(cell) = changes {
+            let change = cell.head_ref();
+            let i = expected
+                .iter()
+                .position(|noun| noun == change)
+                .unwrap_or_else(|| panic!("unexpected change {:?}", change));
+            expected.remove(i);
+            changes = cell.tail_ref();
+        }
+        assert!(expected.is_empty());
+
+        // The renamed files are committed under their new paths.
+        let changes = driver.commit(name, false, &output_tx).expect("commit");
+        assert!(changes.is_null());
+
+        fs::remove_dir_all(&root).expect("remove mount point");
+    }
+
+    #[test]
+    fn rebase_mount_point() {
+        let mut mount_point = MountPoint {
+            entries: HashMap::from([(
+                PathBuf::from("/old/base/gen/foo.hoon"),
+                Entry {
+                    hash: Some(Hash::from(&b"foo"[..])),
+                    len: 3,
+                    mtime: None,
+                    id: None,
+                },
+            )]),
+            ..mount_point_at("/old/base")
+        };
+        mount_point.rebase(PathBuf::from("/new/base"));
+        assert_eq!(mount_point.path, PathBuf::from("/new/base"));
+        assert_eq!(mount_point.entries.len(), 1);
+        let entry = &mount_point.entries[Path::new("/new/base/gen/foo.hoon")];
+        assert!(entry.hash == Some(Hash::from(&b"foo"[..])));
+        assert_eq!(entry.len, 3);
+    }
+
+    #[test]
+    fn resolve_path() {
+        let root = test_root("resolve_path");
+        fs::create_dir_all(root.join("gen")).expect("create mount point");
+        let mount_point = mount_point_at(&root);
+
+        // Paths within the mount point: expect success.
+        {
+            assert_eq!(
+                mount_point.resolve(Path::new("gen/foo.hoon")).unwrap(),
+                root.join("gen/foo.hoon")
+            );
+            assert_eq!(
+                mount_point.resolve(Path::new("app/new.hoon")).unwrap(),
+                root.join("app/new.hoon")
+            );
+
+            let noun = Noun::from(Cell::from([
+                Atom::from(".."),
+                Atom::from(".."),
+                Atom::from("hoon"),
+                Atom::null(),
+            ]));
+            let path = PathBuf::try_from(KnotList::try_from(&noun).expect("Noun to KnotList"))
+                .expect("KnotList to PathBuf");
+            assert!(mount_point.resolve(&path).unwrap().starts_with(&root));
+        }
+
+        // Paths that escape the mount point: expect failure.
+        {
+            assert!(mount_point.resolve(Path::new("../escaped.hoon")).is_err());
+            assert!(mount_point
+                .resolve(Path::new("gen/../../escaped.hoon"))
+                .is_err());
+            assert!(mount_point
+                .resolve(&env::temp_dir().join("escaped.hoon"))
+                .is_err());
+
+            #[cfg(unix)]
+            {
+                std::os::unix::fs::symlink(env::temp_dir(), root.join("link"))
+                    .expect("create symlink");
+                assert!(mount_point.resolve(Path::new("link/escaped.hoon")).is_err());
+            }
+        }
+
+        // Symlinks that a scan follows: expect success.
+        #[cfg(unix)]
+        {
+            let mount_point = MountPoint {
+                follow_symlinks: true,
+                ..mount_point
+            };
+            assert_eq!(
+                mount_point.resolve(Path::new("link/escaped.hoon")).unwrap(),
+                root.join("link/escaped.hoon")
+            );
+            assert!(mount_point.resolve(Path::new("../escaped.hoon")).is_err());
+        }
+
+        fs::remove_dir_all(&root).expect("remove mount point");
+    }
+
+    #[test]
+    fn convert_remount_mount_point_request() {
+        // Noun -> RemountMountPoint: expect success.
+        {
+            let noun = Noun::from(Cell::from([Atom::from("base"), Atom::from("/piers/base")]));
+            let req = RemountMountPoint::try_from(&noun).expect("Noun to RemountMountPoint");
+            assert_eq!(req.mount_point, PathComponent(String::from("base")));
+            assert_eq!(req.path, PathBuf::from("/piers/base"));
+        }
+
+        // Noun -> RemountMountPoint: expect failure.
+        {
+            let noun = Noun::from(Atom::from("base"));
+            assert!(RemountMountPoint::try_from(&noun).is_err());
+        }
+    }
+
     #[test]
     fn convert_scan_mount_points_request() {
         macro_rules! test {
@@ -1170,6 +4053,35 @@ mod tests {
             }
         }
 
+        // Noun -> ScanMountPoints: expect success with path prefixes.
+        {
+            let noun = Noun::from(Cell::from([
+                Noun::from(Cell::from([
+                    Noun::from(Atom::from("base")),
+                    Noun::from(Cell::from([Atom::from("gen"), Atom::null()])),
+                    Noun::from(Cell::from([
+                        Atom::from("app"),
+                        Atom::from("dojo"),
+                        Atom::null(),
+                    ])),
+                    Noun::null(),
+                ])),
+                Noun::from(Atom::from("landscape")),
+                Noun::null(),
+            ]));
+            let req = ScanMountPoints::try_from(&noun).expect("Noun to ScanMountPoints");
+            let base = PathComponent(String::from("base"));
+            assert_eq!(
+                req.mount_points,
+                vec![base.clone(), PathComponent(String::from("landscape"))]
+            );
+            assert_eq!(req.prefixes.len(), 1);
+            assert_eq!(
+                req.prefixes[&base],
+                vec![PathBuf::from("gen"), PathBuf::from("app/dojo")]
+            );
+        }
+
         // Noun -> ScanMountPoints: expect failure.
         {
             {
@@ -1191,6 +4103,34 @@ mod tests {
         }
     }
 
+    #[test]
+    fn split_scan_mount_points_request() {
+        let noun = Noun::from(Cell::from([
+            Noun::from(Atom::from("base")),
+            Noun::from(Cell::from([
+                Noun::from(Atom::from("kids")),
+                Noun::from(Cell::from([Atom::from("gen"), Atom::null()])),
+                Noun::null(),
+            ])),
+            Noun::null(),
+        ]));
+        let req = ScanMountPoints::try_from(&noun).expect("Noun to ScanMountPoints");
+        let reqs = Request::ScanMountPoints(req).into_mount_point_requests();
+        assert_eq!(reqs.len(), 2);
+        for (name, req) in reqs {
+            let req = match req {
+                Request::ScanMountPoints(req) => req,
+                _ => panic!("expected a ScanMountPoints request"),
+            };
+            assert_eq!(req.mount_points, std::slice::from_ref(&name));
+            match name.0.as_str() {
+                "base" => assert!(req.prefixes.is_empty()),
+                "kids" => assert_eq!(req.prefixes[&name], [PathBuf::from("gen")]),
+                _ => panic!("unexpected mount point {}", name),
+            }
+        }
+    }
+
     #[test]
     fn convert_update_file_system_request() {
         // Noun -> UpdateFileSystem: expect success.
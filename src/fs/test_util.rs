@@ -0,0 +1,314 @@
+//! A fixture is a mount point in a temporary pier directory, along with the nouns of the requests
+//! that a test typically sends to the driver about the mount point, so that tests don't have to
+//! build large nouns by hand.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let fixture = MountPointFixture::new("commit", "base")
+//!     .file("gen/foo.hoon", "foo")
+//!     .file("app/bar/baz.bin", [0, 1, 2])
+//!     .build()
+//!     .expect("build fixture");
+//!
+//! // Run the driver in `fixture.pier()` and send it `fixture.hill()` and `fixture.dirk()`.
+//!
+//! fixture.assert_committed(&resp);
+//! ```
+
+use super::{Change, Hash, KnotList};
+use noun::{atom::Atom, cell::Cell, convert, Noun};
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// A builder of a [`Fixture`].
+pub struct MountPointFixture {
+    /// The pier directory.
+    pier: PathBuf,
+
+    /// The name of the mount point.
+    name: String,
+
+    /// The contents of each file in the mount point, keyed by mount-point-relative path.
+    files: BTreeMap<PathBuf, Vec<u8>>,
+
+    /// The mount-point-relative paths of empty directories in the mount point.
+    dirs: Vec<PathBuf>,
+}
+
+impl MountPointFixture {
+    /// Describes an empty mount point named `name` in a pier directory that is unique to the test
+    /// named `test`.
+    pub fn new(test: &str, name: &str) -> Self {
+        Self {
+            pier: env::temp_dir().join(format!("{}.{}.fixture", test, process::id())),
+            name: String::from(name),
+            files: BTreeMap::new(),
+            dirs: Vec::new(),
+        }
+    }
+
+    /// Adds a file to the mount point, creating its parent directories as needed.
+    ///
+    /// `path` is a `/`-separated mount-point-relative path.
+    pub fn file(mut self, path: &str, contents: impl Into<Vec<u8>>) -> Self {
+        self.files.insert(PathBuf::from(path), contents.into());
+        self
+    }
+
+    /// Adds an empty directory to the mount point.
+    ///
+    /// `path` is a `/`-separated mount-point-relative path.
+    pub fn dir(mut self, path: &str) -> Self {
+        self.dirs.push(PathBuf::from(path));
+        self
+    }
+
+    /// Creates the mount point and all of its files and directories.
+    pub fn build(self) -> io::Result<Fixture> {
+        let fixture = self.build_unpopulated()?;
+        for (path, contents) in &fixture.files {
+            let path = fixture.path().join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, contents)?;
+        }
+        Ok(fixture)
+    }
+
+    /// Creates the mount point and its empty directories but none of its files, which is useful
+    /// for testing that the driver creates the files.
+    pub fn build_unpopulated(self) -> io::Result<Fixture> {
+        let _ = fs::remove_dir_all(&self.pier);
+        let fixture = Fixture {
+            pier: self.pier,
+            name: self.name,
+            files: self.files,
+        };
+        fs::create_dir_all(fixture.path())?;
+        for dir in &self.dirs {
+            fs::create_dir_all(fixture.path().join(dir))?;
+        }
+        Ok(fixture)
+    }
+}
+
+/// A mount point in a temporary pier directory, which is removed when the fixture is dropped.
+pub struct Fixture {
+    /// The pier directory.
+    pier: PathBuf,
+
+    /// The name of the mount point.
+    name: String,
+
+    /// The contents of each file in the mount point, keyed by mount-point-relative path.
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl Fixture {
+    /// Returns the pier directory, which is the directory the driver should be run in.
+    pub fn pier(&self) -> &Path {
+        &self.pier
+    }
+
+    /// Returns the mount point directory.
+    pub fn path(&self) -> PathBuf {
+        self.pier.join(&self.name)
+    }
+
+    /// Returns a `%hill` request that scans the mount point.
+    pub fn hill(&self) -> Noun {
+        Noun::from(Cell::from([
+            Noun::from(Atom::from("hill")),
+            Noun::from(Atom::from(self.name.as_str())),
+            Noun::null(),
+        ]))
+    }
+
+    /// Returns a `%dirk` request that commits the mount point.
+    pub fn dirk(&self) -> Noun {
+        Noun::from(Cell::from([
+            Atom::from("dirk"),
+            Atom::from(self.name.as_str()),
+        ]))
+    }
+
+    /// Returns a `%dirk-dry-run` request that previews a commit of the mount point.
+    pub fn dirk_dry_run(&self) -> Noun {
+        Noun::from(Cell::from([
+            Atom::from("dirk-dry-run"),
+            Atom::from(self.name.as_str()),
+        ]))
+    }
+
+    /// Returns an `%ogre` request that deletes the mount point.
+    pub fn ogre(&self) -> Noun {
+        Noun::from(Cell::from([
+            Atom::from("ogre"),
+            Atom::from(self.name.as_str()),
+        ]))
+    }
+
+    /// Returns a `%usage` request that reports the disk usage of the mount point.
+    pub fn usage(&self) -> Noun {
+        Noun::from(Cell::from([
+            Atom::from("usage"),
+            Atom::from(self.name.as_str()),
+        ]))
+    }
+
+    /// Returns a `%verify` request that computes a manifest of the mount point.
+    pub fn verify(&self) -> Noun {
+        Noun::from(Cell::from([
+            Atom::from("verify"),
+            Atom::from(self.name.as_str()),
+        ]))
+    }
+
+    /// Returns an `%ergo` request that writes every file of the mount point.
+    pub fn ergo(&self) -> Noun {
+        let changes = self.files.iter().map(|(path, contents)| {
+            Cell::from([
+                Self::knots(path),
+                Noun::null(),
+                Noun::from(Cell::from([
+                    Atom::from("text"),
+                    Atom::from("plain"),
+                    Atom::null(),
+                ])),
+                Noun::from(Atom::from(contents.len())),
+                Noun::from(Atom::from(contents.clone())),
+            ])
+        });
+        Noun::from(Cell::from([
+            Noun::from(Atom::from("ergo")),
+            Noun::from(Atom::from(self.name.as_str())),
+            // This is safe to unwrap because the conversion from `Cell` to `Noun` will never fail.
+            convert!(changes => Noun).unwrap(),
+        ]))
+    }
+
+    /// Returns the response to a `%usage` request that reports `total_bytes` bytes in
+    /// `file_count` files, the largest of which are `largest`.
+    ///
+    /// Each of `largest` is a `/`-separated mount-point-relative path and a size in bytes.
+    pub fn disk_usage(&self, total_bytes: u64, file_count: usize, largest: &[(&str, u64)]) -> Noun {
+        let largest = largest.iter().map(|(path, len)| {
+            Cell::from([Self::knots(Path::new(path)), Noun::from(Atom::from(*len))])
+        });
+        Noun::from(Cell::from([
+            Noun::from(Atom::from("usage")),
+            Noun::from(Atom::from(self.name.as_str())),
+            Noun::from(Atom::from(total_bytes)),
+            Noun::from(Atom::from(file_count)),
+            // This is safe to unwrap because the conversion from `Cell` to `Noun` will never fail.
+            convert!(largest => Noun).unwrap(),
+        ]))
+    }
+
+    /// Asserts that the responses to a `%verify` request form a manifest that lists exactly the
+    /// files of the mount point in order of path, with the hash of each file's contents, and
+    /// returns the number of entries in each chunk of the manifest.
+    pub fn assert_manifest(&self, chunks: &[Noun]) -> Vec<usize> {
+        let mut expected = self
+            .files
+            .iter()
+            .map(|(path, contents)| (Self::knots(path), Noun::from(Hash::from(&contents[..]))));
+        let mut lens = Vec::new();
+        for (i, chunk) in chunks.iter().enumerate() {
+            let [tag, name, last, entries] = match chunk {
+                Noun::Cell(chunk) => chunk
+                    .to_array::<4>()
+                    .expect("[%manifest <mount_point> <last> <entry_list>]"),
+                Noun::Atom(_) => panic!("chunk is an atom"),
+            };
+            assert_eq!(*tag, Noun::from(Atom::from("manifest")));
+            assert_eq!(*name, Noun::from(Atom::from(self.name.as_str())));
+            // `%.y` is 0 and `%.n` is 1.
+            let last_chunk = i + 1 == chunks.len();
+            assert_eq!(*last, Noun::from(Atom::from(u8::from(!last_chunk))));
+
+            let mut len = 0;
+            let mut entries = &*entries;
+            while let Noun::Cell(cell) = entries {
+                let [path, hash, mtime] = match cell.head_ref() {
+                    Noun::Cell(entry) => entry.to_array::<3>().expect("[<path> <hash> <mtime>]"),
+                    Noun::Atom(_) => panic!("entry is an atom"),
+                };
+                let (expected_path, expected_hash) =
+                    expected.next().expect("manifest has too many entries");
+                assert_eq!(*path, expected_path);
+                assert_eq!(*hash, expected_hash, "hash of {:?}", expected_path);
+                assert!(matches!(*mtime, Noun::Atom(_)), "mtime is a cell");
+                len += 1;
+                entries = cell.tail_ref();
+            }
+            assert!(entries.is_null(), "entry list isn't null-terminated");
+            lens.push(len);
+        }
+        assert!(expected.next().is_none(), "manifest has too few entries");
+        lens
+    }
+
+    /// Asserts that a list of changes adds exactly the files of the mount point, in any order.
+    pub fn assert_committed(&self, changes: &Noun) {
+        let changes = convert!(changes => Vec<Change>).expect("Noun to Vec<Change>");
+        let mut files = BTreeMap::new();
+        for change in changes {
+            match change {
+                Change::EditFile { path, bytes } => {
+                    assert!(
+                        files.insert(path.clone(), bytes).is_none(),
+                        "{} changed more than once",
+                        path.display()
+                    );
+                }
+                Change::RemoveFile { path } => {
+                    panic!("unexpected removal of {}", path.display())
+                }
+            }
+        }
+        assert_eq!(files, self.files);
+    }
+
+    /// Asserts that the response to a `%dirk-dry-run` request previews adding exactly the files of
+    /// the mount point, in any order.
+    pub fn assert_previewed(&self, resp: &Noun) {
+        let [tag, name, changes] = match resp {
+            Noun::Cell(resp) => resp
+                .to_array::<3>()
+                .expect("[%dirk-dry-run <mount_point> <changes>]"),
+            Noun::Atom(_) => panic!("preview is an atom"),
+        };
+        assert_eq!(*tag, Noun::from(Atom::from("dirk-dry-run")));
+        assert_eq!(*name, Noun::from(Atom::from(self.name.as_str())));
+        self.assert_committed(&changes);
+    }
+
+    /// Asserts that every file of the mount point exists on disk with the expected contents.
+    pub fn assert_on_disk(&self) {
+        for (path, contents) in &self.files {
+            let actual = fs::read(self.path().join(path))
+                .unwrap_or_else(|err| panic!("failed to read {}: {}", path.display(), err));
+            assert_eq!(&actual, contents, "contents of {}", path.display());
+        }
+    }
+
+    /// Converts a mount-point-relative path into a list of knots.
+    fn knots(path: &Path) -> Noun {
+        let knots = KnotList::try_from(path)
+            .unwrap_or_else(|_err| panic!("failed to convert {} into knots", path.display()));
+        Noun::from(knots)
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.pier);
+    }
+}
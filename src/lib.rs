@@ -69,12 +69,33 @@ macro_rules! impl_try_from_noun_for_request {
     };
 }
 
+#[cfg(feature = "ames")]
+/// Ames networking.
+pub mod ames;
+#[cfg(all(unix, feature = "conn"))]
+/// Control socket for command-line tools.
+pub mod conn;
+#[cfg(feature = "dns")]
+/// DNS binding.
+pub mod dns;
 #[cfg(feature = "file-system")]
 /// File system.
 pub mod fs;
 #[cfg(feature = "http-client")]
 /// HTTP client and server.
 pub mod http;
+#[cfg(all(unix, feature = "lick"))]
+/// Inter-process communication with local programs.
+pub mod lick;
+#[cfg(all(unix, any(feature = "conn", feature = "lick")))]
+/// Messages exchanged with local programs over a socket.
+mod newt;
+#[cfg(feature = "ntp")]
+/// Time synchronization.
+pub mod ntp;
+#[cfg(all(unix, feature = "term"))]
+/// Terminal.
+pub mod term;
 
 use log::{debug, error, info, warn};
 use noun::{
@@ -84,8 +105,10 @@ use noun::{
     Noun,
 };
 use std::{
+    env,
     marker::{Send, Unpin},
     process::{ExitCode, Termination},
+    str::FromStr,
 };
 use tokio::{
     self,
@@ -360,3 +383,121 @@ where
 pub fn atom_as_str(atom: &Atom) -> Result<&str, convert::Error> {
     atom.as_str().map_err(|_| convert::Error::AtomToStr)
 }
+
+/// Reads a configuration option from an environment variable.
+///
+/// Returns `None` if the environment variable is unset or its value can't be parsed as a `T`, in
+/// which case a warning is logged.
+pub fn env_var<T: FromStr>(var: &str) -> Option<T> {
+    let val = env::var(var).ok()?;
+    match val.parse() {
+        Ok(val) => Some(val),
+        Err(_err) => {
+            warn!(
+                "ignoring malformed value {} of environment variable {}",
+                val, var
+            );
+            None
+        }
+    }
+}
+
+/// Binds a non-blocking Unix domain socket at `path` that only the driver's user may connect to.
+///
+/// A socket already at `path` is replaced, but any other file there is left alone and fails the
+/// bind. The socket is bound and restricted to the driver's user within a fresh directory that
+/// only the driver's user may enter, and only then moved to `path`, so other users never get a
+/// chance to connect to it.
+#[cfg(all(unix, any(feature = "conn", feature = "lick", feature = "term")))]
+pub(crate) fn listen_unix(
+    path: &std::path::Path,
+) -> std::io::Result<std::os::unix::net::UnixListener> {
+    use std::{
+        fs, io,
+        os::unix::{
+            fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+            net::UnixListener,
+        },
+        path::Path,
+        process,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    /// Tells apart the directories of sockets bound at the same time.
+    static BIND_CNT: AtomicUsize = AtomicUsize::new(0);
+
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.file_type().is_socket() => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists and isn't a socket", path.display()),
+            ));
+        }
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let private_dir = parent.join(format!(
+        ".sock.{}.{}",
+        process::id(),
+        BIND_CNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    // A directory left behind by an earlier process with the same ID is stale.
+    let _ = fs::remove_dir_all(&private_dir);
+    fs::DirBuilder::new().mode(0o700).create(&private_dir)?;
+    let private_path = private_dir.join("s");
+    let res = UnixListener::bind(&private_path).and_then(|listener| {
+        fs::set_permissions(&private_path, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&private_path, path)?;
+        listener.set_nonblocking(true)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_dir_all(&private_dir);
+    res
+}
+
+#[cfg(all(test, unix, any(feature = "conn", feature = "lick", feature = "term")))]
+mod tests {
+    use super::*;
+    use std::{
+        fs,
+        os::unix::{
+            fs::{FileTypeExt, PermissionsExt},
+            net::UnixStream,
+        },
+        process,
+    };
+
+    #[test]
+    fn listen_on_unix_socket() {
+        let dir = env::temp_dir().join(format!("listen_on_unix_socket.{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("create directory");
+        let path = dir.join("test.sock");
+
+        // Only the driver's user may connect, and nothing but the socket is left behind.
+        let _listener = listen_unix(&path).expect("bind socket");
+        let metadata = fs::symlink_metadata(&path).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        UnixStream::connect(&path).expect("connect to socket");
+
+        // A socket is replaced.
+        let _listener = listen_unix(&path).expect("rebind socket");
+        UnixStream::connect(&path).expect("connect to socket");
+
+        // Any other file is left alone.
+        fs::remove_file(&path).expect("remove socket");
+        fs::write(&path, "data").expect("create file");
+        assert!(listen_unix(&path).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"data");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(&dir).expect("remove directory");
+    }
+}
@@ -0,0 +1,687 @@
+//! Lick driver.
+//!
+//! This module implements the lick IO driver, which connects an [Arvo] kernel's [Lick] vane to
+//! programs running on the same machine as the ship. Each port an agent opens is a Unix socket
+//! that a local program, e.g. one driving a piece of hardware, can connect to and exchange nouns
+//! with the agent over. Because ports are Unix sockets, the driver is only available on Unix
+//! platforms.
+//!
+//! Each request to the driver arrives as a length-encoded jammed (i.e. serialized) noun from some
+//! input source--`stdin`, a socket, etc. The driver understands three types of requests:
+//! - open a port (`%spin`);
+//! - close a port (`%shut`);
+//! - send a noun to the program connected to a port (`%spit`).
+//!
+//! Each port is named by a path, a null-terminated list of path components, e.g. `/hid/keyboard`.
+//! The socket of a port is at that path relative to the driver's socket directory (see
+//! [Configuration]), e.g. `.urb/dev/hid/keyboard`.
+//!
+//! ### `%spin`
+//!
+//! A jammed noun representing a `%spin` request has the following structure:
+//! ```text
+//! [%spin <name>]
+//! ```
+//! where `<name>` is the name of the port. The driver creates the port's socket, replacing any file
+//! already at its path, which only the driver's user may connect to. `%spin` requests don't
+//! generate responses, and are ignored if the port is already open.
+//!
+//! ### `%shut`
+//!
+//! A jammed noun representing a `%shut` request has the following structure:
+//! ```text
+//! [%shut <name>]
+//! ```
+//! where `<name>` is the name of the port. The driver disconnects the port's program, if any, and
+//! removes the port's socket. `%shut` requests don't generate responses.
+//!
+//! ### `%spit`
+//!
+//! A jammed noun representing a `%spit` request has the following structure:
+//! ```text
+//! [%spit <name> <mark> <noun>]
+//! ```
+//! where `<name>` is the name of the port, `<mark>` is an atom describing `<noun>`, and `<noun>` is
+//! any noun. The driver sends `[<mark> <noun>]` to the program connected to the port. If no program
+//! is connected, the driver generates a response of the form:
+//! ```text
+//! [%soak <name> %error 'not connected']
+//! ```
+//!
+//! ### `%soak`
+//!
+//! Each noun sent by the program connected to a port generates a response of the form:
+//! ```text
+//! [%soak <name> <mark> <noun>]
+//! ```
+//! where `<name>` is the name of the port, and `[<mark> <noun>]` is the noun the program sent. When
+//! a program connects to a port, the driver generates `[%soak <name> %connect ~]`, and when it
+//! disconnects, the driver generates `[%soak <name> %disconnect ~]`.
+//!
+//! ### Protocol
+//!
+//! A port accepts one program at a time, and closes connections made while a program is
+//! connected. The program and the driver exchange messages over the connection, each of which is a
+//! version byte of 0, then the length of the payload as a little-endian 32-bit integer, then the
+//! payload, which is a jammed `[<mark> <noun>]` cell. The driver disconnects a program that sends a
+//! message of another version or a payload longer than 128 MiB, and skips payloads that aren't
+//! jammed `[<mark> <noun>]` cells.
+//!
+//! ### Configuration
+//!
+//! The driver is configured by environment variables, which are read when the driver is
+//! initialized:
+//! - `URBIT_IO_DRIVERS_LICK_DIR`: the directory the sockets of ports are created in, which is
+//!   created if it doesn't exist. Defaults to `.urb/dev` relative to the driver's working
+//!   directory, which is the pier.
+//!
+//! [Configuration]: #configuration
+//! [Arvo]: https://developers.urbit.org/reference/glossary/arvo
+//! [Lick]: https://developers.urbit.org/reference/arvo/lick/lick
+
+use crate::{atom_as_str, env_var, listen_unix, newt, Driver, Status};
+use log::{debug, info, warn};
+use noun::{
+    atom::Atom,
+    cell::Cell,
+    convert,
+    serdes::{Cue, Jam},
+    Noun,
+};
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+use tokio::{
+    io::{AsyncWriteExt, Stdin, Stdout},
+    net::{
+        unix::{OwnedReadHalf, OwnedWriteHalf},
+        UnixListener,
+    },
+    sync::mpsc::{self, Receiver, Sender},
+    task::JoinHandle,
+};
+
+//==================================================================================================
+// Request Types
+//==================================================================================================
+
+/// Requests that can be handled by the lick driver.
+enum Request {
+    /// A request to open a port.
+    Spin(Spin),
+
+    /// A request to close a port.
+    Shut(Shut),
+
+    /// A request to send a noun to the program connected to a port.
+    Spit(Spit),
+}
+
+impl_try_from_noun_for_request!(
+    Request,
+    "spin" => Spin,
+    "shut" => Shut,
+    "spit" => Spit,
+);
+
+/// A request to open a port.
+struct Spin {
+    /// The name of the port.
+    name: PortName,
+}
+
+impl TryFrom<&Noun> for Spin {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// <name>
+    /// ```
+    ///
+    /// where `<name>` is a [`PortName`].
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        Ok(Self {
+            name: PortName::try_from(data)?,
+        })
+    }
+}
+
+/// A request to close a port.
+struct Shut {
+    /// The name of the port.
+    name: PortName,
+}
+
+impl TryFrom<&Noun> for Shut {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// <name>
+    /// ```
+    ///
+    /// where `<name>` is a [`PortName`].
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        Ok(Self {
+            name: PortName::try_from(data)?,
+        })
+    }
+}
+
+/// A request to send a noun to the program connected to a port.
+struct Spit {
+    /// The name of the port.
+    name: PortName,
+
+    /// The `[<mark> <noun>]` cell to send.
+    msg: Noun,
+}
+
+impl TryFrom<&Noun> for Spit {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// [<name> <mark> <noun>]
+    /// ```
+    ///
+    /// where `<name>` is a [`PortName`], `<mark>` is an atom, and `<noun>` is any noun.
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        if let Noun::Cell(data) = data {
+            match data.tail_ref() {
+                Noun::Cell(msg) if matches!(msg.head_ref(), Noun::Atom(_)) => Ok(Self {
+                    name: PortName::try_from(data.head_ref())?,
+                    msg: data.tail_ref().clone(),
+                }),
+                Noun::Cell(_msg) => Err(convert::Error::UnexpectedCell),
+                Noun::Atom(_) => Err(convert::Error::UnexpectedAtom),
+            }
+        } else {
+            Err(convert::Error::UnexpectedAtom)
+        }
+    }
+}
+
+/// The name of a port, which is the path of its socket relative to the socket directory.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+struct PortName(Vec<String>);
+
+impl PortName {
+    /// Returns the path of the port's socket in `dir`.
+    fn path_in(&self, dir: &Path) -> PathBuf {
+        let mut path = dir.to_path_buf();
+        path.extend(&self.0);
+        path
+    }
+}
+
+impl fmt::Display for PortName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        for component in &self.0 {
+            write!(f, "/{}", component)?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&Noun> for PortName {
+    type Error = convert::Error;
+
+    /// A properly structured noun is a non-empty null-terminated list of path components, none of
+    /// which may be empty, `.`, `..`, or contain `/`.
+    fn try_from(noun: &Noun) -> Result<Self, Self::Error> {
+        let mut components = Vec::new();
+        let mut list = noun;
+        loop {
+            match list {
+                Noun::Atom(null) if null.is_null() && !components.is_empty() => {
+                    return Ok(Self(components))
+                }
+                Noun::Atom(_) => return Err(convert::Error::ExpectedNull),
+                Noun::Cell(cell) => {
+                    if let Noun::Atom(component) = cell.head_ref() {
+                        let component = atom_as_str(component)?;
+                        if component.is_empty()
+                            || component == "."
+                            || component == ".."
+                            || component.contains('/')
+                            || component.contains('\0')
+                        {
+                            return Err(convert::Error::ImplType);
+                        }
+                        components.push(String::from(component));
+                        list = cell.tail_ref();
+                    } else {
+                        return Err(convert::Error::UnexpectedCell);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl From<&PortName> for Noun {
+    fn from(name: &PortName) -> Self {
+        let mut list = Noun::null();
+        for component in name.0.iter().rev() {
+            list = Noun::from(Cell::from([
+                Noun::from(Atom::from(component.as_str())),
+                list,
+            ]));
+        }
+        list
+    }
+}
+
+//==================================================================================================
+// Driver
+//==================================================================================================
+
+/// Configuration for the lick driver.
+struct Config {
+    /// The directory the sockets of ports are created in.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_LICK_DIR`. Defaults to [`Config::DIR`].
+    dir: PathBuf,
+}
+
+impl Config {
+    /// The default socket directory, relative to the pier.
+    const DIR: &'static str = ".urb/dev";
+
+    /// Reads the configuration from the environment.
+    fn from_env() -> Self {
+        Self {
+            dir: env_var("URBIT_IO_DRIVERS_LICK_DIR").unwrap_or_else(|| PathBuf::from(Self::DIR)),
+        }
+    }
+}
+
+/// The lick driver.
+pub struct Lick {
+    /// The open ports by name.
+    ports: HashMap<PortName, Port>,
+
+    /// The driver configuration.
+    config: Config,
+}
+
+impl Lick {
+    /// Handles a [`Spin`] request.
+    fn spin(&mut self, req: Spin, output_tx: &Sender<Noun>) {
+        if self.ports.contains_key(&req.name) {
+            debug!(target: Self::name(), "port {} is already open", req.name);
+            return;
+        }
+        let path = req.name.path_in(&self.config.dir);
+        match Port::open(req.name.clone(), path, output_tx.clone()) {
+            Ok(port) => {
+                info!(target: Self::name(), "opened port {}", req.name);
+                self.ports.insert(req.name, port);
+            }
+            Err(err) => warn!(target: Self::name(), "failed to open port {}: {}", req.name, err),
+        }
+    }
+
+    /// Handles a [`Shut`] request.
+    fn shut(&mut self, req: Shut) {
+        match self.ports.remove(&req.name) {
+            Some(_port) => info!(target: Self::name(), "closed port {}", req.name),
+            None => debug!(target: Self::name(), "port {} is not open", req.name),
+        }
+    }
+
+    /// Handles a [`Spit`] request.
+    async fn spit(&self, req: Spit, output_tx: &Sender<Noun>) {
+        let client = self.ports.get(&req.name).and_then(|port| {
+            port.client
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .as_ref()
+                .map(|client| client.msg_tx.clone())
+        });
+        let sent = match client {
+            Some(msg_tx) => msg_tx.send(req.msg).await.is_ok(),
+            None => false,
+        };
+        if !sent {
+            debug!(target: Self::name(), "no program is connected to port {}", req.name);
+            let resp = soak(
+                &req.name,
+                Noun::from(Atom::from("error")),
+                Noun::from(Atom::from("not connected")),
+            );
+            if let Err(_resp) = output_tx.send(resp).await {
+                warn!(target: Self::name(), "failed to send error to output task");
+            }
+        }
+    }
+}
+
+/// Implements the [`Driver`] trait for the [`Lick`] driver.
+macro_rules! impl_driver {
+    ($input_src:ty, $output_sink:ty) => {
+        impl Driver<$input_src, $output_sink> for Lick {
+            fn new() -> Result<Self, Status> {
+                let config = Config::from_env();
+                debug!(
+                    target: Self::name(),
+                    "initialized driver with socket directory {}",
+                    config.dir.display()
+                );
+                Ok(Self {
+                    ports: HashMap::new(),
+                    config,
+                })
+            }
+
+            fn name() -> &'static str {
+                "lick"
+            }
+
+            fn handle_requests(
+                mut self,
+                mut input_rx: Receiver<Noun>,
+                output_tx: Sender<Noun>,
+            ) -> JoinHandle<Status> {
+                let task = tokio::spawn(async move {
+                    while let Some(req) = input_rx.recv().await {
+                        match Request::try_from(req) {
+                            Ok(Request::Spin(req)) => self.spin(req, &output_tx),
+                            Ok(Request::Shut(req)) => self.shut(req),
+                            Ok(Request::Spit(req)) => self.spit(req, &output_tx).await,
+                            Err(_) => {
+                                warn!(target: Self::name(), "skipping unidentifiable request");
+                            }
+                        }
+                    }
+                    // Close every port, which disconnects their programs.
+                    self.ports.clear();
+                    Status::Success
+                });
+                debug!(target: Self::name(), "spawned handling task");
+                task
+            }
+        }
+    };
+}
+
+impl_driver!(Stdin, Stdout);
+
+/// Provides an FFI-friendly interface for running the lick driver with `stdin` as the input source
+/// and `stdout` as the output sink.
+#[no_mangle]
+pub extern "C" fn lick_run() -> Status {
+    match Lick::new() {
+        Ok(driver) => driver.run(tokio::io::stdin(), tokio::io::stdout()),
+        Err(status) => status,
+    }
+}
+
+//==================================================================================================
+// Ports
+//==================================================================================================
+
+/// An open port, which is closed when dropped.
+struct Port {
+    /// The path of the port's socket.
+    path: PathBuf,
+
+    /// The program connected to the port, if any.
+    client: Arc<Mutex<Option<Client>>>,
+
+    /// The task that accepts connections to the port.
+    accept_task: JoinHandle<()>,
+}
+
+impl Port {
+    /// The number of nouns that can wait to be sent to the program connected to a port.
+    const QUEUE_SIZE: usize = 32;
+
+    /// Creates the socket of port `name` at `path`, and starts accepting connections to it.
+    fn open(name: PortName, path: PathBuf, output_tx: Sender<Noun>) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let listener = UnixListener::from_std(listen_unix(&path)?)?;
+        let client = Arc::new(Mutex::new(None));
+        let accept_task = tokio::spawn(accept_clients(listener, name, client.clone(), output_tx));
+        Ok(Self {
+            path,
+            client,
+            accept_task,
+        })
+    }
+}
+
+impl Drop for Port {
+    /// Stops accepting connections, disconnects the connected program, and removes the socket.
+    fn drop(&mut self) {
+        self.accept_task.abort();
+        self.client
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Err(err) = fs::remove_file(&self.path) {
+            debug!(
+                target: Lick::name(),
+                "failed to remove {}: {}",
+                self.path.display(),
+                err
+            );
+        }
+    }
+}
+
+/// A program connected to a port, which is disconnected when dropped.
+struct Client {
+    /// Sends nouns to the program.
+    msg_tx: Sender<Noun>,
+
+    /// The tasks that read nouns from and write nouns to the program.
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Drop for Client {
+    /// Stops the client's tasks, which closes the connection.
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Accepts connections to port `name`, keeping the connected program in `client`, until the port
+/// is closed.
+async fn accept_clients(
+    listener: UnixListener,
+    name: PortName,
+    client: Arc<Mutex<Option<Client>>>,
+    output_tx: Sender<Noun>,
+) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _addr)) => stream,
+            Err(err) => {
+                warn!(target: Lick::name(), "failed to accept connection to {}: {}", name, err);
+                continue;
+            }
+        };
+        // Hold the lock until the client is added so that it can't be removed first.
+        let mut client_guard = client.lock().unwrap_or_else(PoisonError::into_inner);
+        if client_guard.is_some() {
+            info!(target: Lick::name(), "refused second connection to {}", name);
+            continue;
+        }
+        info!(target: Lick::name(), "program connected to {}", name);
+        let (stream_rx, stream_tx) = stream.into_split();
+        let (msg_tx, msg_rx) = mpsc::channel(Port::QUEUE_SIZE);
+        *client_guard = Some(Client {
+            msg_tx,
+            tasks: vec![
+                tokio::spawn(read_messages(
+                    stream_rx,
+                    name.clone(),
+                    client.clone(),
+                    output_tx.clone(),
+                )),
+                tokio::spawn(write_messages(stream_tx, name.clone(), msg_rx)),
+            ],
+        });
+    }
+}
+
+/// Sends the nouns from the program connected to port `name` to the output task until the program
+/// disconnects, and then removes the program from `client`.
+async fn read_messages(
+    mut stream_rx: OwnedReadHalf,
+    name: PortName,
+    client: Arc<Mutex<Option<Client>>>,
+    output_tx: Sender<Noun>,
+) {
+    let connected = soak(&name, Noun::from(Atom::from("connect")), Noun::null());
+    if let Err(_resp) = output_tx.send(connected).await {
+        warn!(target: Lick::name(), "failed to send connection to output task");
+        return;
+    }
+    loop {
+        let msg = match newt::read(&mut stream_rx).await {
+            Ok(msg) => msg,
+            Err(err) => {
+                if err.kind() != io::ErrorKind::UnexpectedEof {
+                    warn!(target: Lick::name(), "failed to read from {}: {}", name, err);
+                }
+                break;
+            }
+        };
+        let resp = match Noun::cue(Atom::from(msg)) {
+            Ok(Noun::Cell(msg)) if matches!(msg.head_ref(), Noun::Atom(_)) => {
+                soak(&name, msg.head_ref().clone(), msg.tail_ref().clone())
+            }
+            _ => {
+                warn!(target: Lick::name(), "skipping malformed message from {}", name);
+                continue;
+            }
+        };
+        if let Err(_resp) = output_tx.send(resp).await {
+            warn!(target: Lick::name(), "failed to send message to output task");
+            return;
+        }
+    }
+    info!(target: Lick::name(), "program disconnected from {}", name);
+    let disconnected = soak(&name, Noun::from(Atom::from("disconnect")), Noun::null());
+    if let Err(_resp) = output_tx.send(disconnected).await {
+        warn!(target: Lick::name(), "failed to send disconnection to output task");
+    }
+    // This aborts the current task, which is finishing anyway.
+    client.lock().unwrap_or_else(PoisonError::into_inner).take();
+}
+
+/// Writes the nouns sent to the program connected to port `name` to the program until it's
+/// disconnected.
+async fn write_messages(mut stream_tx: OwnedWriteHalf, name: PortName, mut msg_rx: Receiver<Noun>) {
+    while let Some(msg) = msg_rx.recv().await {
+        let msg = match newt::encode(&msg.jam().into_vec()) {
+            Some(msg) => msg,
+            None => {
+                warn!(target: Lick::name(), "dropped message too long for {}", name);
+                continue;
+            }
+        };
+        if let Err(err) = stream_tx.write_all(&msg).await {
+            warn!(target: Lick::name(), "failed to write to {}: {}", name, err);
+            return;
+        }
+    }
+}
+
+/// Returns the `%soak` response for `[<mark> <noun>]` from port `name`.
+fn soak(name: &PortName, mark: Noun, noun: Noun) -> Noun {
+    Noun::from(Cell::from([
+        Noun::from(Atom::from("soak")),
+        Noun::from(name),
+        mark,
+        noun,
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests the `TryFrom<&Noun>` implementation for [`PortName`] and its conversion back.
+    #[test]
+    fn port_name_from_noun() {
+        let noun = Noun::from(Cell::from([
+            Atom::from("hid"),
+            Atom::from("keyboard"),
+            Atom::null(),
+        ]));
+        let name = PortName::try_from(&noun).expect("&Noun to PortName");
+        assert_eq!(
+            name,
+            PortName(vec![String::from("hid"), String::from("keyboard")])
+        );
+        assert_eq!(name.to_string(), "/hid/keyboard");
+        assert_eq!(
+            name.path_in(Path::new(".urb/dev")),
+            PathBuf::from(".urb/dev/hid/keyboard")
+        );
+        assert_eq!(Noun::from(&name), noun);
+
+        // Malformed: empty.
+        assert!(PortName::try_from(&Noun::null()).is_err());
+
+        // Malformed: not null-terminated.
+        let noun = Noun::from(Cell::from([Atom::from("hid"), Atom::from("keyboard")]));
+        assert!(PortName::try_from(&noun).is_err());
+
+        // Malformed: escapes the socket directory.
+        for component in ["..", ".", "a/b", ""] {
+            let noun = Noun::from(Cell::from([Atom::from(component), Atom::null()]));
+            assert!(PortName::try_from(&noun).is_err());
+        }
+    }
+
+    /// Tests the `TryFrom<Noun>` implementation for [`Request`].
+    #[test]
+    fn request_from_noun() {
+        let name = Noun::from(Cell::from([Atom::from("port"), Atom::null()]));
+        let req = Noun::from(Cell::from([
+            Noun::from(Atom::from("spit")),
+            name.clone(),
+            Noun::from(Atom::from("mark")),
+            Noun::from(Atom::from(7u64)),
+        ]));
+        match Request::try_from(req) {
+            Ok(Request::Spit(req)) => {
+                assert_eq!(req.name, PortName(vec![String::from("port")]));
+                assert_eq!(
+                    req.msg,
+                    Noun::from(Cell::from([Atom::from("mark"), Atom::from(7u64)]))
+                );
+            }
+            _ => panic!("expected %spit request"),
+        }
+
+        let req = Noun::from(Cell::from([Noun::from(Atom::from("spin")), name.clone()]));
+        assert!(matches!(Request::try_from(req), Ok(Request::Spin(_))));
+        let req = Noun::from(Cell::from([Noun::from(Atom::from("shut")), name.clone()]));
+        assert!(matches!(Request::try_from(req), Ok(Request::Shut(_))));
+
+        // Malformed: the mark is a cell.
+        let req = Noun::from(Cell::from([
+            Noun::from(Atom::from("spit")),
+            name,
+            Noun::from(Cell::from([Atom::from(1u64), Atom::from(2u64)])),
+            Noun::null(),
+        ]));
+        assert!(Request::try_from(req).is_err());
+    }
+}
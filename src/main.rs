@@ -1,4 +1,13 @@
-use io_drivers::{http::client::http_client_run, Status};
+use io_drivers::{
+    ames::ames_run, dns::dns_run, fs::file_system_run, http::client::http_client_run, ntp::ntp_run,
+    Status,
+};
+#[cfg(unix)]
+use io_drivers::{
+    conn::conn_run,
+    lick::lick_run,
+    term::{term_attach_run, term_run},
+};
 use simplelog::{Config, LevelFilter, WriteLogger};
 use std::{env, fs::File};
 
@@ -22,7 +31,19 @@ fn main() -> Status {
         .expect("initialize logger");
     }
     match &driver[..] {
+        "ames" => ames_run(),
+        #[cfg(unix)]
+        "conn" => conn_run(),
+        "dns" => dns_run(),
+        "file-system" => file_system_run(),
         "http-client" => http_client_run(),
+        #[cfg(unix)]
+        "lick" => lick_run(),
+        "ntp" => ntp_run(),
+        #[cfg(unix)]
+        "term" => term_run(),
+        #[cfg(unix)]
+        "term-attach" => term_attach_run(),
         _ => Status::NoDriver,
     }
 }
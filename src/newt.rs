@@ -0,0 +1,81 @@
+//! Messages exchanged with local programs over a socket.
+//!
+//! Each message is a version byte of 0, followed by the length of the payload as a little-endian
+//! 32-bit integer, followed by the payload, which is usually a jammed noun. This is the framing
+//! Vere uses for its sockets, which keeps existing programs working with these drivers.
+
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// The version byte that starts each message.
+const VERSION: u8 = 0;
+
+/// The longest payload of a message.
+pub(crate) const MAX_PAYLOAD_LEN: usize = 1 << 27;
+
+/// Reads a message from `src`, returning its payload.
+///
+/// Fails if the message has an unknown version or a payload longer than [`MAX_PAYLOAD_LEN`].
+pub(crate) async fn read(src: &mut (impl AsyncRead + Unpin)) -> io::Result<Vec<u8>> {
+    let version = src.read_u8().await?;
+    if version != VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unknown message version {}", version),
+        ));
+    }
+    let len = src.read_u32_le().await?;
+    let len = usize::try_from(len)
+        .ok()
+        .filter(|len| *len <= MAX_PAYLOAD_LEN)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("message length {} is too long", len),
+            )
+        })?;
+    let mut payload = vec![0; len];
+    src.read_exact(&mut payload).await?;
+    Ok(payload)
+}
+
+/// Returns the message with `payload`, or `None` if `payload` is longer than
+/// [`MAX_PAYLOAD_LEN`].
+pub(crate) fn encode(payload: &[u8]) -> Option<Vec<u8>> {
+    if payload.len() > MAX_PAYLOAD_LEN {
+        return None;
+    }
+    let len = u32::try_from(payload.len()).ok()?;
+    let mut msg = Vec::with_capacity(payload.len() + 5);
+    msg.push(VERSION);
+    msg.extend_from_slice(&len.to_le_bytes());
+    msg.extend_from_slice(payload);
+    Some(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that [`read()`] reads what [`encode()`] encodes, and rejects malformed messages.
+    #[tokio::test]
+    async fn read_messages() {
+        let msg = encode(b"hello").expect("encode message");
+        assert_eq!(&msg[..5], &[0, 5, 0, 0, 0]);
+        let mut src = &msg[..];
+        assert_eq!(read(&mut src).await.expect("read message"), b"hello");
+        assert!(read(&mut src).await.is_err());
+
+        // Malformed: unknown version.
+        let mut src = &[1, 0, 0, 0, 0][..];
+        assert!(read(&mut src).await.is_err());
+
+        // Malformed: too long.
+        let mut src = &[0, 0, 0, 0, 0x10][..];
+        assert!(read(&mut src).await.is_err());
+
+        // Malformed: the payload is cut off.
+        let mut src = &[0, 2, 0, 0, 0, b'h'][..];
+        assert!(read(&mut src).await.is_err());
+    }
+}
@@ -0,0 +1,640 @@
+//! Time synchronization driver.
+//!
+//! This module implements the time synchronization IO driver, which keeps the king informed of how
+//! far the host's clock is from the true time, so that a ship on a host with a bad clock can
+//! correct the timestamps of its events. The driver queries a list of [NTP] servers with the
+//! simple subset of the protocol described by [RFC 4330] when it starts and periodically after
+//! that, estimates the offset of the host's clock from the responses, and estimates how fast the
+//! host's clock drifts (i.e. its skew) from consecutive offsets.
+//!
+//! Each request to the driver arrives as a length-encoded jammed (i.e. serialized) noun from some
+//! input source--`stdin`, a socket, etc. The driver understands one type of request:
+//! - query the servers now (`%sync`).
+//!
+//! ### `%sync`
+//!
+//! A jammed noun representing a `%sync` request has the following structure:
+//! ```text
+//! [%sync ~]
+//! ```
+//! The driver queries the servers right away and reports the result, whether or not the offset
+//! changed, as a `%time` response. If no server responds, it generates a response of the form:
+//! ```text
+//! [%fail 'no server responded']
+//! ```
+//!
+//! ### `%time`
+//!
+//! The driver generates a response of the form:
+//! ```text
+//! [%time <offset> <skew>]
+//! ```
+//! after its first query, and after each later query that finds the offset has moved by at least
+//! `URBIT_IO_DRIVERS_NTP_THRESHOLD` (see [Configuration]) since it was last reported, where:
+//! - `<offset>` is the true time minus the host's time in microseconds, as a signed integer (i.e.
+//!   an `@s`, so that `--1` is 2 and `-1` is 1). A positive offset means the host's clock is
+//!   behind.
+//! - `<skew>` is `~` after the first query, and otherwise `[~ <ppm>]`, where `<ppm>` is how many
+//!   microseconds per second the host's clock loses relative to the true time, as an `@s`.
+//!
+//! Of the servers that respond to a query, the response that took the least time to arrive is
+//! used. Responses from servers that report they aren't synchronized are ignored.
+//!
+//! ### Configuration
+//!
+//! The driver is configured by environment variables, which are read when the driver is
+//! initialized:
+//! - `URBIT_IO_DRIVERS_NTP_SERVERS`: a comma-separated list of servers to query, each a host name
+//!   or IP address with an optional port, e.g. `time.example.com,192.0.2.1:1123`. Defaults to
+//!   `pool.ntp.org`.
+//! - `URBIT_IO_DRIVERS_NTP_INTERVAL`: how long to wait between queries in seconds. Defaults to
+//!   1024.
+//! - `URBIT_IO_DRIVERS_NTP_THRESHOLD`: how far the offset must move to be reported in
+//!   milliseconds. Defaults to 100.
+//!
+//! [Configuration]: #configuration
+//! [NTP]: https://en.wikipedia.org/wiki/Network_Time_Protocol
+//! [RFC 4330]: https://www.rfc-editor.org/rfc/rfc4330
+
+use crate::{env_var, Driver, Status};
+use log::{debug, info, warn};
+use noun::{atom::Atom, cell::Cell, convert, Noun};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    io::{Stdin, Stdout},
+    net::{self, UdpSocket},
+    sync::mpsc::{self, Receiver, Sender},
+    task::JoinHandle,
+    time,
+};
+
+//==================================================================================================
+// Request Types
+//==================================================================================================
+
+/// Requests that can be handled by the time synchronization driver.
+enum Request {
+    /// A request to query the servers now.
+    Query(Query),
+}
+
+impl_try_from_noun_for_request!(Request, "sync" => Query);
+
+/// A request to query the servers now.
+struct Query;
+
+impl TryFrom<&Noun> for Query {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// ~
+    /// ```
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        match data {
+            Noun::Atom(null) if null.is_null() => Ok(Self),
+            _ => Err(convert::Error::ExpectedNull),
+        }
+    }
+}
+
+//==================================================================================================
+// Driver
+//==================================================================================================
+
+/// Configuration for the time synchronization driver.
+#[derive(Clone)]
+struct Config {
+    /// The servers to query.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_NTP_SERVERS`. Defaults to [`Servers::default()`].
+    servers: Servers,
+
+    /// How long to wait between queries.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_NTP_INTERVAL` as a number of seconds.
+    interval: Duration,
+
+    /// How far the offset must move from the offset last reported to be reported again.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_NTP_THRESHOLD` as a number of milliseconds.
+    threshold: Duration,
+}
+
+impl Config {
+    /// Reads the configuration from the environment.
+    fn from_env() -> Self {
+        Self {
+            servers: env_var("URBIT_IO_DRIVERS_NTP_SERVERS").unwrap_or_default(),
+            interval: env_var("URBIT_IO_DRIVERS_NTP_INTERVAL")
+                .map(Duration::from_secs)
+                .unwrap_or(Duration::from_secs(1024)),
+            threshold: env_var("URBIT_IO_DRIVERS_NTP_THRESHOLD")
+                .map(Duration::from_millis)
+                .unwrap_or(Duration::from_millis(100)),
+        }
+    }
+}
+
+/// The servers to query, each as a `host:port` string.
+#[derive(Clone, Debug, PartialEq)]
+struct Servers(Vec<String>);
+
+impl Servers {
+    /// The port NTP servers listen on.
+    const PORT: u16 = 123;
+}
+
+impl Default for Servers {
+    fn default() -> Self {
+        Self(vec![format!("pool.ntp.org:{}", Self::PORT)])
+    }
+}
+
+impl FromStr for Servers {
+    type Err = ();
+
+    /// Parses a comma-separated list of host names or IP addresses, each with an optional port.
+    /// IPv6 addresses with a port are enclosed in brackets, e.g. `[2001:db8::1]:123`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut servers = Vec::new();
+        for server in s.split(',').map(str::trim) {
+            let server = if server.parse::<SocketAddr>().is_ok() {
+                String::from(server)
+            } else if let Ok(ip) = server.parse::<IpAddr>() {
+                SocketAddr::new(ip, Self::PORT).to_string()
+            } else {
+                match server.rsplit_once(':') {
+                    Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+                        String::from(server)
+                    }
+                    Some(_) => return Err(()),
+                    None if !server.is_empty() => format!("{}:{}", server, Self::PORT),
+                    None => return Err(()),
+                }
+            };
+            servers.push(server);
+        }
+        Ok(Self(servers))
+    }
+}
+
+/// The time synchronization driver.
+pub struct Ntp {
+    /// The driver configuration.
+    config: Config,
+}
+
+impl Ntp {
+    /// How long to wait for a server to respond.
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    /// Queries every server, returning the sample from the response that took the least time to
+    /// arrive, if any.
+    async fn sample(servers: &Servers) -> Option<Sample> {
+        let mut best: Option<Sample> = None;
+        for server in &servers.0 {
+            match time::timeout(Self::TIMEOUT, Self::query(server)).await {
+                Ok(Ok(sample)) => {
+                    debug!(
+                        target: Self::name(),
+                        "{} gave offset {}us with delay {}us", server, sample.offset, sample.delay
+                    );
+                    match best {
+                        Some(best) if best.delay <= sample.delay => (),
+                        _ => best = Some(sample),
+                    }
+                }
+                Ok(Err(err)) => debug!(target: Self::name(), "failed to query {}: {}", server, err),
+                Err(_elapsed) => debug!(target: Self::name(), "{} didn't respond", server),
+            }
+        }
+        best
+    }
+
+    /// Queries `server`, returning the sample from its response.
+    async fn query(server: &str) -> io::Result<Sample> {
+        let addr = net::lookup_host(server)
+            .await?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no address"))?;
+        let local_addr = match addr {
+            SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+            SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+        };
+        let udp = UdpSocket::bind(local_addr).await?;
+        udp.connect(addr).await?;
+        let sent_at = now();
+        let sent_ts = to_timestamp(sent_at);
+        udp.send(&request(sent_ts)).await?;
+        let mut buf = [0; 2 * PACKET_LEN];
+        loop {
+            let len = udp.recv(&mut buf).await?;
+            let recvd_at = now();
+            match parse_reply(&buf[..len], sent_ts, sent_at, recvd_at) {
+                Ok(sample) => return Ok(sample),
+                // Skip packets that aren't the reply to this request.
+                Err(err) if err.kind() == io::ErrorKind::InvalidInput => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Queries the servers when the task starts, every `config.interval` after that, and whenever
+    /// a [`Query`] request arrives over `query_rx`, reporting the results to the output task until
+    /// `query_rx` closes or the output task stops accepting responses.
+    async fn sync_clock(config: Config, mut query_rx: Receiver<()>, output_tx: Sender<Noun>) {
+        let mut clock = Clock::default();
+        let threshold = i64::try_from(config.threshold.as_micros()).unwrap_or(i64::MAX);
+        let mut requested = false;
+        loop {
+            let resp = match Self::sample(&config.servers).await {
+                Some(sample) => {
+                    clock
+                        .observe(sample, threshold, requested)
+                        .map(|(offset, skew)| {
+                            info!(
+                                target: Self::name(),
+                                "clock is off by {}us with skew {:?}ppm", offset, skew
+                            );
+                            time_resp(offset, skew)
+                        })
+                }
+                None => {
+                    warn!(target: Self::name(), "no server responded");
+                    requested.then(|| {
+                        Noun::from(Cell::from([
+                            Atom::from("fail"),
+                            Atom::from("no server responded"),
+                        ]))
+                    })
+                }
+            };
+            if let Some(resp) = resp {
+                if let Err(_resp) = output_tx.send(resp).await {
+                    warn!(target: Self::name(), "failed to send time to output task");
+                    return;
+                }
+            }
+            requested = match time::timeout(config.interval, query_rx.recv()).await {
+                Ok(Some(())) => true,
+                Ok(None) => return,
+                Err(_elapsed) => false,
+            };
+        }
+    }
+}
+
+/// Implements the [`Driver`] trait for the [`Ntp`] driver.
+macro_rules! impl_driver {
+    ($input_src:ty, $output_sink:ty) => {
+        impl Driver<$input_src, $output_sink> for Ntp {
+            fn new() -> Result<Self, Status> {
+                let config = Config::from_env();
+                debug!(
+                    target: Self::name(),
+                    "initialized driver with servers {:?}", config.servers.0
+                );
+                Ok(Self { config })
+            }
+
+            fn name() -> &'static str {
+                "ntp"
+            }
+
+            fn handle_requests(
+                self,
+                mut input_rx: Receiver<Noun>,
+                output_tx: Sender<Noun>,
+            ) -> JoinHandle<Status> {
+                let task = tokio::spawn(async move {
+                    // Queries are only requested one at a time.
+                    let (query_tx, query_rx) = mpsc::channel(1);
+                    let sync_task =
+                        tokio::spawn(Self::sync_clock(self.config, query_rx, output_tx));
+                    while let Some(req) = input_rx.recv().await {
+                        match Request::try_from(req) {
+                            Ok(Request::Query(Query)) => {
+                                if query_tx.try_send(()).is_err() {
+                                    debug!(target: Self::name(), "query already requested");
+                                }
+                            }
+                            Err(_) => {
+                                warn!(target: Self::name(), "skipping unidentifiable request");
+                            }
+                        }
+                    }
+                    sync_task.abort();
+                    let _ = sync_task.await;
+                    Status::Success
+                });
+                debug!(target: Self::name(), "spawned handling task");
+                task
+            }
+        }
+    };
+}
+
+impl_driver!(Stdin, Stdout);
+
+/// Provides an FFI-friendly interface for running the time synchronization driver with `stdin` as
+/// the input source and `stdout` as the output sink.
+#[no_mangle]
+pub extern "C" fn ntp_run() -> Status {
+    match Ntp::new() {
+        Ok(driver) => driver.run(tokio::io::stdin(), tokio::io::stdout()),
+        Err(status) => status,
+    }
+}
+
+//==================================================================================================
+// Clock
+//==================================================================================================
+
+/// What a server's response says about the host's clock.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Sample {
+    /// The true time minus the host's time in microseconds.
+    offset: i64,
+
+    /// How long the request and response spent in transit in microseconds.
+    delay: i64,
+
+    /// When the response arrived by the host's clock in microseconds since the Unix epoch.
+    at: i64,
+}
+
+/// What the driver knows about the host's clock.
+#[derive(Default)]
+struct Clock {
+    /// The latest sample.
+    prev: Option<Sample>,
+
+    /// The offset last reported.
+    reported: Option<i64>,
+}
+
+impl Clock {
+    /// Records `sample`, returning the offset and skew to report if the offset has moved by at
+    /// least `threshold` microseconds since it was last reported or a report was `requested`.
+    fn observe(
+        &mut self,
+        sample: Sample,
+        threshold: i64,
+        requested: bool,
+    ) -> Option<(i64, Option<i64>)> {
+        let skew = self.prev.and_then(|prev| {
+            let elapsed = i128::from(sample.at) - i128::from(prev.at);
+            if elapsed <= 0 {
+                return None;
+            }
+            let drift = i128::from(sample.offset) - i128::from(prev.offset);
+            i64::try_from(drift * 1_000_000 / elapsed).ok()
+        });
+        self.prev = Some(sample);
+        let moved = match self.reported {
+            Some(reported) => sample.offset.abs_diff(reported) >= threshold.unsigned_abs(),
+            None => true,
+        };
+        if moved || requested {
+            self.reported = Some(sample.offset);
+            Some((sample.offset, skew))
+        } else {
+            None
+        }
+    }
+}
+
+/// The length of an SNTP packet without extensions.
+const PACKET_LEN: usize = 48;
+
+/// The number of seconds from the NTP epoch (1900) to the Unix epoch (1970).
+const EPOCH_DELTA: i64 = 2_208_988_800;
+
+/// Returns the time by the host's clock in microseconds since the Unix epoch.
+fn now() -> i64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(since) => i64::try_from(since.as_micros()).unwrap_or(i64::MAX),
+        Err(err) => -i64::try_from(err.duration().as_micros()).unwrap_or(i64::MAX),
+    }
+}
+
+/// Converts a time in microseconds since the Unix epoch to an NTP timestamp.
+fn to_timestamp(micros: i64) -> u64 {
+    // Times after 2036 wrap around into the next era, which `from_timestamp()` accounts for.
+    let secs = (micros.div_euclid(1_000_000) + EPOCH_DELTA) as u64 & 0xffff_ffff;
+    // Round up so that converting back gives the same time.
+    let frac = ((micros.rem_euclid(1_000_000) as u64) << 32).div_ceil(1_000_000);
+    (secs << 32) | frac
+}
+
+/// Converts an NTP timestamp to a time in microseconds since the Unix epoch.
+fn from_timestamp(ts: u64) -> i64 {
+    let mut secs = (ts >> 32) as i64;
+    // Per RFC 4330, timestamps with the most significant bit clear are after 2036.
+    if secs & 0x8000_0000 == 0 {
+        secs += 1 << 32;
+    }
+    let frac = ((ts & 0xffff_ffff) * 1_000_000) >> 32;
+    (secs - EPOCH_DELTA) * 1_000_000 + frac as i64
+}
+
+/// Returns an SNTP request sent at `sent_ts`.
+fn request(sent_ts: u64) -> [u8; PACKET_LEN] {
+    let mut req = [0; PACKET_LEN];
+    // Leap indicator 0, version 4, and client mode.
+    req[0] = 0x23;
+    req[40..48].copy_from_slice(&sent_ts.to_be_bytes());
+    req
+}
+
+/// Parses the reply to the SNTP request sent at `sent_ts`, which is `sent_at` by the host's clock,
+/// that arrived at `recvd_at`.
+///
+/// Fails with [`io::ErrorKind::InvalidInput`] if the packet isn't a reply to the request, and with
+/// [`io::ErrorKind::InvalidData`] if the server can't be used.
+fn parse_reply(buf: &[u8], sent_ts: u64, sent_at: i64, recvd_at: i64) -> io::Result<Sample> {
+    let timestamp = |i: usize| {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&buf[i..i + 8]);
+        u64::from_be_bytes(bytes)
+    };
+    if buf.len() < PACKET_LEN || buf[0] & 0x07 != 4 || timestamp(24) != sent_ts {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a reply"));
+    }
+    let (leap, stratum) = (buf[0] >> 6, buf[1]);
+    if leap == 3 || stratum == 0 || stratum >= 16 || timestamp(40) == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("server isn't synchronized (stratum {})", stratum),
+        ));
+    }
+    let (server_recvd_at, server_sent_at) =
+        (from_timestamp(timestamp(32)), from_timestamp(timestamp(40)));
+    let offset = ((server_recvd_at - sent_at) + (server_sent_at - recvd_at)) / 2;
+    let delay = (recvd_at - sent_at) - (server_sent_at - server_recvd_at);
+    Ok(Sample {
+        offset,
+        delay,
+        at: recvd_at,
+    })
+}
+
+/// Returns `n` as a signed integer (i.e. an `@s`), which is `2n` if `n` is positive and `-2n - 1`
+/// if `n` is negative.
+fn signed(n: i64) -> Atom {
+    let abs = n.unsigned_abs().saturating_mul(2);
+    Atom::from(if n < 0 { abs - 1 } else { abs })
+}
+
+/// Returns the response reporting `offset` and `skew`.
+fn time_resp(offset: i64, skew: Option<i64>) -> Noun {
+    let skew = match skew {
+        Some(skew) => Noun::from(Cell::from([Atom::null(), signed(skew)])),
+        None => Noun::null(),
+    };
+    Noun::from(Cell::from([
+        Noun::from(Atom::from("time")),
+        Noun::from(signed(offset)),
+        skew,
+    ]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests the `FromStr` implementation for [`Servers`].
+    #[test]
+    fn servers_from_str() {
+        assert_eq!(
+            Servers::from_str("time.example.com, 192.0.2.1, 192.0.2.2:1123,::1,[::1]:9"),
+            Ok(Servers(vec![
+                String::from("time.example.com:123"),
+                String::from("192.0.2.1:123"),
+                String::from("192.0.2.2:1123"),
+                String::from("[::1]:123"),
+                String::from("[::1]:9"),
+            ]))
+        );
+        assert_eq!(Servers::from_str("time.example.com:ntp"), Err(()));
+        assert_eq!(Servers::from_str("a,,b"), Err(()));
+        assert_eq!(
+            Servers::default(),
+            Servers(vec![String::from("pool.ntp.org:123")])
+        );
+    }
+
+    /// Tests converting times to and from NTP timestamps.
+    #[test]
+    fn convert_timestamps() {
+        // The Unix epoch.
+        assert_eq!(to_timestamp(0), 2_208_988_800 << 32);
+        assert_eq!(from_timestamp(2_208_988_800 << 32), 0);
+
+        for micros in [1_700_000_000_250_000, 2_200_000_000_000_001, -1] {
+            assert_eq!(from_timestamp(to_timestamp(micros)), micros);
+        }
+    }
+
+    /// Tests how replies are parsed into [`Sample`]s.
+    #[test]
+    fn parse_replies() {
+        let sent_at = 1_700_000_000_000_000;
+        let sent_ts = to_timestamp(sent_at);
+        assert_eq!(&request(sent_ts)[40..48], &sent_ts.to_be_bytes());
+
+        // The server's clock is 5s ahead, and each leg of the trip takes 10ms.
+        let reply = |leap_mode: u8, stratum: u8, orig: u64| {
+            let mut reply = [0; PACKET_LEN];
+            reply[0] = leap_mode;
+            reply[1] = stratum;
+            reply[24..32].copy_from_slice(&orig.to_be_bytes());
+            reply[32..40].copy_from_slice(&to_timestamp(sent_at + 5_010_000).to_be_bytes());
+            reply[40..48].copy_from_slice(&to_timestamp(sent_at + 5_011_000).to_be_bytes());
+            reply
+        };
+        let recvd_at = sent_at + 21_000;
+        let sample =
+            parse_reply(&reply(0x24, 2, sent_ts), sent_ts, sent_at, recvd_at).expect("parse reply");
+        assert_eq!(
+            sample,
+            Sample {
+                offset: 5_000_000,
+                delay: 20_000,
+                at: recvd_at,
+            }
+        );
+
+        // Not a reply to the request.
+        let err = parse_reply(&reply(0x24, 2, sent_ts + 1), sent_ts, sent_at, recvd_at);
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let err = parse_reply(&reply(0x23, 2, sent_ts), sent_ts, sent_at, recvd_at);
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        // Unsynchronized server, and a kiss-of-death packet.
+        let err = parse_reply(&reply(0xe4, 2, sent_ts), sent_ts, sent_at, recvd_at);
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let err = parse_reply(&reply(0x24, 0, sent_ts), sent_ts, sent_at, recvd_at);
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    /// Tests when a [`Clock`] reports offsets, and how it estimates skew.
+    #[test]
+    fn observe_clock() {
+        let mut clock = Clock::default();
+        let sample = |offset, at| Sample {
+            offset,
+            delay: 0,
+            at,
+        };
+
+        // The first sample is always reported.
+        assert_eq!(
+            clock.observe(sample(1_000, 0), 100, false),
+            Some((1_000, None))
+        );
+
+        // The clock loses 50us over 1s.
+        assert_eq!(clock.observe(sample(1_050, 1_000_000), 100, false), None);
+        assert_eq!(
+            clock.observe(sample(1_100, 2_000_000), 100, false),
+            Some((1_100, Some(50)))
+        );
+        assert_eq!(
+            clock.observe(sample(1_100, 3_000_000), 100, true),
+            Some((1_100, Some(0)))
+        );
+    }
+
+    /// Tests how offsets are encoded as signed integers in `%time` responses.
+    #[test]
+    fn time_to_noun() {
+        assert_eq!(signed(0), Atom::from(0u64));
+        assert_eq!(signed(1), Atom::from(2u64));
+        assert_eq!(signed(-1), Atom::from(1u64));
+        assert_eq!(signed(-2), Atom::from(3u64));
+        assert_eq!(
+            time_resp(-5, Some(3)),
+            Noun::from(Cell::from([
+                Noun::from(Atom::from("time")),
+                Noun::from(Atom::from(9u64)),
+                Noun::from(Cell::from([Atom::null(), Atom::from(6u64)])),
+            ]))
+        );
+        assert_eq!(
+            time_resp(5, None),
+            Noun::from(Cell::from([
+                Atom::from("time"),
+                Atom::from(10u64),
+                Atom::null(),
+            ]))
+        );
+    }
+}
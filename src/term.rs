@@ -0,0 +1,2523 @@
+//! Terminal driver.
+//!
+//! This module implements the terminal IO driver, which connects an [Arvo] kernel's [Dill] terminal
+//! vane to the terminal the ship was started from. Because the driver's `stdin` and `stdout` carry
+//! its requests and responses, the driver opens the terminal itself and puts it in raw mode while
+//! it runs, so that each key reaches the ship as it's pressed rather than a line at a time. The
+//! terminal's previous mode is restored when the driver exits. The driver relies on Unix terminal
+//! and socket APIs, so it's only available on Unix platforms.
+//!
+//! Other terminals can attach to the driver as additional sessions, so that more than one person
+//! or window can use the ship's dojo at once (see [Sessions]). Each session has an ID, which is 0
+//! for the terminal the driver was started from, and which the driver's requests and responses
+//! refer to.
+//!
+//! Each request to the driver arrives as a length-encoded jammed (i.e. serialized) noun from some
+//! input source--`stdin`, a socket, etc. The driver understands one type of request:
+//! - draw on the terminal of a session (`%blit`).
+//!
+//! ### `%blit`
+//!
+//! A jammed noun representing a `%blit` request has the following structure:
+//! ```text
+//! [%blit <session> <blit>]
+//! ```
+//! where `<session>` is the ID of the session whose terminal to draw on, and `<blit>` is one of:
+//! - `[%bel ~]`: ring the terminal's bell.
+//! - `[%clr ~]`: clear the screen.
+//! - `[%hop <col>]`: move the cursor to column `<col>` of the current line.
+//! - `[%hop <col> <row>]`: move the cursor to column `<col>` of row `<row>`.
+//! - `[%klr <stub>]`: write styled text at the cursor, where `<stub>` is a null-terminated list of
+//!   `[<style> <text>]` pairs (see below).
+//! - `[%mor <blits>]`: draw each blit of the null-terminated list `<blits>` in order.
+//! - `[%nel ~]`: move the cursor to the start of the next line.
+//! - `[%put <text>]`: write `<text>`, a null-terminated list of UTF-32 characters (i.e. `@c`s), at
+//!   the cursor.
+//! - `[%wyp ~]`: clear the current line.
+//!
+//! Columns and rows count from 0 at the top left of the screen. `%blit` requests don't generate
+//! responses, and are dropped if `<session>` isn't attached.
+//!
+//! The `<style>` of each piece of text of a `%klr` blit has the form:
+//! ```text
+//! [<decorations> <background> <foreground>]
+//! ```
+//! where `<decorations>` is a set of `%bl` (blinking), `%br` (bold), and `%un` (underlined), and
+//! `<background>` and `<foreground>` are each `~` for the terminal's default color, one of `%k`,
+//! `%r`, `%g`, `%y`, `%b`, `%m`, `%c`, and `%w` for black, red, green, yellow, blue, magenta, cyan,
+//! and white, or `[<r> <g> <b>]` for a 24-bit color. Colors the terminal can't display are drawn as
+//! the closest color it can, and styles are left out entirely on a dumb terminal.
+//!
+//! ### `%belt`
+//!
+//! The keys pressed on the terminal of a session generate responses of the form:
+//! ```text
+//! [%belt <session> <belt>]
+//! ```
+//! where `<session>` is the ID of the session, and `<belt>` is one of:
+//! - `[%txt <text>]`: characters were typed, where `<text>` is a null-terminated list of UTF-32
+//!   characters. Characters that arrive together are reported together.
+//! - `[%aro <dir>]`: an arrow key was pressed, where `<dir>` is `%d`, `%l`, `%r`, or `%u`.
+//! - `[%bac ~]`: backspace was pressed.
+//! - `[%del ~]`: delete was pressed.
+//! - `[%ret ~]`: return was pressed.
+//! - `[%fun <n>]`: function key `F<n>` was pressed, where `<n>` is between 1 and 12.
+//! - `[%mod <mod> <key>]`: a key was pressed with control (`%ctl`) or alt (`%met`) held, where
+//!   `<key>` is the key's UTF-32 character, e.g. `[%mod %ctl 'c']`. Tab is `[%mod %ctl 'i']`.
+//!
+//! Escape sequences the driver doesn't recognize are ignored.
+//!
+//! ### `%blew`
+//!
+//! When a session attaches, and whenever its terminal is resized, the driver generates a response
+//! of the form:
+//! ```text
+//! [%blew <session> <cols> <rows>]
+//! ```
+//! where `<session>` is the ID of the session, and `<cols>` and `<rows>` are the width and height
+//! of its terminal in characters.
+//!
+//! ### Sessions
+//!
+//! If `URBIT_IO_DRIVERS_TERM_SOCKET` is set, the driver listens on a Unix socket at that path,
+//! which only the driver's user may connect to. Each connection to the socket attaches a new
+//! session, which generates a response of the form:
+//! ```text
+//! [%open <session>]
+//! ```
+//! where `<session>` is the new session's ID. When the connection closes, the session detaches,
+//! which generates a response of the form:
+//! ```text
+//! [%shut <session>]
+//! ```
+//! The blits of a session are written to its connection as the bytes that draw them on a terminal.
+//! The client sends messages to the driver over the connection, each of which is a one-byte kind,
+//! then the length of the payload as a little-endian 16-bit integer, then the payload:
+//! - kind 0: the payload is bytes read from the client's terminal, which are parsed into keys.
+//! - kind 1: the payload is the width and then the height of the client's terminal in characters,
+//!   each as a little-endian 16-bit integer.
+//!
+//! `io_drivers term-attach` is such a client: it attaches the terminal it's run from to the socket
+//! of `URBIT_IO_DRIVERS_TERM_SOCKET` as a session, until the driver exits or control-] is pressed.
+//!
+//! If the driver can't open its own terminal, e.g. because the ship was started in the background,
+//! but `URBIT_IO_DRIVERS_TERM_SOCKET` is set, it runs without session 0.
+//!
+//! ### Local editing
+//!
+//! If `URBIT_IO_DRIVERS_TERM_EDIT` is `local`, the driver edits the line being typed on each
+//! session's terminal itself, and only sends the line to the ship, as a `%txt` belt followed by a
+//! `%ret` belt, when return is pressed. The line is drawn after the ship's last blit, so it follows
+//! the dojo's prompt. These keys edit the line:
+//! - left and right, or control-b and control-f: move the cursor by a character.
+//! - control-a and control-e: move the cursor to the start and end of the line.
+//! - backspace, and delete or control-d: delete the character before or at the cursor.
+//! - control-k, control-u, and control-w: cut the line after the cursor, the line before the
+//!   cursor, and the word before the cursor.
+//! - control-y: paste the text cut last.
+//! - up and down, or control-p and control-n: replace the line with an older or newer line from the
+//!   history of submitted lines.
+//! - control-r: search the history for older lines containing the text typed next. Control-g
+//!   abandons the search, and any other key keeps the line found.
+//!
+//! Other keys are sent to the ship as they're pressed. Control-c also clears the line. The history
+//! is shared by every session.
+//!
+//! ### Configuration
+//!
+//! The driver is configured by environment variables, which are read when the driver is
+//! initialized:
+//! - `URBIT_IO_DRIVERS_TERM_TTY`: the path of the terminal. Defaults to `/dev/tty`, the controlling
+//!   terminal of the driver's process.
+//! - `URBIT_IO_DRIVERS_TERM_COLOR`: how styled text is drawn, one of `plain` (without styles),
+//!   `none` (with decorations but without colors), `16`, `256`, or `truecolor` (with colors from
+//!   the 16-color palette, the 256-color palette, or any 24-bit color). By default, styles are
+//!   left out if `TERM` is `dumb`, colors are left out if `NO_COLOR` is set to anything, and
+//!   otherwise the colors are chosen from `COLORTERM` and `TERM`.
+//! - `URBIT_IO_DRIVERS_TERM_SOCKET`: the path of the socket that other terminals can attach to the
+//!   driver over. Any file already at the path is replaced, and the socket is removed when the
+//!   driver exits. Unset by default, in which case only the driver's own terminal is attached.
+//! - `URBIT_IO_DRIVERS_TERM_EDIT`: where lines are edited, either `remote` (by the ship) or `local`
+//!   (by the driver, see [Local editing]). Defaults to `remote`.
+//! - `URBIT_IO_DRIVERS_TERM_HISTORY`: the path of the file the history of submitted lines is kept
+//!   in when lines are edited locally, e.g. a file in the pier. The last 1000 lines are kept. Unset
+//!   by default, in which case the history is lost when the driver exits.
+//!
+//! [Sessions]: #sessions
+//! [Local editing]: #local-editing
+//! [Arvo]: https://developers.urbit.org/reference/glossary/arvo
+//! [Dill]: https://developers.urbit.org/reference/arvo/dill/dill
+
+use crate::{atom_as_str, env_var, listen_unix, Driver, Status};
+use log::{debug, error, info, warn};
+use noun::{atom::Atom, cell::Cell, convert, Noun};
+use std::{
+    collections::HashMap,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    mem::{self, MaybeUninit},
+    os::unix::io::{AsRawFd, RawFd},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex, PoisonError},
+};
+use tokio::{
+    io::{unix::AsyncFd, AsyncRead, AsyncReadExt, AsyncWriteExt, Stdin, Stdout},
+    net::{
+        unix::{OwnedReadHalf, OwnedWriteHalf},
+        UnixListener, UnixStream,
+    },
+    runtime,
+    signal::unix::{signal, SignalKind},
+    sync::mpsc::{self, Receiver, Sender},
+    task::JoinHandle,
+};
+
+//==================================================================================================
+// Request Types
+//==================================================================================================
+
+/// Requests that can be handled by the terminal driver.
+enum Request {
+    /// A request to draw on the terminal of a session.
+    Draw(Draw),
+}
+
+impl_try_from_noun_for_request!(Request, "blit" => Draw);
+
+/// A request to draw on the terminal of a session.
+struct Draw {
+    /// The ID of the session.
+    session: u32,
+
+    /// What to draw.
+    blit: Blit,
+}
+
+impl TryFrom<&Noun> for Draw {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// [<session> <blit>]
+    /// ```
+    ///
+    /// where `<session>` is the ID of the session, and `<blit>` is a [`Blit`].
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        if let Noun::Cell(data) = data {
+            if let Noun::Atom(session) = data.head_ref() {
+                Ok(Self {
+                    session: u32_from_atom(session)?,
+                    blit: Blit::try_from(data.tail_ref())?,
+                })
+            } else {
+                Err(convert::Error::UnexpectedCell)
+            }
+        } else {
+            Err(convert::Error::UnexpectedAtom)
+        }
+    }
+}
+
+/// Something to draw on a terminal.
+#[derive(Debug, Eq, PartialEq)]
+enum Blit {
+    /// Ring the terminal's bell.
+    Bel,
+
+    /// Clear the screen.
+    Clr,
+
+    /// Move the cursor to a column of the current line, or of a row if there is one.
+    Hop { col: u32, row: Option<u32> },
+
+    /// Write styled text at the cursor.
+    Klr(Vec<Styled>),
+
+    /// Draw several blits in order.
+    Mor(Vec<Blit>),
+
+    /// Move the cursor to the start of the next line.
+    Nel,
+
+    /// Write text at the cursor.
+    Put(Vec<char>),
+
+    /// Clear the current line.
+    Wyp,
+}
+
+impl Blit {
+    /// Appends the bytes that draw the blit with `styling` to `out`.
+    fn render(&self, styling: Styling, out: &mut Vec<u8>) {
+        match self {
+            Self::Bel => out.push(0x07),
+            Self::Clr => out.extend_from_slice(b"\x1b[H\x1b[2J"),
+            Self::Hop { col, row: None } => {
+                out.extend_from_slice(format!("\x1b[{}G", u64::from(*col) + 1).as_bytes())
+            }
+            Self::Hop {
+                col,
+                row: Some(row),
+            } => out.extend_from_slice(
+                format!("\x1b[{};{}H", u64::from(*row) + 1, u64::from(*col) + 1).as_bytes(),
+            ),
+            Self::Klr(stub) => {
+                for styled in stub {
+                    styled.render(styling, out);
+                }
+            }
+            Self::Mor(blits) => {
+                for blit in blits {
+                    blit.render(styling, out);
+                }
+            }
+            Self::Nel => out.extend_from_slice(b"\r\n"),
+            Self::Put(text) => render_text(text, out),
+            Self::Wyp => out.extend_from_slice(b"\r\x1b[K"),
+        }
+    }
+}
+
+impl TryFrom<&Noun> for Blit {
+    type Error = convert::Error;
+
+    /// A properly structured noun is one of:
+    ///
+    /// ```text
+    /// [%bel ~]
+    /// [%clr ~]
+    /// [%hop <col>]
+    /// [%hop <col> <row>]
+    /// [%klr <stub>]
+    /// [%mor <blits>]
+    /// [%nel ~]
+    /// [%put <text>]
+    /// [%wyp ~]
+    /// ```
+    ///
+    /// where `<stub>` is a null-terminated list of [`Styled`] text, `<blits>` is a null-terminated
+    /// list of blits, and `<text>` is a null-terminated list of UTF-32 characters.
+    fn try_from(data: &Noun) -> Result<Self, Self::Error> {
+        if let Noun::Cell(data) = data {
+            if let Noun::Atom(tag) = data.head_ref() {
+                let null = |blit| match data.tail_ref() {
+                    Noun::Atom(null) if null.is_null() => Ok(blit),
+                    _ => Err(convert::Error::ExpectedNull),
+                };
+                match atom_as_str(tag)? {
+                    "bel" => null(Self::Bel),
+                    "clr" => null(Self::Clr),
+                    "hop" => match data.tail_ref() {
+                        Noun::Atom(col) => Ok(Self::Hop {
+                            col: u32_from_atom(col)?,
+                            row: None,
+                        }),
+                        Noun::Cell(pos) => match (pos.head_ref(), pos.tail_ref()) {
+                            (Noun::Atom(col), Noun::Atom(row)) => Ok(Self::Hop {
+                                col: u32_from_atom(col)?,
+                                row: Some(u32_from_atom(row)?),
+                            }),
+                            _ => Err(convert::Error::UnexpectedCell),
+                        },
+                    },
+                    "klr" => {
+                        let mut stub = Vec::new();
+                        let mut list = data.tail_ref();
+                        loop {
+                            match list {
+                                Noun::Atom(null) if null.is_null() => return Ok(Self::Klr(stub)),
+                                Noun::Atom(_) => return Err(convert::Error::ExpectedNull),
+                                Noun::Cell(cell) => {
+                                    stub.push(Styled::try_from(cell.head_ref())?);
+                                    list = cell.tail_ref();
+                                }
+                            }
+                        }
+                    }
+                    "mor" => {
+                        let mut blits = Vec::new();
+                        let mut list = data.tail_ref();
+                        loop {
+                            match list {
+                                Noun::Atom(null) if null.is_null() => return Ok(Self::Mor(blits)),
+                                Noun::Atom(_) => return Err(convert::Error::ExpectedNull),
+                                Noun::Cell(cell) => {
+                                    blits.push(Self::try_from(cell.head_ref())?);
+                                    list = cell.tail_ref();
+                                }
+                            }
+                        }
+                    }
+                    "nel" => null(Self::Nel),
+                    "put" => Ok(Self::Put(text_from_noun(data.tail_ref())?)),
+                    "wyp" => null(Self::Wyp),
+                    _ => Err(convert::Error::ImplType),
+                }
+            } else {
+                Err(convert::Error::UnexpectedCell)
+            }
+        } else {
+            Err(convert::Error::UnexpectedAtom)
+        }
+    }
+}
+
+//==================================================================================================
+// Driver
+//==================================================================================================
+
+/// Configuration for the terminal driver.
+struct Config {
+    /// The path of the terminal.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_TERM_TTY`.
+    tty: PathBuf,
+
+    /// How styled text is drawn.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_TERM_COLOR`, or detected from `TERM`, `COLORTERM`, and
+    /// `NO_COLOR`.
+    styling: Styling,
+
+    /// The path of the socket other terminals attach sessions over, if any.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_TERM_SOCKET`.
+    socket: Option<PathBuf>,
+
+    /// Where lines are edited.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_TERM_EDIT`.
+    edit: EditMode,
+
+    /// The path of the file submitted lines are kept in, if any.
+    ///
+    /// Read from `URBIT_IO_DRIVERS_TERM_HISTORY`.
+    history: Option<PathBuf>,
+}
+
+impl Config {
+    /// Reads the configuration from the environment.
+    fn from_env() -> Self {
+        Self {
+            tty: env_var("URBIT_IO_DRIVERS_TERM_TTY").unwrap_or_else(|| PathBuf::from("/dev/tty")),
+            styling: env_var("URBIT_IO_DRIVERS_TERM_COLOR").unwrap_or_else(|| {
+                Styling::detect(
+                    env::var("TERM").ok().as_deref(),
+                    env::var("COLORTERM").ok().as_deref(),
+                    env::var_os("NO_COLOR").is_some_and(|val| !val.is_empty()),
+                )
+            }),
+            socket: env_var("URBIT_IO_DRIVERS_TERM_SOCKET"),
+            edit: env_var("URBIT_IO_DRIVERS_TERM_EDIT").unwrap_or(EditMode::Remote),
+            history: env_var("URBIT_IO_DRIVERS_TERM_HISTORY"),
+        }
+    }
+}
+
+/// A terminal in raw mode, which is restored to its previous mode when dropped.
+struct Tty {
+    /// The open terminal.
+    file: File,
+
+    /// The terminal's attributes before it was put in raw mode.
+    attrs: libc::termios,
+}
+
+impl Tty {
+    /// Opens the terminal at `config.tty` and puts it in raw mode.
+    fn open(config: &Config) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&config.tty)?;
+        let fd = file.as_raw_fd();
+        let mut attrs = MaybeUninit::uninit();
+        // SAFETY: `fd` is open, and `tcgetattr()` initializes `attrs` when it succeeds.
+        let attrs = unsafe {
+            if libc::tcgetattr(fd, attrs.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            attrs.assume_init()
+        };
+        let mut raw = attrs;
+        // SAFETY: `raw` is an initialized `termios`, and `fd` is open.
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(fd, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // Reads and writes are driven by Tokio.
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags == -1 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) == -1 {
+                let err = io::Error::last_os_error();
+                libc::tcsetattr(fd, libc::TCSANOW, &attrs);
+                return Err(err);
+            }
+        }
+        Ok(Self { file, attrs })
+    }
+
+    /// Returns the width and height of the terminal in characters.
+    fn size(&self) -> io::Result<(u16, u16)> {
+        // SAFETY: a zeroed `winsize` is valid.
+        let mut size: libc::winsize = unsafe { mem::zeroed() };
+        // SAFETY: `self.file` is open, and `TIOCGWINSZ` fills in a `winsize`.
+        if unsafe { libc::ioctl(self.file.as_raw_fd(), libc::TIOCGWINSZ, &mut size) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((size.ws_col, size.ws_row))
+    }
+}
+
+impl AsRawFd for Tty {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl Drop for Tty {
+    /// Restores the terminal's previous mode.
+    fn drop(&mut self) {
+        // SAFETY: `self.file` is open, and `self.attrs` was filled in by `tcgetattr()`.
+        if unsafe { libc::tcsetattr(self.file.as_raw_fd(), libc::TCSANOW, &self.attrs) } != 0 {
+            warn!(
+                target: Term::name(),
+                "failed to restore terminal mode: {}",
+                io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+/// The terminal driver.
+pub struct Term {
+    /// The terminal, if it could be opened, until the handling task takes it.
+    tty: Option<Tty>,
+
+    /// The driver configuration.
+    config: Config,
+
+    /// The lines submitted on every session, if lines are edited locally.
+    history: Option<Arc<Mutex<History>>>,
+}
+
+impl Term {
+    /// The session of the terminal the driver was started from.
+    const TTY_SESSION: u32 = 0;
+
+    /// Writes `bytes` to the terminal.
+    async fn write(tty: &AsyncFd<Tty>, mut bytes: &[u8]) -> io::Result<()> {
+        while !bytes.is_empty() {
+            let mut guard = tty.writable().await?;
+            match guard.try_io(|tty| (&tty.get_ref().file).write(bytes)) {
+                Ok(Ok(0)) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(Ok(len)) => bytes = &bytes[len..],
+                Ok(Err(err)) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Ok(Err(err)) => return Err(err),
+                Err(_would_block) => continue,
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads from the terminal into `buf`, returning the number of bytes read.
+    async fn read(tty: &AsyncFd<Tty>, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut guard = tty.readable().await?;
+            match guard.try_io(|tty| (&tty.get_ref().file).read(buf)) {
+                Ok(Err(err)) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    /// Handles a [`Draw`] request.
+    async fn draw(&self, sessions: &Sessions, req: Draw) {
+        let mut out = Vec::new();
+        req.blit.render(self.config.styling, &mut out);
+        let session = sessions
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&req.session)
+            .map(|session| (session.output.clone(), session.editor.clone()));
+        match session {
+            Some((output, editor)) => {
+                // The line being edited follows whatever the ship drew.
+                if let Some(editor) = editor {
+                    editor
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .resume(&mut out);
+                }
+                if let Err(_out) = output.send(out).await {
+                    debug!(
+                        target: Self::name(),
+                        "dropped blit to closed session {}", req.session
+                    );
+                }
+            }
+            None => debug!(
+                target: Self::name(),
+                "dropped blit to unknown session {}", req.session
+            ),
+        }
+    }
+
+    /// Starts the session of the terminal the driver was started from.
+    fn open_tty_session(&self, tty: Tty, output_tx: &Sender<Noun>) -> io::Result<Session> {
+        let tty = Arc::new(AsyncFd::new(tty)?);
+        let (blit_tx, blit_rx) = mpsc::channel(Session::QUEUE_SIZE);
+        let keys = Keys::new(
+            Self::TTY_SESSION,
+            self.history.as_ref(),
+            blit_tx.clone(),
+            output_tx.clone(),
+        );
+        Ok(Session {
+            output: blit_tx,
+            editor: keys.editor.clone(),
+            tasks: vec![
+                tokio::spawn(Self::read_keys(tty.clone(), keys)),
+                tokio::spawn(Self::watch_size(
+                    tty.clone(),
+                    output_tx.clone(),
+                    |cols, rows| resized(Self::TTY_SESSION, cols, rows),
+                )),
+                tokio::spawn(Self::write_blits(tty, blit_rx)),
+            ],
+        })
+    }
+
+    /// Writes the drawn blits of the terminal's session to the terminal until the session is
+    /// closed.
+    async fn write_blits(tty: Arc<AsyncFd<Tty>>, mut blit_rx: Receiver<Vec<u8>>) {
+        while let Some(out) = blit_rx.recv().await {
+            if let Err(err) = Self::write(&tty, &out).await {
+                warn!(target: Self::name(), "failed to write to terminal: {}", err);
+            }
+        }
+    }
+
+    /// Handles the keys pressed on the terminal until the terminal closes or the output task stops
+    /// accepting responses.
+    async fn read_keys(tty: Arc<AsyncFd<Tty>>, keys: Keys) {
+        let mut parser = KeyParser::default();
+        let mut buf = [0; 1024];
+        loop {
+            let len = match Self::read(&tty, &mut buf).await {
+                Ok(0) => {
+                    info!(target: Self::name(), "terminal closed");
+                    return;
+                }
+                Ok(len) => len,
+                Err(err) => {
+                    warn!(target: Self::name(), "failed to read from terminal: {}", err);
+                    return;
+                }
+            };
+            for belt in parser.parse(&buf[..len]) {
+                if !keys.press(belt).await {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Sends `msg(cols, rows)` to `tx` when the task starts and whenever the terminal is resized,
+    /// until `tx` stops accepting messages.
+    async fn watch_size<T>(tty: Arc<AsyncFd<Tty>>, tx: Sender<T>, msg: impl Fn(u16, u16) -> T) {
+        let mut winch = match signal(SignalKind::window_change()) {
+            Ok(winch) => Some(winch),
+            Err(err) => {
+                warn!(target: Self::name(), "failed to watch for resizes: {}", err);
+                None
+            }
+        };
+        let mut prev = None;
+        loop {
+            match tty.get_ref().size() {
+                Ok(size) if Some(size) != prev => {
+                    debug!(target: Self::name(), "terminal is {}x{}", size.0, size.1);
+                    prev = Some(size);
+                    if let Err(_msg) = tx.send(msg(size.0, size.1)).await {
+                        warn!(target: Self::name(), "failed to send terminal size");
+                        return;
+                    }
+                }
+                Ok(_size) => {}
+                Err(err) => warn!(target: Self::name(), "failed to get terminal size: {}", err),
+            }
+            match winch.as_mut() {
+                Some(winch) => {
+                    if winch.recv().await.is_none() {
+                        return;
+                    }
+                }
+                None => return,
+            }
+        }
+    }
+}
+
+/// Implements the [`Driver`] trait for the [`Term`] driver.
+macro_rules! impl_driver {
+    ($input_src:ty, $output_sink:ty) => {
+        impl Driver<$input_src, $output_sink> for Term {
+            fn new() -> Result<Self, Status> {
+                let config = Config::from_env();
+                let tty = match Tty::open(&config) {
+                    Ok(tty) => Some(tty),
+                    // Sessions can still attach over the socket.
+                    Err(err) if config.socket.is_some() => {
+                        warn!(
+                            target: Self::name(),
+                            "failed to open terminal {}: {}",
+                            config.tty.display(),
+                            err
+                        );
+                        None
+                    }
+                    Err(err) => {
+                        error!(
+                            target: Self::name(),
+                            "failed to open terminal {}: {}",
+                            config.tty.display(),
+                            err
+                        );
+                        return Err(Status::NoDriver);
+                    }
+                };
+                let history = match config.edit {
+                    EditMode::Local => Some(Arc::new(Mutex::new(History::load(
+                        config.history.clone(),
+                    )))),
+                    EditMode::Remote => None,
+                };
+                debug!(
+                    target: Self::name(),
+                    "initialized driver with {:?} styling and {:?} editing",
+                    config.styling,
+                    config.edit
+                );
+                Ok(Self {
+                    tty,
+                    config,
+                    history,
+                })
+            }
+
+            fn name() -> &'static str {
+                "term"
+            }
+
+            fn handle_requests(
+                mut self,
+                mut input_rx: Receiver<Noun>,
+                output_tx: Sender<Noun>,
+            ) -> JoinHandle<Status> {
+                let task = tokio::spawn(async move {
+                    let sessions = Arc::new(Sessions::default());
+                    if let Some(tty) = self.tty.take() {
+                        match self.open_tty_session(tty, &output_tx) {
+                            Ok(session) => {
+                                sessions
+                                    .lock()
+                                    .unwrap_or_else(PoisonError::into_inner)
+                                    .insert(Self::TTY_SESSION, session);
+                            }
+                            Err(err) => {
+                                error!(
+                                    target: Self::name(),
+                                    "failed to register terminal: {}", err
+                                );
+                                return Status::NoDriver;
+                            }
+                        }
+                    }
+                    let accept_task = match &self.config.socket {
+                        Some(path) => match listen(path) {
+                            Ok(listener) => Some(tokio::spawn(accept_sessions(
+                                listener,
+                                sessions.clone(),
+                                self.history.clone(),
+                                output_tx,
+                            ))),
+                            Err(err) => {
+                                warn!(
+                                    target: Self::name(),
+                                    "failed to listen on {}: {}",
+                                    path.display(),
+                                    err
+                                );
+                                None
+                            }
+                        },
+                        None => None,
+                    };
+                    while let Some(req) = input_rx.recv().await {
+                        match Request::try_from(req) {
+                            Ok(Request::Draw(req)) => self.draw(&sessions, req).await,
+                            _ => {
+                                warn!(target: Self::name(), "skipping unidentifiable request");
+                            }
+                        }
+                    }
+                    if let Some(task) = accept_task {
+                        task.abort();
+                        let _ = task.await;
+                        if let Some(path) = &self.config.socket {
+                            let _ = fs::remove_file(path);
+                        }
+                    }
+                    // Close every session, which restores the terminal's mode.
+                    let sessions = mem::take(
+                        &mut *sessions.lock().unwrap_or_else(PoisonError::into_inner),
+                    );
+                    for (_id, session) in sessions {
+                        session.close().await;
+                    }
+                    Status::Success
+                });
+                debug!(target: Self::name(), "spawned handling task");
+                task
+            }
+        }
+    };
+}
+
+impl_driver!(Stdin, Stdout);
+
+/// Provides an FFI-friendly interface for running the terminal driver with `stdin` as the input
+/// source and `stdout` as the output sink.
+#[no_mangle]
+pub extern "C" fn term_run() -> Status {
+    match Term::new() {
+        Ok(driver) => driver.run(tokio::io::stdin(), tokio::io::stdout()),
+        Err(status) => status,
+    }
+}
+
+//==================================================================================================
+// Sessions
+//==================================================================================================
+
+/// The sessions attached to the driver by ID.
+type Sessions = Mutex<HashMap<u32, Session>>;
+
+/// A terminal attached to the driver.
+struct Session {
+    /// Sends the bytes that draw blits to the session's terminal.
+    output: Sender<Vec<u8>>,
+
+    /// The line being edited on the session's terminal, if lines are edited locally.
+    editor: Option<Arc<Mutex<LineEditor>>>,
+
+    /// The tasks that read keys from and draw blits to the session's terminal.
+    tasks: Vec<JoinHandle<()>>,
+}
+
+impl Session {
+    /// The number of drawn blits that can wait to be written to the session's terminal.
+    const QUEUE_SIZE: usize = 32;
+
+    /// Stops the session's tasks and waits for them to stop.
+    async fn close(mut self) {
+        for task in mem::take(&mut self.tasks) {
+            task.abort();
+            let _ = task.await;
+        }
+    }
+}
+
+impl Drop for Session {
+    /// Stops the session's tasks.
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// A message from the client of a session attached over the driver's socket.
+///
+/// Each message is a one-byte kind, followed by the little-endian two-byte length of the payload,
+/// followed by the payload.
+#[derive(Debug, Eq, PartialEq)]
+enum Frame {
+    /// Keys pressed on the client's terminal, as the bytes read from the terminal.
+    Keys(Vec<u8>),
+
+    /// The width and height of the client's terminal in characters.
+    Size(u16, u16),
+}
+
+impl Frame {
+    /// The kind of a [`Frame::Keys`] message.
+    const KEYS: u8 = 0;
+
+    /// The kind of a [`Frame::Size`] message.
+    const SIZE: u8 = 1;
+
+    /// Reads a message from `src`, returning `None` if it's of an unknown kind.
+    async fn read(src: &mut (impl AsyncRead + Unpin)) -> io::Result<Option<Self>> {
+        let kind = src.read_u8().await?;
+        let mut payload = vec![0; usize::from(src.read_u16_le().await?)];
+        src.read_exact(&mut payload).await?;
+        match (kind, &payload[..]) {
+            (Self::KEYS, _) => Ok(Some(Self::Keys(payload))),
+            (Self::SIZE, [c0, c1, r0, r1]) => Ok(Some(Self::Size(
+                u16::from_le_bytes([*c0, *c1]),
+                u16::from_le_bytes([*r0, *r1]),
+            ))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Encodes the message.
+    fn encode(&self) -> Vec<u8> {
+        let (kind, payload) = match self {
+            // Keys that don't fit are dropped.
+            Self::Keys(keys) => (
+                Self::KEYS,
+                keys[..keys.len().min(usize::from(u16::MAX))].to_vec(),
+            ),
+            Self::Size(cols, rows) => (
+                Self::SIZE,
+                [cols.to_le_bytes(), rows.to_le_bytes()].concat(),
+            ),
+        };
+        let mut frame = vec![kind];
+        frame.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+        frame.extend_from_slice(&payload);
+        frame
+    }
+}
+
+/// Binds a socket at `path` that sessions can attach over, replacing any socket already there.
+fn listen(path: &Path) -> io::Result<UnixListener> {
+    // Anyone who can attach a session can run commands on the ship, so only the driver's user may.
+    let listener = UnixListener::from_std(listen_unix(path)?)?;
+    info!(target: Term::name(), "listening for sessions on {}", path.display());
+    Ok(listener)
+}
+
+/// Attaches a session for each client that connects to `listener`.
+async fn accept_sessions(
+    listener: UnixListener,
+    sessions: Arc<Sessions>,
+    history: Option<Arc<Mutex<History>>>,
+    output_tx: Sender<Noun>,
+) {
+    let mut next_id = Term::TTY_SESSION;
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _addr)) => stream,
+            Err(err) => {
+                warn!(target: Term::name(), "failed to accept session: {}", err);
+                continue;
+            }
+        };
+        next_id = next_id.checked_add(1).unwrap_or(Term::TTY_SESSION + 1);
+        let id = next_id;
+        info!(target: Term::name(), "attached session {}", id);
+        if let Err(_resp) = output_tx.send(attached(id)).await {
+            warn!(target: Term::name(), "failed to send session to output task");
+            return;
+        }
+        let (stream_rx, stream_tx) = stream.into_split();
+        let (blit_tx, blit_rx) = mpsc::channel(Session::QUEUE_SIZE);
+        let keys = Keys::new(id, history.as_ref(), blit_tx.clone(), output_tx.clone());
+        // Hold the lock until the session is added so that it can't be removed first.
+        let mut sessions_guard = sessions.lock().unwrap_or_else(PoisonError::into_inner);
+        let session = Session {
+            output: blit_tx,
+            editor: keys.editor.clone(),
+            tasks: vec![
+                tokio::spawn(read_frames(stream_rx, keys, sessions.clone())),
+                tokio::spawn(write_blits(stream_tx, blit_rx)),
+            ],
+        };
+        sessions_guard.insert(id, session);
+    }
+}
+
+/// Handles the keys and sizes from the client of a session until the client detaches, and then
+/// removes the session.
+async fn read_frames(mut stream_rx: OwnedReadHalf, keys: Keys, sessions: Arc<Sessions>) {
+    let id = keys.session;
+    let mut parser = KeyParser::default();
+    loop {
+        match Frame::read(&mut stream_rx).await {
+            Ok(Some(Frame::Keys(bytes))) => {
+                for belt in parser.parse(&bytes) {
+                    if !keys.press(belt).await {
+                        return;
+                    }
+                }
+            }
+            Ok(Some(Frame::Size(cols, rows))) => {
+                if let Err(_resp) = keys.output_tx.send(resized(id, cols, rows)).await {
+                    warn!(target: Term::name(), "failed to send size to output task");
+                    return;
+                }
+            }
+            Ok(None) => {
+                debug!(target: Term::name(), "skipping unknown message from session {}", id);
+            }
+            Err(err) => {
+                if err.kind() != io::ErrorKind::UnexpectedEof {
+                    warn!(target: Term::name(), "failed to read from session {}: {}", id, err);
+                }
+                break;
+            }
+        }
+    }
+    info!(target: Term::name(), "detached session {}", id);
+    if let Err(_resp) = keys.output_tx.send(detached(id)).await {
+        warn!(target: Term::name(), "failed to send session to output task");
+    }
+    // This aborts the current task, which is finishing anyway.
+    sessions
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&id);
+}
+
+/// Writes the drawn blits of a session to its client until the session is closed.
+async fn write_blits(mut stream_tx: OwnedWriteHalf, mut blit_rx: Receiver<Vec<u8>>) {
+    while let Some(out) = blit_rx.recv().await {
+        if let Err(err) = stream_tx.write_all(&out).await {
+            warn!(target: Term::name(), "failed to write to session: {}", err);
+            return;
+        }
+    }
+}
+
+/// Attaches the terminal to the driver listening on `URBIT_IO_DRIVERS_TERM_SOCKET` until the
+/// driver closes the session or control-] is pressed.
+///
+/// This is the client of a session attached over the driver's socket. It's run in place of the
+/// driver, with the same configuration.
+#[no_mangle]
+pub extern "C" fn term_attach_run() -> Status {
+    /// Control-], which detaches the terminal.
+    const DETACH: u8 = 0x1d;
+
+    let config = Config::from_env();
+    let path = match config.socket.clone() {
+        Some(path) => path,
+        None => {
+            error!(target: Term::name(), "URBIT_IO_DRIVERS_TERM_SOCKET is unset");
+            return Status::NoDriver;
+        }
+    };
+    let tty = match Tty::open(&config) {
+        Ok(tty) => tty,
+        Err(err) => {
+            error!(
+                target: Term::name(),
+                "failed to open terminal {}: {}",
+                config.tty.display(),
+                err
+            );
+            return Status::NoDriver;
+        }
+    };
+    let runtime = match runtime::Builder::new_multi_thread().enable_all().build() {
+        Ok(runtime) => runtime,
+        Err(err) => {
+            error!(target: Term::name(), "could not create Tokio runtime: {}", err);
+            return Status::NoRuntime;
+        }
+    };
+    runtime.block_on(async move {
+        let stream = match UnixStream::connect(&path).await {
+            Ok(stream) => stream,
+            Err(err) => {
+                error!(target: Term::name(), "failed to attach to {}: {}", path.display(), err);
+                return Status::NoDriver;
+            }
+        };
+        let tty = match AsyncFd::new(tty) {
+            Ok(tty) => Arc::new(tty),
+            Err(err) => {
+                error!(target: Term::name(), "failed to register terminal: {}", err);
+                return Status::NoDriver;
+            }
+        };
+        let (mut stream_rx, mut stream_tx) = stream.into_split();
+        let (frame_tx, mut frame_rx) = mpsc::channel::<Frame>(Session::QUEUE_SIZE);
+        // Each task sends to `done_tx` when it finishes, after which the others are stopped.
+        let (done_tx, mut done_rx) = mpsc::channel::<()>(1);
+        let tasks = [
+            tokio::spawn(Term::watch_size(tty.clone(), frame_tx.clone(), Frame::Size)),
+            tokio::spawn({
+                let tty = tty.clone();
+                let done_tx = done_tx.clone();
+                async move {
+                    let mut buf = [0; 1024];
+                    while let Ok(len @ 1..) = Term::read(&tty, &mut buf).await {
+                        let keys = &buf[..len];
+                        let detach = keys.iter().position(|key| *key == DETACH);
+                        let keys = &keys[..detach.unwrap_or(len)];
+                        if !keys.is_empty()
+                            && frame_tx.send(Frame::Keys(keys.to_vec())).await.is_err()
+                        {
+                            break;
+                        }
+                        if detach.is_some() {
+                            break;
+                        }
+                    }
+                    let _ = done_tx.send(()).await;
+                }
+            }),
+            tokio::spawn({
+                let done_tx = done_tx.clone();
+                async move {
+                    while let Some(frame) = frame_rx.recv().await {
+                        if stream_tx.write_all(&frame.encode()).await.is_err() {
+                            break;
+                        }
+                    }
+                    let _ = done_tx.send(()).await;
+                }
+            }),
+            tokio::spawn({
+                let tty = tty.clone();
+                async move {
+                    let mut buf = [0; 4096];
+                    while let Ok(len @ 1..) = stream_rx.read(&mut buf).await {
+                        if Term::write(&tty, &buf[..len]).await.is_err() {
+                            break;
+                        }
+                    }
+                    let _ = done_tx.send(()).await;
+                }
+            }),
+        ];
+        let _ = done_rx.recv().await;
+        for task in tasks {
+            task.abort();
+            let _ = task.await;
+        }
+        // Restore the terminal's mode.
+        drop(tty);
+        Status::Success
+    })
+}
+
+//==================================================================================================
+// Keys
+//==================================================================================================
+
+/// A key pressed on the terminal.
+#[derive(Debug, Eq, PartialEq)]
+enum Belt {
+    /// An arrow key.
+    Aro(Arrow),
+
+    /// Backspace.
+    Bac,
+
+    /// Delete.
+    Del,
+
+    /// A function key, from 1 to 12.
+    Fun(u8),
+
+    /// A key pressed with a modifier held.
+    Mod(Modifier, char),
+
+    /// Return.
+    Ret,
+
+    /// Typed characters.
+    Txt(Vec<char>),
+}
+
+impl From<Belt> for Noun {
+    fn from(belt: Belt) -> Self {
+        let tagged = |tag, data| Noun::from(Cell::from([Noun::from(Atom::from(tag)), data]));
+        match belt {
+            Belt::Aro(arrow) => tagged("aro", Noun::from(Atom::from(arrow.as_str()))),
+            Belt::Bac => tagged("bac", Noun::null()),
+            Belt::Del => tagged("del", Noun::null()),
+            Belt::Fun(n) => tagged("fun", Noun::from(Atom::from(u64::from(n)))),
+            Belt::Mod(modifier, key) => tagged(
+                "mod",
+                Noun::from(Cell::from([
+                    Atom::from(modifier.as_str()),
+                    Atom::from(u64::from(key)),
+                ])),
+            ),
+            Belt::Ret => tagged("ret", Noun::null()),
+            Belt::Txt(text) => tagged("txt", text_to_noun(&text)),
+        }
+    }
+}
+
+/// The direction of an arrow key.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Arrow {
+    Down,
+    Left,
+    Right,
+    Up,
+}
+
+impl Arrow {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Down => "d",
+            Self::Left => "l",
+            Self::Right => "r",
+            Self::Up => "u",
+        }
+    }
+}
+
+/// A modifier key.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Modifier {
+    /// Control.
+    Ctl,
+
+    /// Alt, or meta.
+    Met,
+}
+
+impl Modifier {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ctl => "ctl",
+            Self::Met => "met",
+        }
+    }
+}
+
+/// A key parsed from the start of the bytes read from the terminal.
+enum Key {
+    /// A typed character.
+    Char(char),
+
+    /// Any other key.
+    Belt(Belt),
+
+    /// An escape sequence or byte that isn't understood.
+    Unknown,
+}
+
+/// Parses the bytes read from the terminal into keys.
+#[derive(Default)]
+struct KeyParser {
+    /// Bytes read from the terminal that don't yet make up a whole key.
+    pending: Vec<u8>,
+}
+
+impl KeyParser {
+    /// The length at which an unfinished escape sequence is given up on.
+    const MAX_ESCAPE_LEN: usize = 16;
+
+    /// Parses the keys in `bytes`, keeping the bytes of an unfinished key until the next call.
+    fn parse(&mut self, bytes: &[u8]) -> Vec<Belt> {
+        self.pending.extend_from_slice(bytes);
+        let mut belts = Vec::new();
+        let mut text = Vec::new();
+        let mut start = 0;
+        while let Some((key, len)) = Self::parse_key(&self.pending[start..]) {
+            start += len;
+            match key {
+                Key::Char(c) => text.push(c),
+                Key::Belt(belt) => {
+                    if !text.is_empty() {
+                        belts.push(Belt::Txt(mem::take(&mut text)));
+                    }
+                    belts.push(belt);
+                }
+                Key::Unknown => {}
+            }
+        }
+        if !text.is_empty() {
+            belts.push(Belt::Txt(text));
+        }
+        self.pending.drain(..start);
+        belts
+    }
+
+    /// Parses the key at the start of `bytes`, returning the key and its length in bytes, or
+    /// `None` if `bytes` ends before the key does.
+    fn parse_key(bytes: &[u8]) -> Option<(Key, usize)> {
+        let key = match *bytes.first()? {
+            0x1b => return Self::parse_escape(bytes),
+            0x7f | 0x08 => Key::Belt(Belt::Bac),
+            b'\r' | b'\n' => Key::Belt(Belt::Ret),
+            byte @ 0x01..=0x1a => Key::Belt(Belt::Mod(Modifier::Ctl, char::from(byte + 0x60))),
+            byte @ 0x00..=0x1f => Key::Belt(Belt::Mod(Modifier::Ctl, char::from(byte + 0x40))),
+            _ => {
+                return match Self::parse_char(bytes)? {
+                    (Some(c), len) => Some((Key::Char(c), len)),
+                    (None, len) => Some((Key::Unknown, len)),
+                }
+            }
+        };
+        Some((key, 1))
+    }
+
+    /// Parses the UTF-8 character at the start of `bytes`, returning the character, or `None` if
+    /// it isn't valid UTF-8, and its length in bytes, or `None` if `bytes` ends before the
+    /// character does.
+    fn parse_char(bytes: &[u8]) -> Option<(Option<char>, usize)> {
+        let len = match bytes.first()? {
+            0x00..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => return Some((None, 1)),
+        };
+        let bytes = bytes.get(..len)?;
+        match std::str::from_utf8(bytes) {
+            Ok(s) => Some((s.chars().next(), len)),
+            Err(_err) => Some((None, 1)),
+        }
+    }
+
+    /// Parses the escape sequence at the start of `bytes`, which starts with an escape byte.
+    fn parse_escape(bytes: &[u8]) -> Option<(Key, usize)> {
+        match *bytes.get(1)? {
+            prefix @ (b'[' | b'O') => {
+                let end = match bytes[2..].iter().position(|b| (0x40..=0x7e).contains(b)) {
+                    Some(end) => 2 + end,
+                    None if bytes.len() >= Self::MAX_ESCAPE_LEN => {
+                        return Some((Key::Unknown, bytes.len()))
+                    }
+                    None => return None,
+                };
+                // Parameters after the first, like those of modifiers, are ignored.
+                let param = bytes[2..end]
+                    .split(|b| *b == b';')
+                    .next()
+                    .and_then(|param| std::str::from_utf8(param).ok())
+                    .and_then(|param| param.parse::<u8>().ok());
+                let belt = match (prefix, bytes[end], param) {
+                    (_, b'A', _) => Some(Belt::Aro(Arrow::Up)),
+                    (_, b'B', _) => Some(Belt::Aro(Arrow::Down)),
+                    (_, b'C', _) => Some(Belt::Aro(Arrow::Right)),
+                    (_, b'D', _) => Some(Belt::Aro(Arrow::Left)),
+                    (b'O', final_byte @ b'P'..=b'S', _) => Some(Belt::Fun(final_byte - b'O')),
+                    (b'[', b'~', Some(3)) => Some(Belt::Del),
+                    (b'[', b'~', Some(n @ 11..=15)) => Some(Belt::Fun(n - 10)),
+                    (b'[', b'~', Some(n @ 17..=21)) => Some(Belt::Fun(n - 11)),
+                    (b'[', b'~', Some(n @ 23..=24)) => Some(Belt::Fun(n - 12)),
+                    _ => None,
+                };
+                Some((belt.map_or(Key::Unknown, Key::Belt), end + 1))
+            }
+            // Alt sends an escape byte before the key.
+            _ => match Self::parse_char(&bytes[1..])? {
+                (Some(c), len) => Some((Key::Belt(Belt::Mod(Modifier::Met, c)), 1 + len)),
+                (None, len) => Some((Key::Unknown, 1 + len)),
+            },
+        }
+    }
+}
+
+//==================================================================================================
+// Line Editing
+//==================================================================================================
+
+/// Where the lines typed on a session's terminal are edited.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum EditMode {
+    /// By the ship, which is sent every key as it's pressed.
+    Remote,
+
+    /// By the driver, which sends the ship each line when return is pressed.
+    Local,
+}
+
+impl FromStr for EditMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "remote" => Ok(Self::Remote),
+            "local" => Ok(Self::Local),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Handles the keys pressed on a session's terminal.
+struct Keys {
+    /// The ID of the session.
+    session: u32,
+
+    /// The line being edited on the session's terminal, if lines are edited locally.
+    editor: Option<Arc<Mutex<LineEditor>>>,
+
+    /// Sends the bytes that draw the line being edited to the session's terminal.
+    blit_tx: Sender<Vec<u8>>,
+
+    /// Sends responses to the output task.
+    output_tx: Sender<Noun>,
+}
+
+impl Keys {
+    /// Handles the keys pressed on the terminal of session `session`, editing lines locally if
+    /// there's a `history` to keep them in.
+    fn new(
+        session: u32,
+        history: Option<&Arc<Mutex<History>>>,
+        blit_tx: Sender<Vec<u8>>,
+        output_tx: Sender<Noun>,
+    ) -> Self {
+        Self {
+            session,
+            editor: history.map(|history| Arc::new(Mutex::new(LineEditor::new(history.clone())))),
+            blit_tx,
+            output_tx,
+        }
+    }
+
+    /// Edits the line being edited with `belt` if lines are edited locally, and sends the keys
+    /// meant for the ship to the output task, returning `false` if the output task stopped
+    /// accepting responses.
+    async fn press(&self, belt: Belt) -> bool {
+        debug!(target: Term::name(), "session {} pressed {:?}", self.session, belt);
+        let (belts, out) = match &self.editor {
+            Some(editor) => editor
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .press(belt),
+            None => (vec![belt], Vec::new()),
+        };
+        if !out.is_empty() {
+            if let Err(_out) = self.blit_tx.send(out).await {
+                debug!(target: Term::name(), "dropped line of closed session {}", self.session);
+            }
+        }
+        for belt in belts {
+            if let Err(_resp) = self.output_tx.send(pressed(self.session, belt)).await {
+                warn!(target: Term::name(), "failed to send key to output task");
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// The lines submitted in local edit mode, from oldest to newest.
+struct History {
+    /// The lines.
+    lines: Vec<Vec<char>>,
+
+    /// The file the lines are kept in, if any.
+    file: Option<PathBuf>,
+}
+
+impl History {
+    /// The number of lines kept.
+    const MAX_LEN: usize = 1000;
+
+    /// Loads the lines kept in `file`, if any.
+    fn load(file: Option<PathBuf>) -> Self {
+        let mut lines: Vec<Vec<char>> = match &file {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(text) => text.lines().map(|line| line.chars().collect()).collect(),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+                Err(err) => {
+                    warn!(
+                        target: Term::name(),
+                        "failed to read history from {}: {}",
+                        path.display(),
+                        err
+                    );
+                    Vec::new()
+                }
+            },
+            None => Vec::new(),
+        };
+        if lines.len() > Self::MAX_LEN {
+            lines.drain(..lines.len() - Self::MAX_LEN);
+            // Keep the file from growing without bound.
+            if let Some(path) = &file {
+                let mut text = String::new();
+                for line in &lines {
+                    text.extend(line);
+                    text.push('\n');
+                }
+                if let Err(err) = fs::write(path, text) {
+                    warn!(
+                        target: Term::name(),
+                        "failed to trim history in {}: {}",
+                        path.display(),
+                        err
+                    );
+                }
+            }
+        }
+        Self { lines, file }
+    }
+
+    /// Adds a submitted line to the history unless it's empty or repeats the newest line.
+    fn push(&mut self, line: &[char]) {
+        if line.is_empty() || self.lines.last().map(Vec::as_slice) == Some(line) {
+            return;
+        }
+        self.lines.push(line.to_vec());
+        if self.lines.len() > Self::MAX_LEN {
+            self.lines.remove(0);
+        }
+        if let Some(path) = &self.file {
+            let mut text: String = line.iter().collect();
+            text.push('\n');
+            let result = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(text.as_bytes()));
+            if let Err(err) = result {
+                warn!(
+                    target: Term::name(),
+                    "failed to save history to {}: {}",
+                    path.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    /// Returns the index of the newest line before the line at index `before`, or before the end
+    /// if there's no `before`, that contains `query`.
+    fn find(&self, query: &[char], before: Option<usize>) -> Option<usize> {
+        if query.is_empty() {
+            return None;
+        }
+        let end = before.unwrap_or(self.lines.len());
+        (0..end).rev().find(|index| {
+            self.lines[*index]
+                .windows(query.len())
+                .any(|chars| chars == query)
+        })
+    }
+}
+
+/// A search backwards through the history.
+struct Search {
+    /// The text being searched for.
+    query: Vec<char>,
+
+    /// The index in the history of the line found, if any.
+    found: Option<usize>,
+}
+
+/// The line being edited on a session's terminal in local edit mode.
+///
+/// The line is drawn from the terminal's saved cursor position, which is saved when the line is
+/// first drawn after the ship draws on the terminal.
+struct LineEditor {
+    /// The line.
+    line: Vec<char>,
+
+    /// The position of the cursor in the line.
+    cursor: usize,
+
+    /// Whether the start of the line is the terminal's saved cursor position.
+    saved: bool,
+
+    /// The text cut last.
+    killed: Vec<char>,
+
+    /// The index in the history of the line being shown, and the line that was being edited before
+    /// the history was browsed.
+    browsing: Option<(usize, Vec<char>)>,
+
+    /// The search through the history in progress, if any.
+    search: Option<Search>,
+
+    /// The lines submitted on every session.
+    history: Arc<Mutex<History>>,
+}
+
+impl LineEditor {
+    /// The prompt of a search through the history.
+    const SEARCH_PROMPT: &'static str = "(reverse-i-search)'";
+
+    /// Edits an empty line, keeping submitted lines in `history`.
+    fn new(history: Arc<Mutex<History>>) -> Self {
+        Self {
+            line: Vec::new(),
+            cursor: 0,
+            saved: false,
+            killed: Vec::new(),
+            browsing: None,
+            search: None,
+            history,
+        }
+    }
+
+    /// Edits the line with `belt`, returning the keys to send to the ship and the bytes that
+    /// redraw the line.
+    fn press(&mut self, belt: Belt) -> (Vec<Belt>, Vec<u8>) {
+        if self.search.is_some() {
+            return self.press_in_search(belt);
+        }
+        match belt {
+            Belt::Txt(text) => {
+                self.line
+                    .splice(self.cursor..self.cursor, text.iter().copied());
+                self.cursor += text.len();
+            }
+            Belt::Bac => {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.line.remove(self.cursor);
+                }
+            }
+            Belt::Del | Belt::Mod(Modifier::Ctl, 'd') if self.cursor < self.line.len() => {
+                self.line.remove(self.cursor);
+            }
+            // Control-d at the end of the line is the ship's to handle, e.g. to exit.
+            Belt::Del => {}
+            Belt::Aro(Arrow::Left) | Belt::Mod(Modifier::Ctl, 'b') => {
+                self.cursor = self.cursor.saturating_sub(1);
+            }
+            Belt::Aro(Arrow::Right) | Belt::Mod(Modifier::Ctl, 'f') => {
+                self.cursor = (self.cursor + 1).min(self.line.len());
+            }
+            Belt::Mod(Modifier::Ctl, 'a') => self.cursor = 0,
+            Belt::Mod(Modifier::Ctl, 'e') => self.cursor = self.line.len(),
+            Belt::Aro(Arrow::Up) | Belt::Mod(Modifier::Ctl, 'p') => self.browse(true),
+            Belt::Aro(Arrow::Down) | Belt::Mod(Modifier::Ctl, 'n') => self.browse(false),
+            Belt::Mod(Modifier::Ctl, 'k') => self.killed = self.line.split_off(self.cursor),
+            Belt::Mod(Modifier::Ctl, 'u') => {
+                self.killed = self.line.drain(..self.cursor).collect();
+                self.cursor = 0;
+            }
+            Belt::Mod(Modifier::Ctl, 'w') => {
+                let mut start = self.cursor;
+                while start > 0 && self.line[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                while start > 0 && !self.line[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                self.killed = self.line.drain(start..self.cursor).collect();
+                self.cursor = start;
+            }
+            Belt::Mod(Modifier::Ctl, 'y') => {
+                self.line
+                    .splice(self.cursor..self.cursor, self.killed.iter().copied());
+                self.cursor += self.killed.len();
+            }
+            Belt::Mod(Modifier::Ctl, 'r') => {
+                self.search = Some(Search {
+                    query: Vec::new(),
+                    found: None,
+                });
+            }
+            Belt::Ret => {
+                let line = mem::take(&mut self.line);
+                self.cursor = 0;
+                self.browsing = None;
+                // The ship draws on the terminal when it handles the line, and the next line is
+                // drawn after that.
+                self.saved = false;
+                self.history
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(&line);
+                let mut belts = Vec::new();
+                if !line.is_empty() {
+                    belts.push(Belt::Txt(line));
+                }
+                belts.push(Belt::Ret);
+                return (belts, Vec::new());
+            }
+            belt => {
+                if belt == Belt::Mod(Modifier::Ctl, 'c') {
+                    self.line.clear();
+                    self.cursor = 0;
+                    self.browsing = None;
+                }
+                let mut out = Vec::new();
+                if self.saved {
+                    self.redraw(&mut out);
+                }
+                return (vec![belt], out);
+            }
+        }
+        let mut out = Vec::new();
+        self.redraw(&mut out);
+        (Vec::new(), out)
+    }
+
+    /// Edits the search through the history in progress with `belt`, returning the keys to send to
+    /// the ship and the bytes that redraw the line.
+    fn press_in_search(&mut self, belt: Belt) -> (Vec<Belt>, Vec<u8>) {
+        let search = match &mut self.search {
+            Some(search) => search,
+            None => return (Vec::new(), Vec::new()),
+        };
+        let history = self.history.lock().unwrap_or_else(PoisonError::into_inner);
+        match belt {
+            Belt::Txt(text) => {
+                search.query.extend(text);
+                search.found = history.find(&search.query, None);
+            }
+            Belt::Bac => {
+                search.query.pop();
+                search.found = history.find(&search.query, None);
+            }
+            // Search for an older match, keeping the current one if there isn't one.
+            Belt::Mod(Modifier::Ctl, 'r') => {
+                if let Some(found) = history.find(&search.query, search.found) {
+                    search.found = Some(found);
+                }
+            }
+            Belt::Mod(Modifier::Ctl, 'g') => self.search = None,
+            // Any other key keeps the line found and is then handled as usual.
+            belt => {
+                if let Some(found) = search.found {
+                    self.line = history.lines[found].clone();
+                    self.cursor = self.line.len();
+                    self.browsing = None;
+                }
+                drop(history);
+                self.search = None;
+                return self.press(belt);
+            }
+        }
+        drop(history);
+        let mut out = Vec::new();
+        self.redraw(&mut out);
+        (Vec::new(), out)
+    }
+
+    /// Replaces the line with the line before (if `older`) or after the line being shown from the
+    /// history, or, after the newest line, with the line that was being edited.
+    fn browse(&mut self, older: bool) {
+        let history = self.history.lock().unwrap_or_else(PoisonError::into_inner);
+        let next = match (self.browsing.as_ref().map(|(index, _)| *index), older) {
+            (None, true) => history.lines.len().checked_sub(1),
+            (None, false) => None,
+            (Some(index), true) => index.checked_sub(1),
+            (Some(index), false) => Some(index + 1),
+        };
+        match next {
+            Some(index) if index < history.lines.len() => {
+                let editing = match self.browsing.take() {
+                    Some((_, editing)) => editing,
+                    None => mem::take(&mut self.line),
+                };
+                self.line = history.lines[index].clone();
+                self.browsing = Some((index, editing));
+            }
+            Some(_) => {
+                if let Some((_, editing)) = self.browsing.take() {
+                    self.line = editing;
+                }
+            }
+            None => {}
+        }
+        self.cursor = self.line.len();
+    }
+
+    /// Appends the bytes that draw the line, or the search through the history in progress, from
+    /// its start to `out`.
+    fn redraw(&mut self, out: &mut Vec<u8>) {
+        if self.saved {
+            out.extend_from_slice(b"\x1b8");
+        } else {
+            out.extend_from_slice(b"\x1b7");
+            self.saved = true;
+        }
+        let (text, cursor) = match &self.search {
+            Some(search) => {
+                let mut text: Vec<char> = Self::SEARCH_PROMPT.chars().collect();
+                text.extend(&search.query);
+                text.extend("': ".chars());
+                if let Some(found) = search.found {
+                    let history = self.history.lock().unwrap_or_else(PoisonError::into_inner);
+                    text.extend(&history.lines[found]);
+                }
+                let cursor = text.len();
+                (text, cursor)
+            }
+            None => (self.line.clone(), self.cursor),
+        };
+        render_text(&text, out);
+        // Clear what's left of the previous line, and then move the cursor into place.
+        out.extend_from_slice(b"\x1b[K\x1b8");
+        if cursor > 0 {
+            out.extend_from_slice(format!("\x1b[{}C", cursor).as_bytes());
+        }
+    }
+
+    /// Appends the bytes that draw the line after what the ship just drew to `out`.
+    fn resume(&mut self, out: &mut Vec<u8>) {
+        self.saved = false;
+        if !self.line.is_empty() || self.search.is_some() {
+            self.redraw(out);
+        }
+    }
+}
+
+//==================================================================================================
+// Styles
+//==================================================================================================
+
+/// Styled text.
+#[derive(Debug, Eq, PartialEq)]
+struct Styled {
+    /// The style of the text.
+    style: Style,
+
+    /// The text.
+    text: Vec<char>,
+}
+
+impl Styled {
+    /// Appends the bytes that draw the text with `styling` to `out`.
+    fn render(&self, styling: Styling, out: &mut Vec<u8>) {
+        let params = self.style.sgr_params(styling);
+        if params.is_empty() {
+            render_text(&self.text, out);
+        } else {
+            out.extend_from_slice(format!("\x1b[{}m", params.join(";")).as_bytes());
+            render_text(&self.text, out);
+            out.extend_from_slice(b"\x1b[0m");
+        }
+    }
+}
+
+impl TryFrom<&Noun> for Styled {
+    type Error = convert::Error;
+
+    /// A properly structured noun is:
+    ///
+    /// ```text
+    /// [[<decorations> <background> <foreground>] <text>]
+    /// ```
+    ///
+    /// where `<decorations>` is a set of decorations, `<background>` and `<foreground>` are
+    /// [`Tint`]s, and `<text>` is a null-terminated list of UTF-32 characters.
+    fn try_from(styled: &Noun) -> Result<Self, Self::Error> {
+        if let Noun::Cell(styled) = styled {
+            match styled.head_ref() {
+                Noun::Cell(style) => match style.tail_ref() {
+                    Noun::Cell(tints) => {
+                        let decorations = style.head_ref();
+                        let mut style = Style {
+                            background: Tint::try_from(tints.head_ref())?,
+                            foreground: Tint::try_from(tints.tail_ref())?,
+                            ..Style::default()
+                        };
+                        style.add_decorations(decorations)?;
+                        Ok(Self {
+                            style,
+                            text: text_from_noun(styled.tail_ref())?,
+                        })
+                    }
+                    Noun::Atom(_) => Err(convert::Error::UnexpectedAtom),
+                },
+                Noun::Atom(_) => Err(convert::Error::UnexpectedAtom),
+            }
+        } else {
+            Err(convert::Error::UnexpectedAtom)
+        }
+    }
+}
+
+/// The style of text.
+#[derive(Debug, Default, Eq, PartialEq)]
+struct Style {
+    /// Whether the text blinks.
+    blink: bool,
+
+    /// Whether the text is bold.
+    bold: bool,
+
+    /// Whether the text is underlined.
+    underline: bool,
+
+    /// The background color.
+    background: Tint,
+
+    /// The foreground color.
+    foreground: Tint,
+}
+
+impl Style {
+    /// Adds the decorations of `set`, a set of `%bl`, `%br`, and `%un`, to the style.
+    fn add_decorations(&mut self, set: &Noun) -> Result<(), convert::Error> {
+        match set {
+            Noun::Atom(null) if null.is_null() => Ok(()),
+            Noun::Atom(_) => Err(convert::Error::ExpectedNull),
+            Noun::Cell(node) => {
+                if let Noun::Cell(children) = node.tail_ref() {
+                    match node.head_ref() {
+                        // Hoon's `~` decoration means no decoration.
+                        Noun::Atom(deco) if deco.is_null() => {}
+                        Noun::Atom(deco) => match atom_as_str(deco)? {
+                            "bl" => self.blink = true,
+                            "br" => self.bold = true,
+                            "un" => self.underline = true,
+                            _ => return Err(convert::Error::ImplType),
+                        },
+                        Noun::Cell(_) => return Err(convert::Error::UnexpectedCell),
+                    }
+                    self.add_decorations(children.head_ref())?;
+                    self.add_decorations(children.tail_ref())
+                } else {
+                    Err(convert::Error::UnexpectedAtom)
+                }
+            }
+        }
+    }
+
+    /// Returns the parameters of the Select Graphic Rendition escape sequence that draws the style
+    /// with `styling`.
+    fn sgr_params(&self, styling: Styling) -> Vec<String> {
+        let mut params = Vec::new();
+        if styling == Styling::Plain {
+            return params;
+        }
+        for (on, param) in [(self.bold, "1"), (self.underline, "4"), (self.blink, "5")] {
+            if on {
+                params.push(String::from(param));
+            }
+        }
+        params.extend(self.foreground.sgr_param(30, styling));
+        params.extend(self.background.sgr_param(40, styling));
+        params
+    }
+}
+
+/// A color.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+enum Tint {
+    /// The terminal's default color.
+    #[default]
+    Default,
+
+    /// One of the eight basic colors, in the order of the terminal's palette: black, red, green,
+    /// yellow, blue, magenta, cyan, and white.
+    Basic(u8),
+
+    /// A 24-bit color.
+    Rgb(u8, u8, u8),
+}
+
+impl Tint {
+    /// Returns the Select Graphic Rendition parameter that sets the color with `styling`, where
+    /// `base` is 30 for the foreground or 40 for the background.
+    fn sgr_param(self, base: u8, styling: Styling) -> Option<String> {
+        match (self, styling) {
+            (Self::Default, _) | (_, Styling::Plain | Styling::Decorations) => None,
+            (Self::Basic(color), _) => Some(format!("{}", base + color)),
+            (Self::Rgb(r, g, b), Styling::Basic) => {
+                let color = u8::from(r >= 128) | u8::from(g >= 128) << 1 | u8::from(b >= 128) << 2;
+                Some(format!("{}", base + color))
+            }
+            (Self::Rgb(r, g, b), Styling::Indexed) => {
+                // The 6x6x6 color cube of the 256-color palette.
+                let level = |c: u8| (u16::from(c) * 5 + 127) / 255;
+                let color = 16 + 36 * level(r) + 6 * level(g) + level(b);
+                Some(format!("{};5;{}", base + 8, color))
+            }
+            (Self::Rgb(r, g, b), Styling::TrueColor) => {
+                Some(format!("{};2;{};{};{}", base + 8, r, g, b))
+            }
+        }
+    }
+}
+
+impl TryFrom<&Noun> for Tint {
+    type Error = convert::Error;
+
+    /// A properly structured noun is one of:
+    ///
+    /// ```text
+    /// ~
+    /// %k, %r, %g, %y, %b, %m, %c, or %w
+    /// [<r> <g> <b>]
+    /// ```
+    fn try_from(tint: &Noun) -> Result<Self, Self::Error> {
+        match tint {
+            Noun::Atom(tint) if tint.is_null() => Ok(Self::Default),
+            Noun::Atom(tint) => match atom_as_str(tint)? {
+                "k" => Ok(Self::Basic(0)),
+                "r" => Ok(Self::Basic(1)),
+                "g" => Ok(Self::Basic(2)),
+                "y" => Ok(Self::Basic(3)),
+                "b" => Ok(Self::Basic(4)),
+                "m" => Ok(Self::Basic(5)),
+                "c" => Ok(Self::Basic(6)),
+                "w" => Ok(Self::Basic(7)),
+                _ => Err(convert::Error::ImplType),
+            },
+            Noun::Cell(rgb) => {
+                let channel = |noun: &Noun| match noun {
+                    Noun::Atom(channel) => channel
+                        .as_u64()
+                        .and_then(|channel| u8::try_from(channel).ok())
+                        .ok_or(convert::Error::AtomToUint),
+                    Noun::Cell(_) => Err(convert::Error::UnexpectedCell),
+                };
+                if let Noun::Cell(gb) = rgb.tail_ref() {
+                    Ok(Self::Rgb(
+                        channel(rgb.head_ref())?,
+                        channel(gb.head_ref())?,
+                        channel(gb.tail_ref())?,
+                    ))
+                } else {
+                    Err(convert::Error::UnexpectedAtom)
+                }
+            }
+        }
+    }
+}
+
+/// How much of the style of styled text is drawn, from least to most.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Styling {
+    /// Nothing, for terminals that don't understand escape sequences.
+    Plain,
+
+    /// Decorations, but not colors.
+    Decorations,
+
+    /// Decorations and the colors of the 16-color palette.
+    Basic,
+
+    /// Decorations and the colors of the 256-color palette.
+    Indexed,
+
+    /// Decorations and any 24-bit color.
+    TrueColor,
+}
+
+impl Styling {
+    /// Chooses the styling of a terminal from the values of `TERM` and `COLORTERM`, and whether
+    /// `NO_COLOR` is set.
+    fn detect(term: Option<&str>, colorterm: Option<&str>, no_color: bool) -> Self {
+        match (term, colorterm) {
+            (Some("dumb"), _) => Self::Plain,
+            _ if no_color => Self::Decorations,
+            (_, Some("truecolor" | "24bit")) => Self::TrueColor,
+            (Some(term), _) if term.contains("256color") => Self::Indexed,
+            _ => Self::Basic,
+        }
+    }
+}
+
+impl FromStr for Styling {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "plain" => Ok(Self::Plain),
+            "none" => Ok(Self::Decorations),
+            "16" => Ok(Self::Basic),
+            "256" => Ok(Self::Indexed),
+            "truecolor" => Ok(Self::TrueColor),
+            _ => Err(()),
+        }
+    }
+}
+
+//==================================================================================================
+// Miscellaneous
+//==================================================================================================
+
+/// Appends the UTF-8 encoding of `text` to `out`.
+fn render_text(text: &[char], out: &mut Vec<u8>) {
+    let mut buf = [0; 4];
+    for c in text {
+        out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+    }
+}
+
+/// Converts an atom into a `u32`.
+fn u32_from_atom(atom: &Atom) -> Result<u32, convert::Error> {
+    atom.as_u64()
+        .and_then(|val| u32::try_from(val).ok())
+        .ok_or(convert::Error::AtomToUint)
+}
+
+/// Returns the response reporting that `belt` was pressed on the terminal of session `session`.
+fn pressed(session: u32, belt: Belt) -> Noun {
+    Noun::from(Cell::from([
+        Noun::from(Atom::from("belt")),
+        Noun::from(Atom::from(u64::from(session))),
+        Noun::from(belt),
+    ]))
+}
+
+/// Returns the response reporting the size of the terminal of session `session`.
+fn resized(session: u32, cols: u16, rows: u16) -> Noun {
+    Noun::from(Cell::from([
+        Atom::from("blew"),
+        Atom::from(u64::from(session)),
+        Atom::from(u64::from(cols)),
+        Atom::from(u64::from(rows)),
+    ]))
+}
+
+/// Returns the response reporting that session `session` attached.
+fn attached(session: u32) -> Noun {
+    Noun::from(Cell::from([
+        Atom::from("open"),
+        Atom::from(u64::from(session)),
+    ]))
+}
+
+/// Returns the response reporting that session `session` detached.
+fn detached(session: u32) -> Noun {
+    Noun::from(Cell::from([
+        Atom::from("shut"),
+        Atom::from(u64::from(session)),
+    ]))
+}
+
+/// Converts a null-terminated list of UTF-32 characters into the characters.
+fn text_from_noun(noun: &Noun) -> Result<Vec<char>, convert::Error> {
+    let mut text = Vec::new();
+    let mut list = noun;
+    loop {
+        match list {
+            Noun::Atom(null) if null.is_null() => return Ok(text),
+            Noun::Atom(_) => return Err(convert::Error::ExpectedNull),
+            Noun::Cell(cell) => {
+                if let Noun::Atom(c) = cell.head_ref() {
+                    text.push(char::from_u32(u32_from_atom(c)?).ok_or(convert::Error::AtomToUint)?);
+                    list = cell.tail_ref();
+                } else {
+                    return Err(convert::Error::UnexpectedCell);
+                }
+            }
+        }
+    }
+}
+
+/// Converts characters into a null-terminated list of UTF-32 characters.
+fn text_to_noun(text: &[char]) -> Noun {
+    text.iter().rev().fold(Noun::null(), |list, c| {
+        Noun::from(Cell::from([Noun::from(Atom::from(u64::from(*c))), list]))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests the `TryFrom<&Noun>` implementation for [`Blit`] and how blits are drawn.
+    #[test]
+    fn render_blits() {
+        let noun = Noun::from(Cell::from([
+            Noun::from(Atom::from("mor")),
+            Noun::from(Cell::from([Atom::from("hop"), Atom::from(4u64)])),
+            Noun::from(Cell::from([
+                Noun::from(Atom::from("put")),
+                text_to_noun(&['h', 'é', '!']),
+            ])),
+            Noun::from(Cell::from([Atom::from("nel"), Atom::null()])),
+            Noun::from(Cell::from([
+                Atom::from("hop"),
+                Atom::from(0u64),
+                Atom::from(2u64),
+            ])),
+            Noun::from(Cell::from([Atom::from("wyp"), Atom::null()])),
+            Noun::null(),
+        ]));
+        let blit = Blit::try_from(&noun).expect("&Noun to Blit");
+        assert_eq!(
+            blit,
+            Blit::Mor(vec![
+                Blit::Hop { col: 4, row: None },
+                Blit::Put(vec!['h', 'é', '!']),
+                Blit::Nel,
+                Blit::Hop {
+                    col: 0,
+                    row: Some(2)
+                },
+                Blit::Wyp,
+            ])
+        );
+        let mut out = Vec::new();
+        blit.render(Styling::TrueColor, &mut out);
+        assert_eq!(out, "\x1b[5Ghé!\r\n\x1b[3;1H\r\x1b[K".as_bytes());
+
+        // Malformed request: `%bel` has data.
+        let noun = Noun::from(Cell::from([Atom::from("bel"), Atom::from(1u64)]));
+        assert!(Blit::try_from(&noun).is_err());
+
+        // Malformed request: the text isn't null-terminated.
+        let noun = Noun::from(Cell::from([
+            Atom::from("put"),
+            Atom::from(u64::from('a')),
+            Atom::from(u64::from('b')),
+        ]));
+        assert!(Blit::try_from(&noun).is_err());
+
+        // Malformed request: unknown blit.
+        let noun = Noun::from(Cell::from([Atom::from("sav"), Atom::null()]));
+        assert!(Blit::try_from(&noun).is_err());
+    }
+
+    /// Tests the `TryFrom<&Noun>` implementation for [`Draw`].
+    #[test]
+    fn draw_from_noun() {
+        let noun = Noun::from(Cell::from([
+            Atom::from(3u64),
+            Atom::from("bel"),
+            Atom::null(),
+        ]));
+        let req = Draw::try_from(&noun).expect("&Noun to Draw");
+        assert_eq!(req.session, 3);
+        assert_eq!(req.blit, Blit::Bel);
+
+        // Malformed request: missing session.
+        let noun = Noun::from(Cell::from([Atom::from("bel"), Atom::null()]));
+        assert!(Draw::try_from(&noun).is_err());
+    }
+
+    /// Tests the `TryFrom<&Noun>` implementation for [`Styled`] and how styled text is drawn with
+    /// each [`Styling`].
+    #[test]
+    fn render_styled_text() {
+        // `[%klr [[[%br ~ [%un ~ ~]] ~ [255 0 0]] "hi"] [[~ %b ~] "!"] ~]`
+        let noun = Noun::from(Cell::from([
+            Noun::from(Atom::from("klr")),
+            Noun::from(Cell::from([
+                Noun::from(Cell::from([
+                    Noun::from(Cell::from([
+                        Noun::from(Atom::from("br")),
+                        Noun::null(),
+                        Noun::from(Cell::from([Atom::from("un"), Atom::null(), Atom::null()])),
+                    ])),
+                    Noun::null(),
+                    Noun::from(Cell::from([
+                        Atom::from(255u64),
+                        Atom::from(0u64),
+                        Atom::from(0u64),
+                    ])),
+                ])),
+                text_to_noun(&['h', 'i']),
+            ])),
+            Noun::from(Cell::from([
+                Noun::from(Cell::from([
+                    Noun::null(),
+                    Noun::from(Atom::from("b")),
+                    Noun::null(),
+                ])),
+                text_to_noun(&['!']),
+            ])),
+            Noun::null(),
+        ]));
+        let blit = Blit::try_from(&noun).expect("&Noun to Blit");
+        assert_eq!(
+            blit,
+            Blit::Klr(vec![
+                Styled {
+                    style: Style {
+                        bold: true,
+                        underline: true,
+                        foreground: Tint::Rgb(255, 0, 0),
+                        ..Style::default()
+                    },
+                    text: vec!['h', 'i'],
+                },
+                Styled {
+                    style: Style {
+                        background: Tint::Basic(4),
+                        ..Style::default()
+                    },
+                    text: vec!['!'],
+                },
+            ])
+        );
+
+        let render = |styling| {
+            let mut out = Vec::new();
+            blit.render(styling, &mut out);
+            String::from_utf8(out).expect("UTF-8")
+        };
+        assert_eq!(render(Styling::Plain), "hi!");
+        assert_eq!(render(Styling::Decorations), "\x1b[1;4mhi\x1b[0m!");
+        assert_eq!(
+            render(Styling::Basic),
+            "\x1b[1;4;31mhi\x1b[0m\x1b[44m!\x1b[0m"
+        );
+        assert_eq!(
+            render(Styling::Indexed),
+            "\x1b[1;4;38;5;196mhi\x1b[0m\x1b[44m!\x1b[0m"
+        );
+        assert_eq!(
+            render(Styling::TrueColor),
+            "\x1b[1;4;38;2;255;0;0mhi\x1b[0m\x1b[44m!\x1b[0m"
+        );
+
+        // Malformed request: unknown decoration.
+        let noun = Noun::from(Cell::from([
+            Noun::from(Cell::from([
+                Noun::from(Cell::from([Atom::from("it"), Atom::null(), Atom::null()])),
+                Noun::null(),
+                Noun::null(),
+            ])),
+            Noun::null(),
+        ]));
+        assert!(Styled::try_from(&noun).is_err());
+
+        // Malformed request: color channel is wider than 8 bits.
+        let noun = Noun::from(Cell::from([
+            Atom::from(256u64),
+            Atom::from(0u64),
+            Atom::from(0u64),
+        ]));
+        assert!(Tint::try_from(&noun).is_err());
+    }
+
+    /// Tests how [`Styling`] is configured and detected.
+    #[test]
+    fn detect_styling() {
+        assert_eq!(Styling::from_str("plain"), Ok(Styling::Plain));
+        assert_eq!(Styling::from_str("none"), Ok(Styling::Decorations));
+        assert_eq!(Styling::from_str("256"), Ok(Styling::Indexed));
+        assert_eq!(Styling::from_str("auto"), Err(()));
+
+        assert_eq!(
+            Styling::detect(Some("dumb"), Some("truecolor"), false),
+            Styling::Plain
+        );
+        assert_eq!(
+            Styling::detect(Some("xterm-256color"), Some("truecolor"), true),
+            Styling::Decorations
+        );
+        assert_eq!(
+            Styling::detect(Some("xterm-256color"), Some("truecolor"), false),
+            Styling::TrueColor
+        );
+        assert_eq!(
+            Styling::detect(Some("xterm-256color"), None, false),
+            Styling::Indexed
+        );
+        assert_eq!(Styling::detect(Some("xterm"), None, false), Styling::Basic);
+        assert_eq!(Styling::detect(None, None, false), Styling::Basic);
+    }
+
+    /// Tests that [`KeyParser`] parses typed text, control keys, and escape sequences, including
+    /// those split across reads.
+    #[test]
+    fn parse_keys() {
+        let mut parser = KeyParser::default();
+        assert_eq!(
+            parser.parse(b"hi\x1b[A\x1b[1;5Dok\r\x7f\x03\t"),
+            vec![
+                Belt::Txt(vec!['h', 'i']),
+                Belt::Aro(Arrow::Up),
+                Belt::Aro(Arrow::Left),
+                Belt::Txt(vec!['o', 'k']),
+                Belt::Ret,
+                Belt::Bac,
+                Belt::Mod(Modifier::Ctl, 'c'),
+                Belt::Mod(Modifier::Ctl, 'i'),
+            ]
+        );
+        assert_eq!(
+            parser.parse(b"\x1bOP\x1b[15~\x1b[24~\x1b[3~\x1bx"),
+            vec![
+                Belt::Fun(1),
+                Belt::Fun(5),
+                Belt::Fun(12),
+                Belt::Del,
+                Belt::Mod(Modifier::Met, 'x'),
+            ]
+        );
+
+        // Unknown escape sequences are skipped.
+        assert_eq!(parser.parse(b"\x1b[200~a"), vec![Belt::Txt(vec!['a'])]);
+
+        // Keys split across reads are parsed once they're complete.
+        assert_eq!(parser.parse(b"\x1b"), vec![]);
+        assert_eq!(parser.parse(b"["), vec![]);
+        assert_eq!(parser.parse(b"B\xc3"), vec![Belt::Aro(Arrow::Down)]);
+        assert_eq!(parser.parse(b"\xa9"), vec![Belt::Txt(vec!['é'])]);
+    }
+
+    /// Tests that a [`Frame`] reads what it encodes, and skips messages of unknown kinds.
+    #[tokio::test]
+    async fn read_frames() {
+        let mut bytes = Frame::Keys(b"hi".to_vec()).encode();
+        bytes.extend_from_slice(&[9, 1, 0, 0]);
+        bytes.extend_from_slice(&Frame::Size(80, 24).encode());
+        assert_eq!(&bytes[..5], &[0, 2, 0, b'h', b'i']);
+
+        let mut src = &bytes[..];
+        assert_eq!(
+            Frame::read(&mut src).await.expect("read keys"),
+            Some(Frame::Keys(b"hi".to_vec()))
+        );
+        assert_eq!(Frame::read(&mut src).await.expect("read unknown"), None);
+        assert_eq!(
+            Frame::read(&mut src).await.expect("read size"),
+            Some(Frame::Size(80, 24))
+        );
+        assert!(Frame::read(&mut src).await.is_err());
+
+        // Malformed message: the payload is cut off.
+        let mut src = &[0, 2, 0, b'h'][..];
+        assert!(Frame::read(&mut src).await.is_err());
+    }
+
+    /// Tests the `From<Belt>` implementation for [`Noun`].
+    #[test]
+    fn belt_to_noun() {
+        assert_eq!(
+            Noun::from(Belt::Aro(Arrow::Right)),
+            Noun::from(Cell::from([Atom::from("aro"), Atom::from("r")]))
+        );
+        assert_eq!(
+            Noun::from(Belt::Mod(Modifier::Ctl, 'd')),
+            Noun::from(Cell::from([
+                Atom::from("mod"),
+                Atom::from("ctl"),
+                Atom::from(u64::from('d')),
+            ]))
+        );
+        assert_eq!(
+            Noun::from(Belt::Txt(vec!['a', 'b'])),
+            Noun::from(Cell::from([
+                Atom::from("txt"),
+                Atom::from(u64::from('a')),
+                Atom::from(u64::from('b')),
+                Atom::null(),
+            ]))
+        );
+    }
+
+    /// Tests how [`LineEditor`] edits a line and submits it to the ship.
+    #[test]
+    fn edit_line() {
+        let history = Arc::new(Mutex::new(History::load(None)));
+        let mut editor = LineEditor::new(history.clone());
+        let ctl = |key| Belt::Mod(Modifier::Ctl, key);
+
+        // The first key saves the start of the line, and the rest return to it.
+        let (belts, out) = editor.press(Belt::Txt(vec!['h', 'o']));
+        assert_eq!(belts, vec![]);
+        assert_eq!(out, b"\x1b7ho\x1b[K\x1b8\x1b[2C");
+        let (_belts, out) = editor.press(Belt::Aro(Arrow::Left));
+        assert_eq!(out, b"\x1b8ho\x1b[K\x1b8\x1b[1C");
+
+        editor.press(Belt::Txt(vec!['e', 'l', 'l']));
+        assert_eq!(editor.line, vec!['h', 'e', 'l', 'l', 'o']);
+        editor.press(ctl('a'));
+        editor.press(Belt::Del);
+        editor.press(ctl('e'));
+        editor.press(Belt::Bac);
+        assert_eq!(editor.line, vec!['e', 'l', 'l']);
+
+        // Cut words and paste them.
+        editor.press(Belt::Txt(" two".chars().collect()));
+        editor.press(ctl('w'));
+        assert_eq!(editor.line, "ell ".chars().collect::<Vec<_>>());
+        editor.press(ctl('a'));
+        editor.press(ctl('y'));
+        assert_eq!(editor.line, "twoell ".chars().collect::<Vec<_>>());
+        editor.press(ctl('f'));
+        editor.press(ctl('f'));
+        editor.press(ctl('k'));
+        editor.press(ctl('u'));
+        assert!(editor.line.is_empty());
+        editor.press(ctl('y'));
+        assert_eq!(editor.line, vec!['t', 'w', 'o', 'e', 'l']);
+
+        // Other keys are sent to the ship.
+        let (belts, _out) = editor.press(Belt::Fun(1));
+        assert_eq!(belts, vec![Belt::Fun(1)]);
+        let (belts, _out) = editor.press(ctl('c'));
+        assert_eq!(belts, vec![ctl('c')]);
+        assert!(editor.line.is_empty());
+        let (belts, _out) = editor.press(ctl('d'));
+        assert_eq!(belts, vec![ctl('d')]);
+
+        // Return submits the line, and the next line starts after what the ship draws.
+        editor.press(Belt::Txt(vec!['+', 'h']));
+        let (belts, out) = editor.press(Belt::Ret);
+        assert_eq!(belts, vec![Belt::Txt(vec!['+', 'h']), Belt::Ret]);
+        assert_eq!(out, b"");
+        assert_eq!(history.lock().unwrap().lines, vec![vec!['+', 'h']]);
+        let (belts, _out) = editor.press(Belt::Ret);
+        assert_eq!(belts, vec![Belt::Ret]);
+        let mut out = Vec::new();
+        editor.resume(&mut out);
+        assert_eq!(out, b"");
+        editor.press(Belt::Txt(vec!['x']));
+        let mut out = Vec::new();
+        editor.resume(&mut out);
+        assert_eq!(out, b"\x1b7x\x1b[K\x1b8\x1b[1C");
+    }
+
+    /// Tests how [`LineEditor`] browses and searches the history, and how [`History`] is kept.
+    #[test]
+    fn edit_history() {
+        assert_eq!(EditMode::from_str("local"), Ok(EditMode::Local));
+        assert_eq!(EditMode::from_str("remote"), Ok(EditMode::Remote));
+        assert_eq!(EditMode::from_str("vi"), Err(()));
+
+        let path = env::temp_dir().join(format!("term.{}.history", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let history = Arc::new(Mutex::new(History::load(Some(path.clone()))));
+        {
+            let mut history = history.lock().unwrap();
+            for line in ["ab", "bc", "bc", "", "cd"] {
+                history.push(&line.chars().collect::<Vec<_>>());
+            }
+        }
+        assert_eq!(
+            fs::read_to_string(&path).expect("read history"),
+            "ab\nbc\ncd\n"
+        );
+        assert_eq!(History::load(Some(path.clone())).lines.len(), 3);
+        fs::remove_file(&path).expect("remove history");
+
+        let mut editor = LineEditor::new(history);
+        editor.press(Belt::Txt(vec!['z']));
+        editor.press(Belt::Aro(Arrow::Up));
+        assert_eq!(editor.line, vec!['c', 'd']);
+        editor.press(Belt::Mod(Modifier::Ctl, 'p'));
+        editor.press(Belt::Aro(Arrow::Up));
+        editor.press(Belt::Aro(Arrow::Up));
+        assert_eq!(editor.line, vec!['a', 'b']);
+        editor.press(Belt::Aro(Arrow::Down));
+        assert_eq!(editor.line, vec!['b', 'c']);
+        editor.press(Belt::Mod(Modifier::Ctl, 'n'));
+        editor.press(Belt::Aro(Arrow::Down));
+        assert_eq!(editor.line, vec!['z']);
+
+        // Abandon a search.
+        editor.press(Belt::Mod(Modifier::Ctl, 'r'));
+        let (_belts, out) = editor.press(Belt::Txt(vec!['c']));
+        assert_eq!(
+            out,
+            "\x1b8(reverse-i-search)'c': cd\x1b[K\x1b8\x1b[25C".as_bytes()
+        );
+        editor.press(Belt::Mod(Modifier::Ctl, 'g'));
+        assert_eq!(editor.line, vec!['z']);
+
+        // Find an older match, and keep it.
+        editor.press(Belt::Mod(Modifier::Ctl, 'r'));
+        editor.press(Belt::Txt(vec!['b']));
+        editor.press(Belt::Mod(Modifier::Ctl, 'r'));
+        editor.press(Belt::Mod(Modifier::Ctl, 'r'));
+        let (belts, _out) = editor.press(Belt::Ret);
+        assert_eq!(belts, vec![Belt::Txt(vec!['a', 'b']), Belt::Ret]);
+    }
+}
@@ -0,0 +1,63 @@
+//! Tests the Ames driver.
+//!
+//! The general pattern for each test is to launch the Ames driver in a subprocess with piped
+//! `stdin` and `stdout` via the crate's binary (defined in `src/main.rs`) and write Ames requests
+//! to the driver over the subprocess's `stdin` pipe and read responses to those requests over the
+//! subprocess's `stdout` pipe.
+
+use noun::{Atom, Cell, Noun};
+use std::{net::UdpSocket, path::Path, time::Duration};
+
+mod common;
+
+/// Receives a packet from and sends a packet to a peer over the Ames driver.
+#[test]
+fn hear_and_send() {
+    let mut driver = common::spawn_driver("ames", Path::new("hear_and_send.ames_tests.log"));
+
+    let mut input = driver.0.stdin.take().unwrap();
+    let mut output = driver.0.stdout.take().unwrap();
+
+    // The driver reports the port it bound its socket to when it starts.
+    let port = if let Noun::Cell(resp) = common::read_response(&mut output) {
+        let [tag, port] = resp.to_array::<2>().expect("response to array");
+        assert_eq!(*tag, Noun::from(Atom::from("bound")));
+        if let Noun::Atom(port) = &*port {
+            u16::try_from(port.as_u64().expect("port to u64")).expect("port to u16")
+        } else {
+            panic!("port is a cell");
+        }
+    } else {
+        panic!("response is an atom");
+    };
+
+    let peer = UdpSocket::bind("127.0.0.1:0").expect("bind peer");
+    peer.set_read_timeout(Some(Duration::from_secs(5)))
+        .expect("set read timeout");
+    let lane = Noun::from(Cell::from([
+        Atom::from(0x7f000001u64),
+        Atom::from(u64::from(peer.local_addr().expect("peer address").port())),
+    ]));
+
+    peer.send_to(b"ping", ("127.0.0.1", port))
+        .expect("send packet");
+    let expected = Noun::from(Cell::from([
+        Noun::from(Atom::from("hear")),
+        lane.clone(),
+        Noun::from(Atom::from(4u64)),
+        Noun::from(Atom::from("ping")),
+    ]));
+    assert_eq!(common::read_response(&mut output), expected);
+
+    let req = Noun::from(Cell::from([
+        Noun::from(Atom::from("send")),
+        lane,
+        Noun::from(Atom::from(4u64)),
+        Noun::from(Atom::from("pong")),
+    ]));
+    common::write_request(&mut input, req);
+    let mut buf = [0; 16];
+    let (len, addr) = peer.recv_from(&mut buf).expect("receive packet");
+    assert_eq!(&buf[..len], b"pong");
+    assert_eq!(addr.port(), port);
+}
@@ -5,6 +5,7 @@ use noun::{
     Atom, Noun,
 };
 use std::{
+    env,
     io::{Read, Write},
     path::Path,
     process::{Child, ChildStdin, ChildStdout, Command, Stdio},
@@ -21,23 +22,59 @@ impl Drop for DriverProcess {
 
 /// Spawns an IO driver in a subprocess with piped `stdin` and `stdout`.
 pub(crate) fn spawn_driver(driver: &'static str, log_file: &Path) -> DriverProcess {
-    // Absolute path to the binary defined by `src/main.rs`.
-    const BINARY: &'static str = env!("CARGO_BIN_EXE_io_drivers");
+    spawn_driver_in(driver, log_file, Path::new("."))
+}
 
-    const LOG_VAR: &'static str = "URBIT_IO_DRIVERS_LOG";
+/// Spawns an IO driver in a subprocess with piped `stdin` and `stdout` and `dir` as its working
+/// directory.
+pub(crate) fn spawn_driver_in(driver: &'static str, log_file: &Path, dir: &Path) -> DriverProcess {
+    DriverProcess(
+        driver_command(driver, log_file, dir)
+            .spawn()
+            .expect("spawn io_drivers process"),
+    )
+}
 
+/// Spawns an IO driver in a subprocess with piped `stdin` and `stdout` and `vars` set in its
+/// environment.
+pub(crate) fn spawn_driver_with_env(
+    driver: &'static str,
+    log_file: &Path,
+    vars: &[(&str, &str)],
+) -> DriverProcess {
     DriverProcess(
-        Command::new(BINARY)
-            .arg(driver)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::inherit())
-            .env(LOG_VAR, log_file)
+        driver_command(driver, log_file, Path::new("."))
+            .envs(vars.iter().copied())
             .spawn()
             .expect("spawn io_drivers process"),
     )
 }
 
+/// Returns the command that runs an IO driver with piped `stdin` and `stdout` and `dir` as its
+/// working directory.
+fn driver_command(driver: &'static str, log_file: &Path, dir: &Path) -> Command {
+    // Absolute path to the binary defined by `src/main.rs`.
+    const BINARY: &'static str = env!("CARGO_BIN_EXE_io_drivers");
+
+    const LOG_VAR: &'static str = "URBIT_IO_DRIVERS_LOG";
+
+    let mut command = Command::new(BINARY);
+    command
+        .arg(driver)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        // Keep the log file relative to the test's working directory rather than `dir`.
+        .env(
+            LOG_VAR,
+            env::current_dir()
+                .expect("current directory")
+                .join(log_file),
+        );
+    command
+}
+
 /// Writes a request to a driver's input source.
 pub(crate) fn write_request(input: &mut ChildStdin, req: Noun) {
     let req = req.jam().into_vec();
@@ -0,0 +1,113 @@
+//! Tests the control socket driver.
+//!
+//! The general pattern for each test is to launch the control socket driver in a subprocess with
+//! piped `stdin` and `stdout` via the crate's binary (defined in `src/main.rs`), send commands to
+//! the driver over its socket, read the commands forwarded over the subprocess's `stdout` pipe,
+//! and write their results to the driver over the subprocess's `stdin` pipe.
+
+#![cfg(unix)]
+
+use noun::{
+    serdes::{Cue, Jam},
+    Atom, Cell, Noun,
+};
+use std::{
+    env,
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+mod common;
+
+/// Sends a noun over a connection to the driver's socket.
+fn send(stream: &mut UnixStream, noun: Noun) {
+    let noun = noun.jam().into_vec();
+    let mut msg = vec![0];
+    msg.extend_from_slice(&u32::try_from(noun.len()).unwrap().to_le_bytes());
+    msg.extend_from_slice(&noun);
+    stream.write_all(&msg).expect("send message");
+}
+
+/// Receives a noun over a connection to the driver's socket.
+fn recv(stream: &mut UnixStream) -> Noun {
+    let mut header = [0; 5];
+    stream.read_exact(&mut header).expect("read message header");
+    assert_eq!(header[0], 0);
+    let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]);
+    let mut msg = vec![0; usize::try_from(len).unwrap()];
+    stream.read_exact(&mut msg).expect("read message");
+    Noun::cue(Atom::from(msg)).expect("cue message")
+}
+
+/// Sends commands to the driver and receives their results.
+#[test]
+fn command_and_give() {
+    let socket = env::temp_dir().join(format!("conn_tests.{}.sock", std::process::id()));
+    let socket = socket.to_str().expect("socket path to str");
+    let mut driver = common::spawn_driver_with_env(
+        "conn",
+        Path::new("command_and_give.conn_tests.log"),
+        &[("URBIT_IO_DRIVERS_CONN_SOCKET", socket)],
+    );
+
+    let mut input = driver.0.stdin.take().unwrap();
+    let mut output = driver.0.stdout.take().unwrap();
+
+    // Wait for the driver to listen on the socket.
+    let start = Instant::now();
+    let mut client = loop {
+        match UnixStream::connect(socket) {
+            Ok(client) => break client,
+            Err(err) if start.elapsed() > Duration::from_secs(5) => panic!("connect: {}", err),
+            Err(_err) => std::thread::sleep(Duration::from_millis(10)),
+        }
+    };
+    let expected = Noun::from(Cell::from([Atom::from("open"), Atom::from(1u64)]));
+    assert_eq!(common::read_response(&mut output), expected);
+
+    // Commands the driver knows are forwarded.
+    let cmd = Noun::from(Cell::from([
+        Atom::from(7u64),
+        Atom::from("peek"),
+        Atom::from("now"),
+    ]));
+    send(&mut client, cmd.clone());
+    let expected = Noun::from(Cell::from([
+        Noun::from(Atom::from("conn")),
+        Noun::from(Atom::from(1u64)),
+        cmd,
+    ]));
+    assert_eq!(common::read_response(&mut output), expected);
+
+    // Commands the driver doesn't know are answered by the driver.
+    send(
+        &mut client,
+        Noun::from(Cell::from([
+            Atom::from(8u64),
+            Atom::from("zap"),
+            Atom::null(),
+        ])),
+    );
+    let expected = Noun::from(Cell::from([
+        Atom::from(8u64),
+        Atom::from("error"),
+        Atom::from("unknown command"),
+    ]));
+    assert_eq!(recv(&mut client), expected);
+
+    let req = Noun::from(Cell::from([
+        Atom::from("give"),
+        Atom::from(1u64),
+        Atom::from(7u64),
+        Atom::from(42u64),
+    ]));
+    common::write_request(&mut input, req);
+    let expected = Noun::from(Cell::from([Atom::from(7u64), Atom::from(42u64)]));
+    assert_eq!(recv(&mut client), expected);
+
+    drop(client);
+    let expected = Noun::from(Cell::from([Atom::from("shut"), Atom::from(1u64)]));
+    assert_eq!(common::read_response(&mut output), expected);
+}
@@ -0,0 +1,98 @@
+//! Tests the DNS binding driver.
+//!
+//! The general pattern for each test is to launch the DNS binding driver in a subprocess with piped
+//! `stdin` and `stdout` via the crate's binary (defined in `src/main.rs`), write requests to bind
+//! `localhost` to the driver over the subprocess's `stdin` pipe, answer the driver's confirmation
+//! requests with a fake web server on the loopback interface, and read responses over the
+//! subprocess's `stdout` pipe.
+
+use noun::{Atom, Cell, Noun};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::Path,
+    thread,
+};
+
+mod common;
+
+/// Returns a `%bind` request for `localhost` with the given ID, address, port, and path.
+fn bind(id: u64, addr: [u8; 4], port: u16, path: &str) -> Noun {
+    Noun::from(Cell::from([
+        Noun::from(Atom::from("bind")),
+        Noun::from(Atom::from(id)),
+        Noun::from(Cell::from([Atom::from("localhost"), Atom::null()])),
+        Noun::from(Atom::from(u64::from(u32::from_be_bytes(addr)))),
+        Noun::from(Atom::from(u64::from(port))),
+        Noun::from(Atom::from(path)),
+    ]))
+}
+
+/// Checks bindings that succeed, that resolve to the wrong address, and that aren't confirmed.
+#[test]
+fn bind_localhost() {
+    // A web server that only confirms `/~/dns/ok`.
+    let server = TcpListener::bind("127.0.0.1:0").expect("bind server");
+    let port = server.local_addr().expect("server address").port();
+    thread::spawn(move || {
+        for stream in server.incoming() {
+            let mut stream = stream.expect("accept connection");
+            let mut req_line = String::new();
+            let mut reader = BufReader::new(&stream);
+            reader.read_line(&mut req_line).expect("read request line");
+            // Skip the headers.
+            let mut line = String::new();
+            while reader.read_line(&mut line).expect("read header") > 2 {
+                line.clear();
+            }
+            let status = if req_line.starts_with("GET /~/dns/ok ") {
+                "200 OK"
+            } else {
+                "404 Not Found"
+            };
+            let resp = format!(
+                "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            );
+            stream.write_all(resp.as_bytes()).expect("write response");
+        }
+    });
+
+    let mut driver = common::spawn_driver_with_env(
+        "dns",
+        Path::new("bind_localhost.dns_tests.log"),
+        &[("URBIT_IO_DRIVERS_DNS_TIMEOUT", "1")],
+    );
+
+    let mut input = driver.0.stdin.take().unwrap();
+    let mut output = driver.0.stdout.take().unwrap();
+
+    common::write_request(&mut input, bind(1, [127, 0, 0, 1], port, "/~/dns/ok"));
+    let expected = Noun::from(Cell::from([Atom::from("resolved"), Atom::from(1u64)]));
+    assert_eq!(common::read_response(&mut output), expected);
+    let expected = Noun::from(Cell::from([Atom::from("confirmed"), Atom::from(1u64)]));
+    assert_eq!(common::read_response(&mut output), expected);
+
+    // `localhost` doesn't resolve to a documentation address.
+    common::write_request(&mut input, bind(2, [192, 0, 2, 1], port, "/~/dns/ok"));
+    match common::read_response(&mut output) {
+        Noun::Cell(resp) => {
+            let [tag, id, step, _reason] = resp.to_array::<4>().expect("response to array");
+            assert_eq!(*tag, Noun::from(Atom::from("failed")));
+            assert_eq!(*id, Noun::from(Atom::from(2u64)));
+            assert_eq!(*step, Noun::from(Atom::from("resolve")));
+        }
+        _ => panic!("response is an atom"),
+    }
+
+    common::write_request(&mut input, bind(3, [127, 0, 0, 1], port, "/~/dns/missing"));
+    let expected = Noun::from(Cell::from([Atom::from("resolved"), Atom::from(3u64)]));
+    assert_eq!(common::read_response(&mut output), expected);
+    let expected = Noun::from(Cell::from([
+        Atom::from("failed"),
+        Atom::from(3u64),
+        Atom::from("confirm"),
+        Atom::from("unexpected status 404"),
+    ]));
+    assert_eq!(common::read_response(&mut output), expected);
+}
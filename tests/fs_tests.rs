@@ -0,0 +1,208 @@
+//! Tests the file system driver.
+//!
+//! The general pattern for each test is to build a mount point fixture, launch the file system
+//! driver in a subprocess within the fixture's pier directory via the crate's binary (defined in
+//! `src/main.rs`), and then write requests to the driver over the subprocess's `stdin` pipe and
+//! read responses to those requests over the subprocess's `stdout` pipe.
+
+use io_drivers::fs::test_util::MountPointFixture;
+use noun::Noun;
+use std::{
+    fs,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+#[allow(dead_code)]
+mod common;
+
+/// Sends `%hill` and `%dirk` requests to the file system driver.
+#[test]
+fn commit_mount_point() {
+    let fixture = MountPointFixture::new("commit_mount_point", "base")
+        .file("sys.kelvin", "[%zuse 413]\n")
+        .file("gen/hello.hoon", "|=  a=@  +(a)\n")
+        .file("app/dojo/lib/util.hoon", "~\n")
+        .file("mar/blob.bin", [0xde, 0xad, 0xbe, 0xef, 0, 0])
+        .dir("tests")
+        .build()
+        .expect("build fixture");
+
+    let mut driver = common::spawn_driver_in(
+        "file-system",
+        Path::new("commit_mount_point.fs_tests.log"),
+        fixture.pier(),
+    );
+    let mut input = driver.0.stdin.take().unwrap();
+    let mut output = driver.0.stdout.take().unwrap();
+
+    common::write_request(&mut input, fixture.hill());
+    common::write_request(&mut input, fixture.dirk());
+    fixture.assert_committed(&common::read_response(&mut output));
+
+    // Nothing has changed since the last commit.
+    common::write_request(&mut input, fixture.dirk());
+    assert_eq!(common::read_response(&mut output), Noun::null());
+}
+
+/// Sends `%ogre` requests to the file system driver.
+#[test]
+fn delete_mount_point() {
+    let fixture = MountPointFixture::new("delete_mount_point", "base")
+        .file("gen/hello.hoon", "|=  a=@  +(a)\n")
+        .build()
+        .expect("build fixture");
+
+    let mut driver = common::spawn_driver_in(
+        "file-system",
+        Path::new("delete_mount_point.fs_tests.log"),
+        fixture.pier(),
+    );
+    let mut input = driver.0.stdin.take().unwrap();
+    let mut output = driver.0.stdout.take().unwrap();
+
+    common::write_request(&mut input, fixture.hill());
+    common::write_request(&mut input, fixture.dirk());
+    fixture.assert_committed(&common::read_response(&mut output));
+    common::write_request(&mut input, fixture.ogre());
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while fixture.path().exists() {
+        assert!(Instant::now() < deadline, "mount point wasn't deleted");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    // A mount point of the same name is handled from scratch once the old one is deleted.
+    fs::create_dir(fixture.path()).expect("recreate mount point");
+    common::write_request(&mut input, fixture.hill());
+    common::write_request(&mut input, fixture.ergo());
+    common::write_request(&mut input, fixture.dirk());
+    assert_eq!(common::read_response(&mut output), Noun::null());
+    fixture.assert_on_disk();
+}
+
+/// Sends `%dirk-dry-run` requests to the file system driver.
+#[test]
+fn dry_run_commit_mount_point() {
+    let fixture = MountPointFixture::new("dry_run_commit_mount_point", "base")
+        .file("gen/hello.hoon", "|=  a=@  +(a)\n")
+        .file("mar/blob.bin", [0xde, 0xad, 0xbe, 0xef])
+        .build()
+        .expect("build fixture");
+
+    let mut driver = common::spawn_driver_in(
+        "file-system",
+        Path::new("dry_run_commit_mount_point.fs_tests.log"),
+        fixture.pier(),
+    );
+    let mut input = driver.0.stdin.take().unwrap();
+    let mut output = driver.0.stdout.take().unwrap();
+
+    common::write_request(&mut input, fixture.hill());
+    common::write_request(&mut input, fixture.dirk_dry_run());
+    fixture.assert_previewed(&common::read_response(&mut output));
+
+    // The preview leaves the changes to be committed.
+    common::write_request(&mut input, fixture.dirk());
+    fixture.assert_committed(&common::read_response(&mut output));
+}
+
+/// Sends `%ergo` requests to the file system driver.
+#[test]
+fn update_file_system() {
+    let fixture = MountPointFixture::new("update_file_system", "base")
+        .file("gen/hello.hoon", "|=  a=@  +(a)\n")
+        .file("lib/nested/deeply/util.hoon", "~\n")
+        .file("mar/blob.bin", [0, 1, 2, 0])
+        .build_unpopulated()
+        .expect("build fixture");
+
+    let mut driver = common::spawn_driver_in(
+        "file-system",
+        Path::new("update_file_system.fs_tests.log"),
+        fixture.pier(),
+    );
+    let mut input = driver.0.stdin.take().unwrap();
+    let mut output = driver.0.stdout.take().unwrap();
+
+    common::write_request(&mut input, fixture.hill());
+    common::write_request(&mut input, fixture.ergo());
+    // The files written by `%ergo` aren't reported as changes.
+    common::write_request(&mut input, fixture.dirk());
+    assert_eq!(common::read_response(&mut output), Noun::null());
+    fixture.assert_on_disk();
+}
+
+/// Sends `%verify` requests to the file system driver.
+#[test]
+fn verify_mount_point() {
+    // Enough files to fill more than one chunk of the manifest.
+    let fixture = (0..1000)
+        .fold(
+            MountPointFixture::new("verify_mount_point", "base"),
+            |fixture, i| fixture.file(&format!("gen/{:04}.hoon", i), format!("{}\n", i)),
+        )
+        .file("mar/blob.bin", [0xde, 0xad, 0xbe, 0xef, 0, 0])
+        .build()
+        .expect("build fixture");
+
+    let mut driver = common::spawn_driver_in(
+        "file-system",
+        Path::new("verify_mount_point.fs_tests.log"),
+        fixture.pier(),
+    );
+    let mut input = driver.0.stdin.take().unwrap();
+    let mut output = driver.0.stdout.take().unwrap();
+
+    common::write_request(&mut input, fixture.hill());
+    common::write_request(&mut input, fixture.verify());
+    let chunks = [
+        common::read_response(&mut output),
+        common::read_response(&mut output),
+    ];
+    assert_eq!(fixture.assert_manifest(&chunks), [1000, 1]);
+}
+
+/// Sends `%usage` requests to the file system driver.
+#[test]
+fn disk_usage() {
+    // Twelve files of 1 to 12 bytes, plus another of 12 bytes that sorts before the first.
+    let fixture = (1..=12)
+        .fold(
+            MountPointFixture::new("disk_usage", "base"),
+            |fixture, len| fixture.file(&format!("gen/{:02}.hoon", len), vec![b'a'; len]),
+        )
+        .file("app/tie.hoon", [b'a'; 12])
+        .build()
+        .expect("build fixture");
+
+    let mut driver = common::spawn_driver_in(
+        "file-system",
+        Path::new("disk_usage.fs_tests.log"),
+        fixture.pier(),
+    );
+    let mut input = driver.0.stdin.take().unwrap();
+    let mut output = driver.0.stdout.take().unwrap();
+
+    common::write_request(&mut input, fixture.hill());
+    common::write_request(&mut input, fixture.usage());
+    assert_eq!(
+        common::read_response(&mut output),
+        fixture.disk_usage(
+            90,
+            13,
+            &[
+                ("app/tie.hoon", 12),
+                ("gen/12.hoon", 12),
+                ("gen/11.hoon", 11),
+                ("gen/10.hoon", 10),
+                ("gen/09.hoon", 9),
+                ("gen/08.hoon", 8),
+                ("gen/07.hoon", 7),
+                ("gen/06.hoon", 6),
+                ("gen/05.hoon", 5),
+                ("gen/04.hoon", 4),
+            ]
+        )
+    );
+}
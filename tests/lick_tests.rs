@@ -0,0 +1,133 @@
+//! Tests the lick driver.
+//!
+//! The general pattern for each test is to launch the lick driver in a subprocess with piped
+//! `stdin` and `stdout` via the crate's binary (defined in `src/main.rs`), write lick requests to
+//! the driver over the subprocess's `stdin` pipe, exchange messages with the driver over the socket
+//! of a port, and read responses over the subprocess's `stdout` pipe.
+
+#![cfg(unix)]
+
+use noun::{
+    serdes::{Cue, Jam},
+    Atom, Cell, Noun,
+};
+use std::{
+    env, fs,
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+mod common;
+
+/// Returns the `%soak` response for `[<mark> <noun>]` from the port `/test/port`.
+fn soak(mark: &str, noun: Noun) -> Noun {
+    Noun::from(Cell::from([
+        Noun::from(Atom::from("soak")),
+        Noun::from(Cell::from([
+            Atom::from("test"),
+            Atom::from("port"),
+            Atom::null(),
+        ])),
+        Noun::from(Atom::from(mark)),
+        noun,
+    ]))
+}
+
+/// Opens a port, exchanges nouns with a program connected to it, and closes it.
+#[test]
+fn spin_spit_soak() {
+    let dir = env::temp_dir().join(format!("lick_tests.{}", std::process::id()));
+    let mut driver = common::spawn_driver_with_env(
+        "lick",
+        Path::new("spin_spit_soak.lick_tests.log"),
+        &[(
+            "URBIT_IO_DRIVERS_LICK_DIR",
+            dir.to_str().expect("dir to str"),
+        )],
+    );
+
+    let mut input = driver.0.stdin.take().unwrap();
+    let mut output = driver.0.stdout.take().unwrap();
+
+    let name = Noun::from(Cell::from([
+        Atom::from("test"),
+        Atom::from("port"),
+        Atom::null(),
+    ]));
+
+    // Sending to a port without a program connected to it fails.
+    let spit = Noun::from(Cell::from([
+        Noun::from(Atom::from("spit")),
+        name.clone(),
+        Noun::from(Atom::from("yo")),
+        Noun::from(Atom::from(7u64)),
+    ]));
+    common::write_request(&mut input, spit.clone());
+    let expected = soak("error", Noun::from(Atom::from("not connected")));
+    assert_eq!(common::read_response(&mut output), expected);
+
+    let req = Noun::from(Cell::from([Noun::from(Atom::from("spin")), name.clone()]));
+    common::write_request(&mut input, req);
+    let socket = dir.join("test").join("port");
+    let start = Instant::now();
+    let mut program = loop {
+        match UnixStream::connect(&socket) {
+            Ok(program) => break program,
+            Err(err) if start.elapsed() > Duration::from_secs(5) => panic!("connect: {}", err),
+            Err(_err) => std::thread::sleep(Duration::from_millis(10)),
+        }
+    };
+    assert_eq!(
+        common::read_response(&mut output),
+        soak("connect", Noun::null())
+    );
+
+    // The program sends a noun.
+    let msg = Noun::from(Cell::from([Atom::from("hi"), Atom::from(42u64)]))
+        .jam()
+        .into_vec();
+    let mut bytes = vec![0];
+    bytes.extend_from_slice(&u32::try_from(msg.len()).unwrap().to_le_bytes());
+    bytes.extend_from_slice(&msg);
+    program.write_all(&bytes).expect("send message");
+    assert_eq!(
+        common::read_response(&mut output),
+        soak("hi", Noun::from(Atom::from(42u64)))
+    );
+
+    // The ship sends a noun.
+    common::write_request(&mut input, spit);
+    let mut header = [0; 5];
+    program
+        .read_exact(&mut header)
+        .expect("read message header");
+    assert_eq!(header[0], 0);
+    let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]);
+    let mut msg = vec![0; usize::try_from(len).unwrap()];
+    program.read_exact(&mut msg).expect("read message");
+    assert_eq!(
+        Noun::cue(Atom::from(msg)).expect("cue message"),
+        Noun::from(Cell::from([Atom::from("yo"), Atom::from(7u64)]))
+    );
+
+    drop(program);
+    assert_eq!(
+        common::read_response(&mut output),
+        soak("disconnect", Noun::null())
+    );
+
+    // Closing the port removes its socket.
+    let req = Noun::from(Cell::from([Noun::from(Atom::from("shut")), name]));
+    common::write_request(&mut input, req);
+    let start = Instant::now();
+    while socket.exists() {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "socket not removed"
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,106 @@
+//! Tests the time synchronization driver.
+//!
+//! The general pattern for each test is to launch the time synchronization driver in a subprocess
+//! with piped `stdin` and `stdout` via the crate's binary (defined in `src/main.rs`), answer the
+//! driver's queries with a fake NTP server on the loopback interface, write requests to the driver
+//! over the subprocess's `stdin` pipe, and read responses over the subprocess's `stdout` pipe.
+
+use noun::{Atom, Cell, Noun};
+use std::{
+    net::UdpSocket,
+    path::Path,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+mod common;
+
+/// The number of seconds from the NTP epoch (1900) to the Unix epoch (1970).
+const EPOCH_DELTA: u64 = 2_208_988_800;
+
+/// Returns the NTP timestamp of the time `ahead` seconds from now.
+fn timestamp(ahead: u64) -> u64 {
+    let since = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time since epoch");
+    let frac = (u64::from(since.subsec_nanos()) << 32) / 1_000_000_000;
+    ((since.as_secs() + ahead + EPOCH_DELTA) << 32) | frac
+}
+
+/// Decodes a signed integer (i.e. an `@s`).
+fn unsigned(atom: &Atom) -> i64 {
+    let n = atom.as_u64().expect("atom as u64");
+    let half = (n >> 1) as i64;
+    if n & 1 == 0 {
+        half
+    } else {
+        -half - 1
+    }
+}
+
+/// Receives the host's clock from a server that's 10 seconds ahead.
+#[test]
+fn sync_with_server() {
+    let server = UdpSocket::bind("127.0.0.1:0").expect("bind server");
+    let addr = server.local_addr().expect("server address").to_string();
+    thread::spawn(move || {
+        let mut req = [0; 48];
+        while let Ok((_len, client)) = server.recv_from(&mut req) {
+            let mut reply = [0; 48];
+            // Leap indicator 0, version 4, server mode, and stratum 1.
+            reply[0] = 0x24;
+            reply[1] = 1;
+            reply[24..32].copy_from_slice(&req[40..48]);
+            reply[32..40].copy_from_slice(&timestamp(10).to_be_bytes());
+            reply[40..48].copy_from_slice(&timestamp(10).to_be_bytes());
+            server.send_to(&reply, client).expect("send reply");
+        }
+    });
+
+    let mut driver = common::spawn_driver_with_env(
+        "ntp",
+        Path::new("sync_with_server.ntp_tests.log"),
+        &[("URBIT_IO_DRIVERS_NTP_SERVERS", addr.as_str())],
+    );
+
+    let mut input = driver.0.stdin.take().unwrap();
+    let mut output = driver.0.stdout.take().unwrap();
+
+    // Returns the offset and skew of a `%time` response.
+    let mut read_time = || match common::read_response(&mut output) {
+        Noun::Cell(resp) => {
+            assert_eq!(*resp.head_ref(), Noun::from(Atom::from("time")));
+            match resp.tail_ref() {
+                Noun::Cell(resp) => match resp.head_ref() {
+                    Noun::Atom(offset) => (unsigned(offset), resp.tail_ref().clone()),
+                    _ => panic!("offset is a cell"),
+                },
+                _ => panic!("response has no skew"),
+            }
+        }
+        _ => panic!("response is an atom"),
+    };
+
+    // The first query is made when the driver starts.
+    let (offset, skew) = read_time();
+    assert!(
+        (9_000_000..=11_000_000).contains(&offset),
+        "offset {}",
+        offset
+    );
+    assert_eq!(skew, Noun::null());
+
+    // Later queries estimate skew.
+    let req = Noun::from(Cell::from([Atom::from("sync"), Atom::null()]));
+    common::write_request(&mut input, req);
+    let (offset, skew) = read_time();
+    assert!(
+        (9_000_000..=11_000_000).contains(&offset),
+        "offset {}",
+        offset
+    );
+    match skew {
+        Noun::Cell(skew) => assert_eq!(*skew.head_ref(), Noun::null()),
+        _ => panic!("skew is null"),
+    }
+}
@@ -0,0 +1,176 @@
+//! Tests the terminal driver.
+//!
+//! The general pattern for each test is to launch the terminal driver in a subprocess with piped
+//! `stdin` and `stdout` via the crate's binary (defined in `src/main.rs`) and a pseudoterminal as
+//! its terminal, write terminal requests to the driver over the subprocess's `stdin` pipe, type
+//! keys into the pseudoterminal or a session attached over the driver's socket, and read responses
+//! over the subprocess's `stdout` pipe.
+
+#![cfg(unix)]
+
+use noun::{Atom, Cell, Noun};
+use std::{
+    env,
+    ffi::CStr,
+    fs::File,
+    io::{Read, Write},
+    os::unix::{io::FromRawFd, net::UnixStream},
+    path::Path,
+    ptr,
+    time::{Duration, Instant},
+};
+
+mod common;
+
+/// Opens a pseudoterminal with the given size, returning its controlling side and the path of its
+/// terminal side.
+fn open_pty(cols: u16, rows: u16) -> (File, String) {
+    let (mut controller, mut terminal) = (0, 0);
+    let size = libc::winsize {
+        ws_row: rows,
+        ws_col: cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // SAFETY: the pointers are valid for the duration of the call.
+    let ret = unsafe {
+        libc::openpty(
+            &mut controller,
+            &mut terminal,
+            ptr::null_mut(),
+            ptr::null(),
+            &size,
+        )
+    };
+    assert_eq!(ret, 0, "open pseudoterminal");
+    // SAFETY: `terminal` is open, and `ttyname()` returns a null-terminated string.
+    let path = unsafe { CStr::from_ptr(libc::ttyname(terminal)) }
+        .to_str()
+        .expect("terminal path to str")
+        .to_string();
+    // SAFETY: `controller` is open and owned by nothing else. The terminal side is left open so
+    // that the pseudoterminal isn't hung up if the driver closes it.
+    (unsafe { File::from_raw_fd(controller) }, path)
+}
+
+/// Draws on and types into a terminal over the terminal driver.
+#[test]
+fn blit_and_belt() {
+    let (mut pty, path) = open_pty(80, 24);
+    let mut driver = common::spawn_driver_with_env(
+        "term",
+        Path::new("blit_and_belt.term_tests.log"),
+        &[("URBIT_IO_DRIVERS_TERM_TTY", &path)],
+    );
+
+    let mut input = driver.0.stdin.take().unwrap();
+    let mut output = driver.0.stdout.take().unwrap();
+
+    // The driver reports the size of the terminal when it starts.
+    let expected = Noun::from(Cell::from([
+        Atom::from("blew"),
+        Atom::from(0u64),
+        Atom::from(80u64),
+        Atom::from(24u64),
+    ]));
+    assert_eq!(common::read_response(&mut output), expected);
+
+    pty.write_all(b"hi").expect("type text");
+    let expected = Noun::from(Cell::from([
+        Atom::from("belt"),
+        Atom::from(0u64),
+        Atom::from("txt"),
+        Atom::from(u64::from('h')),
+        Atom::from(u64::from('i')),
+        Atom::null(),
+    ]));
+    assert_eq!(common::read_response(&mut output), expected);
+
+    pty.write_all(b"\x1b[A").expect("press arrow key");
+    let expected = Noun::from(Cell::from([
+        Atom::from("belt"),
+        Atom::from(0u64),
+        Atom::from("aro"),
+        Atom::from("u"),
+    ]));
+    assert_eq!(common::read_response(&mut output), expected);
+
+    let req = Noun::from(Cell::from([
+        Noun::from(Atom::from("blit")),
+        Noun::from(Atom::from(0u64)),
+        Noun::from(Atom::from("put")),
+        Noun::from(Atom::from(u64::from('o'))),
+        Noun::from(Atom::from(u64::from('k'))),
+        Noun::null(),
+    ]));
+    common::write_request(&mut input, req);
+    let mut buf = [0; 2];
+    pty.read_exact(&mut buf).expect("read drawn text");
+    assert_eq!(&buf, b"ok");
+}
+
+/// Attaches a session over the terminal driver's socket, and draws on and types into it.
+#[test]
+fn attach_session() {
+    let socket = env::temp_dir().join(format!("term_tests.{}.sock", std::process::id()));
+    let socket = socket.to_str().expect("socket path to str");
+    // The driver runs without a terminal of its own.
+    let mut driver = common::spawn_driver_with_env(
+        "term",
+        Path::new("attach_session.term_tests.log"),
+        &[
+            ("URBIT_IO_DRIVERS_TERM_TTY", "/nonexistent/tty"),
+            ("URBIT_IO_DRIVERS_TERM_SOCKET", socket),
+        ],
+    );
+
+    let mut input = driver.0.stdin.take().unwrap();
+    let mut output = driver.0.stdout.take().unwrap();
+
+    // Wait for the driver to listen on the socket.
+    let start = Instant::now();
+    let mut session = loop {
+        match UnixStream::connect(socket) {
+            Ok(session) => break session,
+            Err(err) if start.elapsed() > Duration::from_secs(5) => panic!("connect: {}", err),
+            Err(_err) => std::thread::sleep(Duration::from_millis(10)),
+        }
+    };
+    let expected = Noun::from(Cell::from([Atom::from("open"), Atom::from(1u64)]));
+    assert_eq!(common::read_response(&mut output), expected);
+
+    // A size message, and then a keys message.
+    session
+        .write_all(&[1, 4, 0, 100, 0, 30, 0, 0, 1, 0, b'x'])
+        .expect("send size and keys");
+    let expected = Noun::from(Cell::from([
+        Atom::from("blew"),
+        Atom::from(1u64),
+        Atom::from(100u64),
+        Atom::from(30u64),
+    ]));
+    assert_eq!(common::read_response(&mut output), expected);
+    let expected = Noun::from(Cell::from([
+        Atom::from("belt"),
+        Atom::from(1u64),
+        Atom::from("txt"),
+        Atom::from(u64::from('x')),
+        Atom::null(),
+    ]));
+    assert_eq!(common::read_response(&mut output), expected);
+
+    let req = Noun::from(Cell::from([
+        Noun::from(Atom::from("blit")),
+        Noun::from(Atom::from(1u64)),
+        Noun::from(Atom::from("bel")),
+        Noun::null(),
+    ]));
+    common::write_request(&mut input, req);
+    let mut buf = [0; 1];
+    session.read_exact(&mut buf).expect("read drawn bell");
+    assert_eq!(buf, [0x07]);
+
+    drop(session);
+    let expected = Noun::from(Cell::from([Atom::from("shut"), Atom::from(1u64)]));
+    assert_eq!(common::read_response(&mut output), expected);
+}